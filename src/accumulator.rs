@@ -0,0 +1,79 @@
+use crate::camera::CustomCamera;
+use crate::color::LinearColor;
+use crate::framebuffer::Framebuffer;
+
+// Running sum of every sample traced for each pixel while the view stays
+// still. Each new pass refines the average, so the interactive view
+// converges to an anti-aliased image on its own.
+pub struct Accumulator {
+    width: usize,
+    height: usize,
+    sum: Vec<LinearColor>,
+    samples: u32,
+    passes: u32,
+    view: Option<CustomCamera>,
+}
+
+impl Accumulator {
+    pub fn new(width: usize, height: usize) -> Self {
+        Accumulator {
+            width,
+            height,
+            sum: vec![LinearColor::BLACK; width * height],
+            samples: 0,
+            passes: 0,
+            view: None,
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.sum.iter_mut().for_each(|s| *s = LinearColor::BLACK);
+        self.samples = 0;
+        self.passes = 0;
+    }
+
+    // Start over whenever the camera moved (orbit, zoom, ...) or the target
+    // size changed since the last pass.
+    pub fn sync(&mut self, camera: &CustomCamera, width: usize, height: usize) {
+        if width != self.width || height != self.height {
+            *self = Accumulator::new(width, height);
+        }
+        if self.view.as_ref() != Some(camera) {
+            self.reset();
+            self.view = Some(camera.clone());
+        }
+    }
+
+    // Samples per pixel accumulated so far
+    pub fn samples(&self) -> u32 {
+        self.samples
+    }
+
+    // Number of passes added since the last reset, used to seed the next one
+    pub fn passes(&self) -> u32 {
+        self.passes
+    }
+
+    // Add one pass holding the sum of `samples_per_pixel` samples per pixel
+    pub fn add(&mut self, pass: &[LinearColor], samples_per_pixel: u32) {
+        debug_assert_eq!(pass.len(), self.sum.len());
+        for (acc, sample) in self.sum.iter_mut().zip(pass) {
+            *acc += *sample;
+        }
+        self.samples += samples_per_pixel;
+        self.passes += 1;
+    }
+
+    // Write the current average into the framebuffer, which tone maps it
+    pub fn resolve(&self, framebuffer: &mut Framebuffer) {
+        if self.samples == 0 {
+            return;
+        }
+        let inv = 1.0 / self.samples as f32;
+        for (i, acc) in self.sum.iter().enumerate() {
+            let x = (i % self.width) as u32;
+            let y = (i / self.width) as u32;
+            framebuffer.set_pixel_hdr(x, y, *acc * inv);
+        }
+    }
+}
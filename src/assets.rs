@@ -0,0 +1,137 @@
+use crate::material::Material;
+use crate::texture::{Filter, Texture, WrapMode};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// Position of a texture in the list a `TextureRegistry` builds. Only the
+// registry hands these out, so an object cannot point at a texture that was
+// never registered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TextureHandle(usize);
+
+impl TextureHandle {
+    pub fn index(self) -> usize {
+        self.0
+    }
+}
+
+// Every texture a scene uses, registered under a name. Objects keep the
+// handle that registering returned, so a name is looked up once while the
+// scene is built and a typo is an error there instead of a silently
+// untextured object.
+#[derive(Default)]
+pub struct TextureRegistry {
+    textures: Vec<Texture>,
+    names: HashMap<String, TextureHandle>,
+    // Decoded images by canonical path and sampling settings, so a file
+    // shared by several names or models is loaded only once
+    files: HashMap<(PathBuf, Filter, WrapMode), TextureHandle>,
+}
+
+impl TextureRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Register a texture under a new name and return its handle
+    pub fn add(&mut self, name: &str, texture: Texture) -> Result<TextureHandle, String> {
+        if self.names.contains_key(name) {
+            return Err(format!("texture '{}' is declared twice", name));
+        }
+        let id = self.push(texture);
+        self.names.insert(name.to_string(), id);
+        Ok(id)
+    }
+
+    // Give an already registered texture a (further) name
+    pub fn alias(&mut self, name: &str, id: TextureHandle) -> Result<(), String> {
+        if self.names.contains_key(name) {
+            return Err(format!("texture '{}' is declared twice", name));
+        }
+        self.names.insert(name.to_string(), id);
+        Ok(())
+    }
+
+    // Texture without a name, such as a model's diffuse map
+    pub fn push(&mut self, texture: Texture) -> TextureHandle {
+        self.textures.push(texture);
+        TextureHandle(self.textures.len() - 1)
+    }
+
+    // Handle to an image file sampled with `filter` and `wrap`, decoding it
+    // only the first time it is asked for
+    pub fn load_file(&mut self, path: &Path, filter: Filter, wrap: WrapMode) -> Result<TextureHandle, String> {
+        let canonical = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+        let key = (canonical, filter, wrap);
+        if let Some(&id) = self.files.get(&key) {
+            return Ok(id);
+        }
+        let texture = Texture::from_file(&path.to_string_lossy())?.with_filter(filter).with_wrap(wrap);
+        let id = self.push(texture);
+        self.files.insert(key, id);
+        Ok(id)
+    }
+
+    pub fn get(&self, name: &str) -> Option<TextureHandle> {
+        self.names.get(name).copied()
+    }
+
+    // Like `get`, with an error listing the declared names
+    pub fn resolve(&self, name: &str) -> Result<TextureHandle, String> {
+        self.get(name).ok_or_else(|| {
+            let mut known: Vec<&str> = self.names.keys().map(String::as_str).collect();
+            known.sort_unstable();
+            if known.is_empty() {
+                format!("unknown texture '{}' (the scene declares no textures)", name)
+            } else {
+                format!("unknown texture '{}' (declared: {})", name, known.join(", "))
+            }
+        })
+    }
+
+    pub fn into_textures(self) -> Vec<Texture> {
+        self.textures
+    }
+}
+
+// Materials declared by a scene, looked up by name before the built-in
+// `Material::preset`s. A declared name may not hide a preset.
+#[derive(Default)]
+pub struct MaterialLibrary {
+    materials: HashMap<String, Material>,
+}
+
+impl MaterialLibrary {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&mut self, name: &str, material: Material) -> Result<(), String> {
+        if Material::preset(name).is_some() {
+            return Err(format!("material '{}' shadows the built-in preset of the same name", name));
+        }
+        if self.materials.contains_key(name) {
+            return Err(format!("material '{}' is declared twice", name));
+        }
+        self.materials.insert(name.to_string(), material);
+        Ok(())
+    }
+
+    pub fn get(&self, name: &str) -> Option<Material> {
+        self.materials.get(name).copied().or_else(|| Material::preset(name))
+    }
+
+    // Like `get`, with an error listing what could have been meant
+    pub fn resolve(&self, name: &str) -> Result<Material, String> {
+        self.get(name).ok_or_else(|| {
+            let mut known: Vec<&str> = self.materials.keys().map(String::as_str).collect();
+            known.sort_unstable();
+            if known.is_empty() {
+                format!("unknown material '{}' (not a preset, and the scene declares no materials)", name)
+            } else {
+                format!("unknown material '{}' (not a preset or one of: {})", name, known.join(", "))
+            }
+        })
+    }
+}
@@ -0,0 +1,514 @@
+use raylib::prelude::*;
+use crate::ray_intersect::{Intersect, RayIntersect};
+
+// SAH tuning: bins per axis, max primitives per leaf and the relative cost of
+// visiting a node versus intersecting one primitive.
+const SAH_BINS: usize = 12;
+const MAX_LEAF_SIZE: usize = 4;
+const TRAVERSAL_COST: f32 = 1.0;
+const INTERSECTION_COST: f32 = 1.0;
+
+// Node boxes are padded slightly so that rounding in the box test never culls
+// a hit that the primitive's own intersection routine would report.
+const BOUNDS_PADDING: f32 = 1e-4;
+
+// Same threshold the cube slab test uses to treat a ray as parallel to an axis
+const PARALLEL_EPSILON: f32 = 1e-8;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Aabb {
+    pub min: Vector3,
+    pub max: Vector3,
+}
+
+impl Aabb {
+    pub fn new(min: Vector3, max: Vector3) -> Self {
+        Aabb { min, max }
+    }
+
+    pub fn empty() -> Self {
+        Aabb {
+            min: Vector3::new(f32::INFINITY, f32::INFINITY, f32::INFINITY),
+            max: Vector3::new(f32::NEG_INFINITY, f32::NEG_INFINITY, f32::NEG_INFINITY),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.min.x > self.max.x || self.min.y > self.max.y || self.min.z > self.max.z
+    }
+
+    pub fn union(&self, other: &Aabb) -> Aabb {
+        Aabb {
+            min: self.min.min(other.min),
+            max: self.max.max(other.max),
+        }
+    }
+
+    pub fn grow(&mut self, point: Vector3) {
+        self.min = self.min.min(point);
+        self.max = self.max.max(point);
+    }
+
+    pub fn padded(&self, amount: f32) -> Aabb {
+        let pad = Vector3::new(amount, amount, amount);
+        Aabb {
+            min: self.min - pad,
+            max: self.max + pad,
+        }
+    }
+
+    pub fn centroid(&self) -> Vector3 {
+        (self.min + self.max) * 0.5
+    }
+
+    pub fn surface_area(&self) -> f32 {
+        if self.is_empty() {
+            return 0.0;
+        }
+        let d = self.max - self.min;
+        2.0 * (d.x * d.y + d.y * d.z + d.z * d.x)
+    }
+
+    // Slab test returning the entry distance (clamped to 0 when the origin is
+    // inside the box), or None if the box is missed or lies beyond max_distance.
+    pub fn hit_distance(&self, ray_origin: &Vector3, ray_direction: &Vector3, max_distance: f32) -> Option<f32> {
+        let mut t_min = f32::NEG_INFINITY;
+        let mut t_max = f32::INFINITY;
+
+        let axes = [
+            (ray_origin.x, ray_direction.x, self.min.x, self.max.x),
+            (ray_origin.y, ray_direction.y, self.min.y, self.max.y),
+            (ray_origin.z, ray_direction.z, self.min.z, self.max.z),
+        ];
+
+        for (origin, direction, min, max) in axes {
+            if direction.abs() < PARALLEL_EPSILON {
+                if origin < min || origin > max {
+                    return None;
+                }
+                continue;
+            }
+
+            let inv_dir = 1.0 / direction;
+            let mut t1 = (min - origin) * inv_dir;
+            let mut t2 = (max - origin) * inv_dir;
+            if t1 > t2 {
+                std::mem::swap(&mut t1, &mut t2);
+            }
+            t_min = t_min.max(t1);
+            t_max = t_max.min(t2);
+            if t_min > t_max {
+                return None;
+            }
+        }
+
+        if t_max < 0.0 || t_min > max_distance {
+            return None;
+        }
+        Some(t_min.max(0.0))
+    }
+
+    fn axis(v: &Vector3, axis: usize) -> f32 {
+        match axis {
+            0 => v.x,
+            1 => v.y,
+            _ => v.z,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct BvhNode {
+    bounds: Aabb,
+    // Leaf: first entry in `indices`. Interior: index of the left child; the
+    // right child always follows it.
+    first: usize,
+    // Number of primitives for a leaf, 0 for interior nodes
+    count: usize,
+    // Smallest object index stored below this node, used by the ordered
+    // any-hit query to skip subtrees that cannot beat the current occluder.
+    min_index: usize,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Bin {
+    bounds: Aabb,
+    count: usize,
+}
+
+// Bounding volume hierarchy over a list of primitives (scene objects, mesh
+// triangles), built with a binned surface area heuristic from their bounds.
+// It stores indices only, so queries take the same slice the hierarchy was
+// built from, or a closure that intersects primitive `i`.
+#[derive(Debug, Clone)]
+pub struct Bvh {
+    nodes: Vec<BvhNode>,
+    indices: Vec<usize>,
+    object_count: usize,
+}
+
+impl Bvh {
+    pub fn build(primitive_bounds: &[Aabb]) -> Self {
+        let count = primitive_bounds.len();
+        let mut bvh = Bvh {
+            nodes: Vec::new(),
+            indices: (0..count).collect(),
+            object_count: count,
+        };
+
+        if count == 0 {
+            return bvh;
+        }
+
+        let bounds: Vec<Aabb> = primitive_bounds.iter().map(|b| b.padded(BOUNDS_PADDING)).collect();
+        let centroids: Vec<Vector3> = bounds.iter().map(|b| b.centroid()).collect();
+
+        bvh.nodes.reserve(count * 2);
+        bvh.nodes.push(BvhNode { bounds: Aabb::empty(), first: 0, count, min_index: 0 });
+        bvh.subdivide(0, &bounds, &centroids);
+        bvh
+    }
+
+    fn subdivide(&mut self, node_index: usize, bounds: &[Aabb], centroids: &[Vector3]) {
+        let first = self.nodes[node_index].first;
+        let count = self.nodes[node_index].count;
+        let items = &self.indices[first..first + count];
+
+        let mut node_bounds = Aabb::empty();
+        let mut centroid_bounds = Aabb::empty();
+        for &i in items {
+            node_bounds = node_bounds.union(&bounds[i]);
+            centroid_bounds.grow(centroids[i]);
+        }
+        self.nodes[node_index].bounds = node_bounds;
+        self.nodes[node_index].min_index = items.iter().copied().min().unwrap_or(usize::MAX);
+
+        if count <= MAX_LEAF_SIZE {
+            return;
+        }
+
+        let Some((axis, split_bin)) = Self::find_split(items, bounds, centroids, &centroid_bounds, &node_bounds) else {
+            return;
+        };
+
+        // Partition indices so everything left of the split plane comes first
+        let axis_min = Aabb::axis(&centroid_bounds.min, axis);
+        let scale = SAH_BINS as f32 / (Aabb::axis(&centroid_bounds.max, axis) - axis_min);
+        let bin_of = |i: usize| -> usize {
+            (((Aabb::axis(&centroids[i], axis) - axis_min) * scale) as usize).min(SAH_BINS - 1)
+        };
+
+        let slice = &mut self.indices[first..first + count];
+        let mut left_count = 0;
+        for j in 0..slice.len() {
+            if bin_of(slice[j]) < split_bin {
+                slice.swap(j, left_count);
+                left_count += 1;
+            }
+        }
+
+        if left_count == 0 || left_count == count {
+            return;
+        }
+
+        let left = self.nodes.len();
+        self.nodes.push(BvhNode { bounds: Aabb::empty(), first, count: left_count, min_index: 0 });
+        self.nodes.push(BvhNode { bounds: Aabb::empty(), first: first + left_count, count: count - left_count, min_index: 0 });
+
+        self.nodes[node_index].first = left;
+        self.nodes[node_index].count = 0;
+
+        self.subdivide(left, bounds, centroids);
+        self.subdivide(left + 1, bounds, centroids);
+    }
+
+    // Evaluate the SAH at every bin boundary on all three axes and return the
+    // cheapest split, or None when keeping the node as a leaf is cheaper.
+    fn find_split(
+        items: &[usize],
+        bounds: &[Aabb],
+        centroids: &[Vector3],
+        centroid_bounds: &Aabb,
+        node_bounds: &Aabb,
+    ) -> Option<(usize, usize)> {
+        let mut best: Option<(usize, usize)> = None;
+        let mut best_cost = INTERSECTION_COST * items.len() as f32;
+        let parent_area = node_bounds.surface_area().max(f32::EPSILON);
+
+        for axis in 0..3 {
+            let axis_min = Aabb::axis(&centroid_bounds.min, axis);
+            let extent = Aabb::axis(&centroid_bounds.max, axis) - axis_min;
+            if extent <= f32::EPSILON {
+                continue;
+            }
+
+            let mut bins = [Bin { bounds: Aabb::empty(), count: 0 }; SAH_BINS];
+            let scale = SAH_BINS as f32 / extent;
+            for &i in items {
+                let b = (((Aabb::axis(&centroids[i], axis) - axis_min) * scale) as usize).min(SAH_BINS - 1);
+                bins[b].count += 1;
+                bins[b].bounds = bins[b].bounds.union(&bounds[i]);
+            }
+
+            // Sweep from the right to get the area/count of every right side
+            let mut right_area = [0.0; SAH_BINS];
+            let mut right_count = [0usize; SAH_BINS];
+            let mut acc = Aabb::empty();
+            let mut n = 0;
+            for b in (1..SAH_BINS).rev() {
+                acc = acc.union(&bins[b].bounds);
+                n += bins[b].count;
+                right_area[b] = acc.surface_area();
+                right_count[b] = n;
+            }
+
+            let mut acc = Aabb::empty();
+            let mut n = 0;
+            for split in 1..SAH_BINS {
+                acc = acc.union(&bins[split - 1].bounds);
+                n += bins[split - 1].count;
+                if n == 0 || right_count[split] == 0 {
+                    continue;
+                }
+                let cost = TRAVERSAL_COST
+                    + INTERSECTION_COST
+                        * (acc.surface_area() * n as f32 + right_area[split] * right_count[split] as f32)
+                        / parent_area;
+                if cost < best_cost {
+                    best_cost = cost;
+                    best = Some((axis, split));
+                }
+            }
+        }
+
+        best
+    }
+
+    // Nearest-hit query. Ties on distance resolve to the lowest object index,
+    // which is exactly what a front-to-back linear scan with `<` would return.
+    pub fn closest_hit<T: RayIntersect>(&self, objects: &[T], ray_origin: &Vector3, ray_direction: &Vector3) -> Option<(Intersect, usize)> {
+        debug_assert_eq!(objects.len(), self.object_count, "BVH queried with a different object list");
+        self.closest_hit_with(ray_origin, ray_direction, |i| {
+            let hit = objects[i].ray_intersect(ray_origin, ray_direction);
+            hit.is_intersecting.then_some((hit.distance, hit))
+        })
+    }
+
+    // Nearest-hit traversal with a caller supplied primitive test, which
+    // returns the hit distance plus whatever the caller needs from the hit.
+    pub fn closest_hit_with<H>(
+        &self,
+        ray_origin: &Vector3,
+        ray_direction: &Vector3,
+        mut intersect: impl FnMut(usize) -> Option<(f32, H)>,
+    ) -> Option<(H, usize)> {
+        let root_distance = self.nodes.first()?.bounds.hit_distance(ray_origin, ray_direction, f32::INFINITY)?;
+
+        let mut best: Option<(H, usize)> = None;
+        let mut best_distance = f32::INFINITY;
+        let mut stack: Vec<(usize, f32)> = Vec::with_capacity(64);
+        stack.push((0, root_distance));
+
+        while let Some((node_index, entry)) = stack.pop() {
+            if entry > best_distance {
+                continue;
+            }
+            let node = &self.nodes[node_index];
+
+            if node.count > 0 {
+                for &i in &self.indices[node.first..node.first + node.count] {
+                    let Some((distance, hit)) = intersect(i) else {
+                        continue;
+                    };
+                    let better = match &best {
+                        None => true,
+                        Some((_, best_index)) => {
+                            distance < best_distance || (distance == best_distance && i < *best_index)
+                        }
+                    };
+                    if better {
+                        best_distance = distance;
+                        best = Some((hit, i));
+                    }
+                }
+                continue;
+            }
+
+            // Push the far child first so the near one is visited next
+            let left = self.nodes[node.first].bounds.hit_distance(ray_origin, ray_direction, best_distance);
+            let right = self.nodes[node.first + 1].bounds.hit_distance(ray_origin, ray_direction, best_distance);
+            match (left, right) {
+                (Some(l), Some(r)) => {
+                    if l <= r {
+                        stack.push((node.first + 1, r));
+                        stack.push((node.first, l));
+                    } else {
+                        stack.push((node.first, l));
+                        stack.push((node.first + 1, r));
+                    }
+                }
+                (Some(l), None) => stack.push((node.first, l)),
+                (None, Some(r)) => stack.push((node.first + 1, r)),
+                (None, None) => {}
+            }
+        }
+
+        best
+    }
+
+    // Any-hit query for shadow rays: returns an occluder closer than
+    // max_distance. When several objects occlude, the one with the lowest
+    // index wins so results match a linear scan that stops at the first hit.
+    pub fn first_occluder<T: RayIntersect>(
+        &self,
+        objects: &[T],
+        ray_origin: &Vector3,
+        ray_direction: &Vector3,
+        max_distance: f32,
+    ) -> Option<(Intersect, usize)> {
+        debug_assert_eq!(objects.len(), self.object_count, "BVH queried with a different object list");
+        self.nodes.first()?.bounds.hit_distance(ray_origin, ray_direction, max_distance)?;
+
+        let mut best: Option<(Intersect, usize)> = None;
+        let mut best_index = usize::MAX;
+        let mut stack: Vec<usize> = Vec::with_capacity(64);
+        stack.push(0);
+
+        while let Some(node_index) = stack.pop() {
+            let node = &self.nodes[node_index];
+            if node.min_index >= best_index {
+                continue;
+            }
+
+            if node.count > 0 {
+                for &i in &self.indices[node.first..node.first + node.count] {
+                    if i >= best_index {
+                        continue;
+                    }
+                    let hit = objects[i].ray_intersect(ray_origin, ray_direction);
+                    if hit.is_intersecting && hit.distance < max_distance {
+                        best_index = i;
+                        best = Some((hit, i));
+                    }
+                }
+                continue;
+            }
+
+            // Visit the subtree holding lower indices first so later subtrees
+            // can be rejected by index alone
+            let (left, right) = (node.first, node.first + 1);
+            let (first, second) = if self.nodes[left].min_index <= self.nodes[right].min_index {
+                (left, right)
+            } else {
+                (right, left)
+            };
+            for child in [second, first] {
+                if self.nodes[child].bounds.hit_distance(ray_origin, ray_direction, max_distance).is_some() {
+                    stack.push(child);
+                }
+            }
+        }
+
+        best
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cube::Cube;
+    use crate::material::Material;
+    use crate::renderer::Object;
+    use crate::sampling::Rng;
+    use crate::sphere::Sphere;
+
+    // Random boxes and spheres over a floor of touching cubes, with some
+    // objects repeated so that equal distances actually occur
+    fn random_objects(rng: &mut Rng) -> Vec<Object> {
+        let mut objects = Vec::new();
+        for x in -4..=4 {
+            for z in -4..=4 {
+                let center = Vector3::new(x as f32, -1.0, z as f32);
+                objects.push(Object::Cube(Cube::new(center, 1.0, Material::concrete_base())));
+            }
+        }
+        for _ in 0..200 {
+            let center = Vector3::new(
+                rng.next_f32() * 8.0 - 4.0,
+                rng.next_f32() * 3.0 - 1.0,
+                rng.next_f32() * 8.0 - 4.0,
+            );
+            let object = if rng.next_f32() < 0.6 {
+                Object::Cube(Cube::new(center, rng.next_f32() * 0.8 + 0.05, Material::rubber()))
+            } else {
+                Object::Sphere(Sphere::new(center, rng.next_f32() * 0.5 + 0.05, Material::glass()))
+            };
+            if rng.next_f32() < 0.1 {
+                objects.push(object.clone());
+            }
+            objects.push(object);
+        }
+        objects
+    }
+
+    fn random_ray(rng: &mut Rng, k: usize) -> (Vector3, Vector3) {
+        let origin = if k.is_multiple_of(3) {
+            Vector3::new(rng.next_f32() * 8.0 - 4.0, rng.next_f32() * 3.0 - 1.0, rng.next_f32() * 8.0 - 4.0)
+        } else {
+            Vector3::new(rng.next_f32() * 30.0 - 15.0, rng.next_f32() * 10.0, rng.next_f32() * 30.0 - 15.0)
+        };
+        let mut direction = Vector3::new(rng.next_f32() * 2.0 - 1.0, rng.next_f32() * 2.0 - 1.0, rng.next_f32() * 2.0 - 1.0);
+        // Axis-parallel rays exercise the slab test's special case
+        if k.is_multiple_of(7) {
+            direction.y = 0.0;
+        }
+        if k.is_multiple_of(11) {
+            direction.x = 0.0;
+        }
+        (origin, direction.normalized())
+    }
+
+    #[test]
+    fn queries_match_a_linear_scan() {
+        let mut rng = Rng::new(7, 1);
+        let objects = random_objects(&mut rng);
+        let bounds: Vec<Aabb> = objects.iter().map(Object::bounds).collect();
+        let bvh = Bvh::build(&bounds);
+
+        for k in 0..20_000 {
+            let (origin, direction) = random_ray(&mut rng, k);
+
+            let mut closest: Option<(f32, usize)> = None;
+            for (i, object) in objects.iter().enumerate() {
+                let hit = object.ray_intersect(&origin, &direction);
+                if hit.is_intersecting && closest.is_none_or(|(distance, _)| hit.distance < distance) {
+                    closest = Some((hit.distance, i));
+                }
+            }
+            let found = bvh
+                .closest_hit(&objects, &origin, &direction)
+                .map(|(hit, i)| (hit.distance, i));
+            assert_eq!(found, closest, "closest hit of ray {k}");
+
+            let max_distance = rng.next_f32() * 10.0;
+            let occluder = objects.iter().position(|object| {
+                let hit = object.ray_intersect(&origin, &direction);
+                hit.is_intersecting && hit.distance < max_distance
+            });
+            let found = bvh
+                .first_occluder(&objects, &origin, &direction, max_distance)
+                .map(|(_, i)| i);
+            assert_eq!(found, occluder, "occluder of ray {k}");
+        }
+    }
+
+    #[test]
+    fn empty_scene_has_no_hits() {
+        let objects: Vec<Object> = Vec::new();
+        let bvh = Bvh::build(&[]);
+        let origin = Vector3::new(0.0, 0.0, 0.0);
+        let direction = Vector3::new(0.0, 0.0, -1.0);
+        assert!(bvh.closest_hit(&objects, &origin, &direction).is_none());
+        assert!(bvh.first_occluder(&objects, &origin, &direction, 1.0).is_none());
+    }
+}
@@ -1,10 +1,108 @@
 use raylib::prelude::*;
+use crate::sampling::{concentric_disk, regular_polygon};
 use std::f32::consts::PI;
+use std::fmt;
+use std::str::FromStr;
 
+// Vertical field of view of a new camera, the tracer's original 60°
+pub const DEFAULT_FOV: f32 = PI / 3.0;
+
+// How the camera maps the image onto rays
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Projection {
+    // Pinhole (or thin lens) seeing `fov` from the top to the bottom edge
+    Perspective,
+    // Parallel rays, for true isometric views. The view is as tall as the
+    // perspective one at the looked-at point, so switching keeps the
+    // framing and moving the eye closer still zooms in.
+    Orthographic,
+    // 360° equirectangular panorama: longitude across the width, latitude
+    // down the height, the view direction in the middle. Ignores `fov`.
+    Panorama,
+    // Equidistant fisheye: the angle from the view direction grows with the
+    // distance from the image centre, reaching `fov` / 2 at the top and
+    // bottom edges. Fields wider than 360° leave the corners black.
+    Fisheye,
+}
+
+impl Projection {
+    pub const ALL: [Projection; 4] = [
+        Projection::Perspective,
+        Projection::Orthographic,
+        Projection::Panorama,
+        Projection::Fisheye,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Projection::Perspective => "perspective",
+            Projection::Orthographic => "orthographic",
+            Projection::Panorama => "panorama",
+            Projection::Fisheye => "fisheye",
+        }
+    }
+
+    // Next projection in `ALL`, used to switch from the keyboard
+    pub fn next(&self) -> Projection {
+        let index = Projection::ALL.iter().position(|p| p == self).unwrap_or(0);
+        Projection::ALL[(index + 1) % Projection::ALL.len()]
+    }
+
+    // Widest field of view that still makes sense (radians)
+    pub fn max_fov(&self) -> f32 {
+        match self {
+            Projection::Fisheye => 2.0 * PI,
+            _ => 170f32.to_radians(),
+        }
+    }
+}
+
+impl fmt::Display for Projection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for Projection {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Projection::ALL
+            .into_iter()
+            .find(|p| p.name() == s)
+            .ok_or_else(|| format!("unknown projection '{}' (available: perspective, orthographic, panorama, fisheye)", s))
+    }
+}
+
+// Where a thin-lens camera keeps its plane of sharp focus
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Focus {
+    // On the point the camera looks at, following it as the camera moves
+    Center,
+    // Fixed distance from the eye, along the view direction
+    Distance(f32),
+    // On whatever object is under the middle of the screen, measured again
+    // every frame (see `renderer::focus_distance`)
+    Auto,
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct CustomCamera {
     pub eye: Vector3,    // Camera position in world space
     pub center: Vector3, // Point the camera is looking at
     pub up: Vector3,     // Up vector
+    // Vertical field of view in radians, and how the image maps onto rays
+    pub fov: f32,
+    pub projection: Projection,
+    // Thin lens: radius of the aperture in world units (0 is a pinhole with
+    // everything sharp) and the distance it focuses at
+    pub aperture: f32,
+    pub focus: Focus,
+    // Polygonal aperture with this many blades, turned by `blade_rotation`
+    // radians, for polygonal bokeh; fewer than 3 is a round aperture.
+    // `aperture` is the radius of the polygon's corners.
+    pub blades: u32,
+    pub blade_rotation: f32,
 }
 
 impl CustomCamera {
@@ -13,7 +111,134 @@ impl CustomCamera {
             eye,
             center,
             up,
+            fov: DEFAULT_FOV,
+            projection: Projection::Perspective,
+            aperture: 0.0,
+            focus: Focus::Center,
+            blades: 0,
+            blade_rotation: 0.0,
+        }
+    }
+
+    pub fn with_fov(mut self, fov: f32) -> Self {
+        self.fov = fov;
+        self
+    }
+
+    pub fn with_projection(mut self, projection: Projection) -> Self {
+        self.projection = projection;
+        self
+    }
+
+    // Widen or narrow the field of view, within what the projection allows
+    pub fn change_fov(&mut self, delta: f32) {
+        self.fov = (self.fov + delta).clamp(1f32.to_radians(), self.projection.max_fov());
+    }
+
+    pub fn with_aperture(mut self, radius: f32) -> Self {
+        self.aperture = radius;
+        self
+    }
+
+    pub fn with_focus(mut self, focus: Focus) -> Self {
+        self.focus = focus;
+        self
+    }
+
+    pub fn with_blades(mut self, blades: u32, rotation: f32) -> Self {
+        self.blades = blades;
+        self.blade_rotation = rotation;
+        self
+    }
+
+    // Distance to the plane of focus for a fixed focus or the looked-at
+    // point; None when it must be measured in the scene
+    pub fn focal_distance(&self) -> Option<f32> {
+        match self.focus {
+            Focus::Center => Some((self.center - self.eye).length()),
+            Focus::Distance(distance) => Some(distance),
+            Focus::Auto => None,
+        }
+    }
+
+    // Origin and direction of the primary ray through the screen point
+    // `screen_x`, `screen_y` in [-1, 1] (y up) of an image `aspect_ratio`
+    // times wider than tall. `lens` picks a point on the aperture with two
+    // numbers in [0, 1), None being its centre; only perspective views have
+    // depth of field. None where the projection covers no direction.
+    pub fn primary_ray(
+        &self,
+        screen_x: f32,
+        screen_y: f32,
+        aspect_ratio: f32,
+        focal_distance: f32,
+        lens: Option<(f32, f32)>,
+    ) -> Option<(Vector3, Vector3)> {
+        match self.projection {
+            Projection::Perspective => {
+                let scale = (self.fov * 0.5).tan();
+                let mut direction = Vector3::new(screen_x * aspect_ratio * scale, screen_y * scale, -1.0);
+                direction.normalize();
+                Some(match lens {
+                    Some((u, v)) => self.lens_ray(&direction, focal_distance, u, v),
+                    None => (self.eye, self.basis_change(&direction)),
+                })
+            }
+            Projection::Orthographic => {
+                let half_height = (self.center - self.eye).length() * (self.fov * 0.5).tan();
+                let (right, up, forward) = self.basis();
+                let offset = right * (screen_x * aspect_ratio * half_height) + up * (screen_y * half_height);
+                Some((self.eye + offset, forward))
+            }
+            Projection::Panorama => {
+                let (sin_lon, cos_lon) = (screen_x * PI).sin_cos();
+                let (sin_lat, cos_lat) = (screen_y * PI * 0.5).sin_cos();
+                let direction = Vector3::new(sin_lon * cos_lat, sin_lat, -cos_lon * cos_lat);
+                Some((self.eye, self.basis_change(&direction)))
+            }
+            Projection::Fisheye => {
+                let (x, y) = (screen_x * aspect_ratio, screen_y);
+                let radius = (x * x + y * y).sqrt();
+                let angle = radius * self.fov * 0.5;
+                if angle > PI {
+                    return None;
+                }
+                if radius == 0.0 {
+                    return Some((self.eye, self.basis_change(&Vector3::new(0.0, 0.0, -1.0))));
+                }
+                let (sin, cos) = angle.sin_cos();
+                let direction = Vector3::new(sin * x / radius, sin * y / radius, -cos);
+                Some((self.eye, self.basis_change(&direction)))
+            }
+        }
+    }
+
+    // Perspective ray for the camera-space `direction` through the lens
+    // point picked by `u`, `v`. Every ray through the lens for the same
+    // pixel meets the others on the plane of focus `focal_distance` away, so
+    // only that plane is sharp.
+    fn lens_ray(&self, direction: &Vector3, focal_distance: f32, u: f32, v: f32) -> (Vector3, Vector3) {
+        let pinhole = self.basis_change(direction);
+        if self.aperture <= 0.0 {
+            return (self.eye, pinhole);
         }
+
+        let (x, y) = if self.blades >= 3 {
+            regular_polygon(self.blades, self.blade_rotation, u, v)
+        } else {
+            concentric_disk(u, v)
+        };
+        let (right, up, forward) = self.basis();
+        let focus_point = self.eye + pinhole * (focal_distance / pinhole.dot(forward).max(1e-4));
+        let origin = self.eye + (right * x + up * y) * self.aperture;
+        (origin, (focus_point - origin).normalized())
+    }
+
+    // Right, up and forward unit vectors of the view
+    fn basis(&self) -> (Vector3, Vector3, Vector3) {
+        let forward = (self.center - self.eye).normalized();
+        let right = forward.cross(self.up).normalized();
+        (right, right.cross(forward), forward)
     }
 
     pub fn basis_change(&self, vector: &Vector3) -> Vector3 {
@@ -33,6 +258,34 @@ impl CustomCamera {
         rotated
     }
 
+    // First-person mouse-look: turn the view about the eye, `delta_yaw` to
+    // the right around the world vertical and `delta_pitch` upwards. The
+    // looked-at point keeps its distance, so orbiting afterwards circles a
+    // spot straight ahead.
+    pub fn look(&mut self, delta_yaw: f32, delta_pitch: f32) {
+        let view = self.center - self.eye;
+        let distance = view.length();
+        let direction = view.normalized();
+
+        // Yaw 0 looks along -Z and grows towards +X; pitch is the elevation
+        let yaw = direction.x.atan2(-direction.z) + delta_yaw;
+        // Same limit as `orbit`, away from the poles where yaw is undefined
+        let pitch = (direction.y.clamp(-1.0, 1.0).asin() + delta_pitch).clamp(-PI / 2.0 + 0.1, PI / 2.0 - 0.1);
+
+        let turned = Vector3::new(pitch.cos() * yaw.sin(), pitch.sin(), -pitch.cos() * yaw.cos());
+        self.center = self.eye + turned * distance;
+    }
+
+    // Free-fly movement: slide the eye and the looked-at point together,
+    // `forward` along the view direction, `right` sideways and `up` along
+    // the world vertical
+    pub fn fly(&mut self, forward: f32, right: f32, up: f32) {
+        let (right_axis, _, forward_axis) = self.basis();
+        let offset = forward_axis * forward + right_axis * right + Vector3::new(0.0, up, 0.0);
+        self.eye += offset;
+        self.center += offset;
+    }
+
     pub fn orbit(&mut self, delta_yaw: f32, delta_pitch: f32) {
         // Calculate the vector from the center to the eye (radius vector) and measure the distance
         let radius_vector = self.eye - self.center;
@@ -1,4 +1,5 @@
 use std::fmt;
+use std::sync::OnceLock;
 
 #[derive(Debug, Clone, Copy)]
 pub struct Color {
@@ -37,10 +38,75 @@ impl Color {
     pub fn to_raylib(&self) -> raylib::color::Color {
         raylib::color::Color::new(self.r, self.g, self.b, 255)
     }
+
+    // Decode the sRGB-encoded channels into linear light for shading
+    pub fn to_linear(self) -> LinearColor {
+        let table = srgb_to_linear_table();
+        LinearColor::new(
+            table[self.r as usize],
+            table[self.g as usize],
+            table[self.b as usize],
+        )
+    }
+}
+
+// Linear-light RGB used while shading. Channels are unbounded: lights add up
+// past 1.0 freely and only the tone mapper brings them back into range when
+// the framebuffer is written.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct LinearColor {
+    pub r: f32,
+    pub g: f32,
+    pub b: f32,
+}
+
+impl LinearColor {
+    pub const BLACK: LinearColor = LinearColor { r: 0.0, g: 0.0, b: 0.0 };
+
+    pub fn new(r: f32, g: f32, b: f32) -> Self {
+        LinearColor { r, g, b }
+    }
+
+    // Relative luminance (Rec. 709 primaries)
+    pub fn luminance(&self) -> f32 {
+        0.2126 * self.r + 0.7152 * self.g + 0.0722 * self.b
+    }
+
+    pub fn map(self, f: impl Fn(f32) -> f32) -> LinearColor {
+        LinearColor::new(f(self.r), f(self.g), f(self.b))
+    }
+
+    // Encode a displayable [0, 1] color with the sRGB transfer curve
+    pub fn to_srgb(self) -> Color {
+        let encode = |c: f32| (linear_to_srgb(c.clamp(0.0, 1.0)) * 255.0).round() as u8;
+        Color::new(encode(self.r), encode(self.g), encode(self.b))
+    }
+}
+
+pub fn srgb_to_linear(c: f32) -> f32 {
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+pub fn linear_to_srgb(c: f32) -> f32 {
+    if c <= 0.0031308 {
+        c * 12.92
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+// Every textured hit decodes a texel, so the 256 possible values are cached
+fn srgb_to_linear_table() -> &'static [f32; 256] {
+    static TABLE: OnceLock<[f32; 256]> = OnceLock::new();
+    TABLE.get_or_init(|| std::array::from_fn(|i| srgb_to_linear(i as f32 / 255.0)))
 }
 
 // Implement addition for Color
-use std::ops::Add;
+use std::ops::{Add, AddAssign};
 
 impl Add for Color {
     type Output = Color;
@@ -69,6 +135,37 @@ impl Mul<f32> for Color {
     }
 }
 
+impl Add for LinearColor {
+    type Output = LinearColor;
+
+    fn add(self, other: LinearColor) -> LinearColor {
+        LinearColor::new(self.r + other.r, self.g + other.g, self.b + other.b)
+    }
+}
+
+impl AddAssign for LinearColor {
+    fn add_assign(&mut self, other: LinearColor) {
+        *self = *self + other;
+    }
+}
+
+impl Mul<f32> for LinearColor {
+    type Output = LinearColor;
+
+    fn mul(self, scalar: f32) -> LinearColor {
+        LinearColor::new(self.r * scalar, self.g * scalar, self.b * scalar)
+    }
+}
+
+// Component-wise product, e.g. light color filtered by surface color
+impl Mul for LinearColor {
+    type Output = LinearColor;
+
+    fn mul(self, other: LinearColor) -> LinearColor {
+        LinearColor::new(self.r * other.r, self.g * other.g, self.b * other.b)
+    }
+}
+
 // Implement display formatting for Color
 impl fmt::Display for Color {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
@@ -1,13 +1,74 @@
 use raylib::prelude::*;
 use crate::ray_intersect::{Intersect, RayIntersect};
 use crate::material::Material;
+use crate::bvh::Aabb;
+use crate::emitter::SurfaceSample;
+use crate::texture::Texture;
+use crate::assets::TextureHandle;
 
-#[derive(Debug, Clone, Copy)]
+// The six faces of a cube, in the order `get_uv` tells them apart
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Face {
+    Right,  // +X
+    Left,   // -X
+    Top,    // +Y
+    Bottom, // -Y
+    Front,  // +Z
+    Back,   // -Z
+}
+
+impl Face {
+    pub const ALL: [Face; 6] = [Face::Right, Face::Left, Face::Top, Face::Bottom, Face::Front, Face::Back];
+
+    // Face whose outward normal is closest to `normal`
+    pub fn from_normal(normal: Vector3) -> Face {
+        if normal.x > 0.5 {
+            Face::Right
+        } else if normal.x < -0.5 {
+            Face::Left
+        } else if normal.y > 0.5 {
+            Face::Top
+        } else if normal.y < -0.5 {
+            Face::Bottom
+        } else if normal.z > 0.5 {
+            Face::Front
+        } else {
+            Face::Back
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Face> {
+        match name {
+            "right" => Some(Face::Right),
+            "left" => Some(Face::Left),
+            "top" => Some(Face::Top),
+            "bottom" => Some(Face::Bottom),
+            "front" => Some(Face::Front),
+            "back" => Some(Face::Back),
+            _ => None,
+        }
+    }
+
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct Cube {
     pub min: Vector3,    // Minimum corner of the cube
     pub max: Vector3,    // Maximum corner of the cube
     pub material: Material,
-    pub texture_id: Option<usize>, // Index into texture array
+    pub texture_id: Option<TextureHandle>,
+    pub uv_scale: (f32, f32),      // Texture repeats per face along u and v
+    // Relief for lighting: a tangent-space normal map, and/or a height map
+    // with the depth in world units its full range stands for
+    pub normal_map: Option<TextureHandle>,
+    pub bump_map: Option<(TextureHandle, f32)>,
+    // Per-face overrides of `texture_id` and `material`, indexed by `Face`.
+    // Materials are boxed so plain cubes stay small.
+    pub face_textures: [Option<TextureHandle>; 6],
+    pub face_materials: Option<Box<[Option<Material>; 6]>>,
 }
 
 impl Cube {
@@ -26,6 +87,11 @@ impl Cube {
             ),
             material,
             texture_id: None,
+            uv_scale: (1.0, 1.0),
+            normal_map: None,
+            bump_map: None,
+            face_textures: [None; 6],
+            face_materials: None,
         }
     }
 
@@ -48,14 +114,119 @@ impl Cube {
             ),
             material,
             texture_id: None,
+            uv_scale: (1.0, 1.0),
+            normal_map: None,
+            bump_map: None,
+            face_textures: [None; 6],
+            face_materials: None,
         }
     }
 
-    pub fn with_texture(mut self, texture_id: usize) -> Self {
+    pub fn with_texture(mut self, texture_id: TextureHandle) -> Self {
         self.texture_id = Some(texture_id);
         self
     }
 
+    // Texture for one face only, e.g. grass on top of a dirt block
+    pub fn with_face_texture(mut self, face: Face, texture_id: TextureHandle) -> Self {
+        self.face_textures[face.index()] = Some(texture_id);
+        self
+    }
+
+    pub fn with_face_material(mut self, face: Face, material: Material) -> Self {
+        self.face_materials.get_or_insert_with(|| Box::new([None; 6]))[face.index()] = Some(material);
+        self
+    }
+
+    // Texture and material of the face with this normal
+    pub fn texture_for(&self, normal: Vector3) -> Option<TextureHandle> {
+        self.face_textures[Face::from_normal(normal).index()].or(self.texture_id)
+    }
+
+    pub fn material_for(&self, normal: Vector3) -> Material {
+        self.face_materials
+            .as_ref()
+            .and_then(|materials| materials[Face::from_normal(normal).index()])
+            .unwrap_or(self.material)
+    }
+
+    pub fn is_emissive(&self) -> bool {
+        Face::ALL.iter().any(|&face| {
+            let material = self.face_materials.as_ref().and_then(|materials| materials[face.index()]);
+            material.unwrap_or(self.material).is_emissive()
+        })
+    }
+
+    // Tile the texture `u` x `v` times across each face (needs a repeating
+    // or mirrored wrap mode on the texture)
+    pub fn with_uv_scale(mut self, u: f32, v: f32) -> Self {
+        self.uv_scale = (u, v);
+        self
+    }
+
+    // Normal map in tangent space (OpenGL convention: green points up the
+    // image), tiled like the colour texture
+    pub fn with_normal_map(mut self, texture_id: TextureHandle) -> Self {
+        self.normal_map = Some(texture_id);
+        self
+    }
+
+    // Height map read from the texture's brightness; white stands `depth`
+    // world units above black
+    pub fn with_bump_map(mut self, texture_id: TextureHandle, depth: f32) -> Self {
+        self.bump_map = Some((texture_id, depth));
+        self
+    }
+
+    pub fn bounds(&self) -> Aabb {
+        Aabb::new(self.min, self.max)
+    }
+
+    pub fn area(&self) -> f32 {
+        let size = self.max - self.min;
+        2.0 * (size.x * size.y + size.y * size.z + size.z * size.x)
+    }
+
+    // Uniformly distributed point on the surface: `pick` chooses a face in
+    // proportion to its area, `s` and `t` the position on it
+    pub fn sample_surface(&self, s: f32, t: f32, pick: f32) -> SurfaceSample {
+        let size = self.max - self.min;
+        let faces = [size.y * size.z, size.z * size.x, size.x * size.y];
+        let mut target = pick * (faces[0] + faces[1] + faces[2]);
+        let mut axis = 0;
+        while axis < 2 && target >= faces[axis] {
+            target -= faces[axis];
+            axis += 1;
+        }
+        // Which of the two opposite faces, reusing what is left of `pick`
+        let positive = target >= faces[axis] * 0.5;
+
+        let at = |min: f32, max: f32, f: f32| min + (max - min) * f;
+        let side = |min: f32, max: f32| if positive { max } else { min };
+        let sign = if positive { 1.0 } else { -1.0 };
+        let (point, normal) = match axis {
+            0 => (
+                Vector3::new(side(self.min.x, self.max.x), at(self.min.y, self.max.y, s), at(self.min.z, self.max.z, t)),
+                Vector3::new(sign, 0.0, 0.0),
+            ),
+            1 => (
+                Vector3::new(at(self.min.x, self.max.x, s), side(self.min.y, self.max.y), at(self.min.z, self.max.z, t)),
+                Vector3::new(0.0, sign, 0.0),
+            ),
+            _ => (
+                Vector3::new(at(self.min.x, self.max.x, s), at(self.min.y, self.max.y, t), side(self.min.z, self.max.z)),
+                Vector3::new(0.0, 0.0, sign),
+            ),
+        };
+        SurfaceSample { point, normal, material: self.material_for(normal) }
+    }
+
+    // UV units per world unit on the face with this normal
+    pub fn uv_density(&self, normal: Vector3) -> f32 {
+        let (extent_u, extent_v) = self.face_extent(normal);
+        (self.uv_scale.0 / extent_u.max(1e-6) * self.uv_scale.1 / extent_v.max(1e-6)).sqrt()
+    }
+
     // Calculate UV coordinates for a point on the cube surface
     pub fn get_uv(&self, point: Vector3, normal: Vector3) -> (f32, f32) {
         let epsilon = 1e-4;
@@ -95,6 +266,70 @@ impl Cube {
     }
 }
 
+impl Cube {
+    // Directions in which `get_uv`'s u grows and v shrinks (up the image) on
+    // the face with this normal
+    fn face_frame(normal: Vector3) -> (Vector3, Vector3) {
+        let up = Vector3::new(0.0, 1.0, 0.0);
+        match Face::from_normal(normal) {
+            Face::Right => (Vector3::new(0.0, 0.0, 1.0), up),
+            Face::Left => (Vector3::new(0.0, 0.0, -1.0), up),
+            Face::Top => (Vector3::new(1.0, 0.0, 0.0), Vector3::new(0.0, 0.0, -1.0)),
+            Face::Bottom => (Vector3::new(1.0, 0.0, 0.0), Vector3::new(0.0, 0.0, 1.0)),
+            Face::Front => (Vector3::new(-1.0, 0.0, 0.0), up),
+            Face::Back => (Vector3::new(1.0, 0.0, 0.0), up),
+        }
+    }
+
+    // Face extents along its u and v directions
+    fn face_extent(&self, normal: Vector3) -> (f32, f32) {
+        let size = self.max - self.min;
+        if normal.x.abs() > 0.5 {
+            (size.z, size.y)
+        } else if normal.y.abs() > 0.5 {
+            (size.x, size.z)
+        } else {
+            (size.x, size.y)
+        }
+    }
+
+    // Normal for lighting at a hit on the cube: the face normal bent by the
+    // normal map and the slope of the bump map, or unchanged without them
+    pub fn shading_normal(&self, intersect: &Intersect, textures: &[Texture]) -> Vector3 {
+        let normal = intersect.normal;
+        if self.normal_map.is_none() && self.bump_map.is_none() {
+            return normal;
+        }
+        let (u, v) = self.get_uv(intersect.point, normal);
+        let (u, v) = (u * self.uv_scale.0, v * self.uv_scale.1);
+
+        // Tangent space: x along u, y up the image, z out of the face
+        let mut local = Vector3::new(0.0, 0.0, 1.0);
+        if let Some(id) = self.normal_map {
+            let texture = &textures[id.index()];
+            let texel = texture.sample_raw(u, v);
+            let mapped = Vector3::new(texel.r * 2.0 - 1.0, texel.g * 2.0 - 1.0, texel.b * 2.0 - 1.0);
+            if mapped.z > 0.0 {
+                local = mapped.normalized();
+            }
+        }
+        if let Some((id, depth)) = self.bump_map {
+            let texture = &textures[id.index()];
+            // Central differences one texel apart, turned into height change
+            // per world unit along each face axis
+            let (du, dv) = (1.0 / texture.width as f32, 1.0 / texture.height as f32);
+            let height = |u: f32, v: f32| texture.sample_raw(u, v).luminance();
+            let (extent_u, extent_v) = self.face_extent(normal);
+            let slope_u = (height(u + du, v) - height(u - du, v)) / (2.0 * du) * self.uv_scale.0 / extent_u.max(1e-6);
+            let slope_up = (height(u, v - dv) - height(u, v + dv)) / (2.0 * dv) * self.uv_scale.1 / extent_v.max(1e-6);
+            local = Vector3::new(local.x - depth * slope_u * local.z, local.y - depth * slope_up * local.z, local.z).normalized();
+        }
+
+        let (tangent, up) = Self::face_frame(normal);
+        (tangent * local.x + up * local.y + normal * local.z).normalized()
+    }
+}
+
 impl RayIntersect for Cube {
     fn ray_intersect(&self, ray_origin: &Vector3, ray_direction: &Vector3) -> Intersect {
         // Slab method for ray-cube intersection
@@ -195,7 +430,7 @@ impl RayIntersect for Cube {
         
         if t > 0.0 {
             let point = *ray_origin + *ray_direction * t;
-            return Intersect::new(point, hit_normal, t, self.material);
+            return Intersect::new(point, hit_normal, t, self.material_for(hit_normal)).with_tangent(Self::face_frame(hit_normal).0);
         }
 
         Intersect::empty()
@@ -0,0 +1,42 @@
+use crate::renderer::Object;
+use crate::texture::Texture;
+use crate::camera::CustomCamera;
+use crate::light::Light;
+use crate::skybox::Skybox;
+use crate::scene_file;
+use std::path::Path;
+
+// Everything needed to render a diorama: the texture list the objects'
+// `TextureHandle`s index, the objects, the lights, the sky and the camera.
+pub struct Diorama {
+    pub textures: Vec<Texture>,
+    pub objects: Vec<Object>,
+    pub lights: Vec<Light>,
+    pub skybox: Skybox,
+    pub camera: CustomCamera,
+}
+
+// Built-in scene, compiled into the binary so it runs without any files
+const ZEN_GARDEN: &str = include_str!("../scenes/zen_garden.json");
+
+// Modern Zen Garden - shared by the interactive viewer and the headless renderer
+pub fn zen_garden() -> Diorama {
+    match scene_file::parse(ZEN_GARDEN, Path::new("scenes")) {
+        Ok(diorama) => diorama,
+        Err(err) => panic!("built-in scenes/zen_garden.json is invalid: {}", err),
+    }
+}
+
+// Load a scene file if a path was given, otherwise fall back to the garden
+pub fn load_or_default(path: Option<&str>) -> Result<Diorama, scene_file::SceneError> {
+    match path {
+        Some(path) => scene_file::load(path),
+        None => Ok(zen_garden()),
+    }
+}
+
+// The scene's own lights plus the sun of a physical sky, which is kept apart
+// so it can follow the sky when the time of day changes
+pub fn lights_with_sun(lights: &[Light], skybox: &Skybox) -> Vec<Light> {
+    lights.iter().cloned().chain(skybox.sun_light()).collect()
+}
@@ -0,0 +1,74 @@
+use raylib::prelude::*;
+use crate::color::LinearColor;
+use crate::light::LightSample;
+use crate::material::Material;
+use crate::renderer::{Object, Scene};
+
+// Shadow rays stop this short of the emitting surface so they are not
+// blocked by the emitter itself
+const EMITTER_BIAS: f32 = 1e-3;
+
+// Shadow rays per shading point the Whitted tracer sends to each emitter.
+// Few are needed since the viewer keeps accumulating passes.
+pub const EMITTER_SAMPLES: u32 = 4;
+
+// A point on an object's surface with the material found there
+#[derive(Debug, Clone, Copy)]
+pub struct SurfaceSample {
+    pub point: Vector3,
+    pub normal: Vector3,
+    pub material: Material,
+}
+
+// An object whose material glows, lighting the scene like an area light.
+// Points are picked uniformly over its whole surface; parts of a mesh that
+// do not glow simply contribute nothing.
+#[derive(Debug, Clone, Copy)]
+pub struct Emitter {
+    // Index into the scene's objects
+    pub object: usize,
+    area: f32,
+}
+
+impl Emitter {
+    pub fn collect(objects: &[Object]) -> Vec<Emitter> {
+        objects
+            .iter()
+            .enumerate()
+            .filter(|(_, object)| object.is_emissive())
+            .map(|(object, o)| Emitter { object, area: o.area() })
+            .filter(|emitter| emitter.area > 0.0)
+            .collect()
+    }
+
+    // Shadow ray towards a random point on the emitter for the numbers
+    // `s`, `t`, `pick` in [0, 1), plus the radiance leaving that point.
+    // `weight` turns that radiance into the light arriving at `from`.
+    pub fn sample(&self, scene: &Scene, from: Vector3, s: f32, t: f32, pick: f32) -> Option<(LightSample, LinearColor)> {
+        let surface = scene.objects()[self.object].sample_surface(s, t, pick)?;
+        let to_light = surface.point - from;
+        let distance = to_light.length();
+        if distance <= EMITTER_BIAS {
+            return None;
+        }
+        let direction = to_light / distance;
+        // Emission leaves the outside of the surface only
+        let cos_emitter = -surface.normal.dot(direction);
+        if cos_emitter <= 0.0 {
+            return None;
+        }
+        let sample = LightSample {
+            direction,
+            distance: distance - EMITTER_BIAS,
+            weight: cos_emitter * self.area / (distance * distance),
+            pdf: Some(self.pdf(distance, cos_emitter)),
+        };
+        Some((sample, surface.material.emitted()))
+    }
+
+    // Solid-angle density of `sample` picking a point `distance` away whose
+    // normal makes `cos_emitter` with the ray
+    pub fn pdf(&self, distance: f32, cos_emitter: f32) -> f32 {
+        distance * distance / (self.area * cos_emitter)
+    }
+}
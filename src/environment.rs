@@ -0,0 +1,281 @@
+use raylib::prelude::*;
+use crate::color::LinearColor;
+use crate::hdr_image::HdrImage;
+use crate::sampling::{phong_lobe, stratified, Rng};
+use crate::skybox::{direction_to_equirect, equirect_to_direction, Sky, Skybox};
+use rayon::prelude::*;
+use std::f32::consts::PI;
+
+// Resolution the sky is tabulated at for lighting. Image skies keep up to
+// `MAX_TABLE_WIDTH` of their own width so small bright suns survive.
+const TABLE_WIDTH: usize = 256;
+const MAX_TABLE_WIDTH: usize = 1024;
+
+// Phong exponents of the prefiltered maps, with the width each is stored at.
+// Lobes sharper than the last one read the table directly.
+const PREFILTER_LEVELS: [(f32, usize); 6] = [(1.0, 32), (4.0, 32), (16.0, 64), (64.0, 128), (256.0, 256), (1024.0, 256)];
+// Lobe samples per prefiltered texel
+const PREFILTER_SAMPLES: u32 = 64;
+
+// Lighting from the sky, precomputed from a `Skybox`:
+// - importance sampling of directions in proportion to their brightness,
+//   for the path tracer's next-event estimation;
+// - diffuse irradiance as 9 spherical-harmonic coefficients;
+// - the sky blurred by Phong lobes of growing sharpness, for glossy
+//   reflections in the Whitted tracer.
+// Everything is tabulated on a latitude-longitude grid laid out like
+// `Sky::Equirect`, after the skybox's rotation and intensity.
+pub struct Environment {
+    pub skybox: Skybox,
+    table: HdrImage,
+    // Downsampled copies of `table`, halving down to a few texels
+    mips: Vec<HdrImage>,
+    // Sampling density of each texel before the sin(θ) area factor, and the
+    // cumulative distributions over rows and, within each row, columns
+    weights: Vec<f32>,
+    row_cdf: Vec<f32>,
+    column_cdf: Vec<f32>,
+    weight_total: f32,
+    irradiance_sh: [LinearColor; 9],
+    prefiltered: Vec<(f32, HdrImage)>,
+}
+
+impl Environment {
+    pub fn new(skybox: Skybox) -> Self {
+        let width = match &skybox.sky {
+            Sky::Equirect(image) => (image.width as usize).clamp(TABLE_WIDTH, MAX_TABLE_WIDTH),
+            _ => TABLE_WIDTH,
+        };
+        let table = tabulate(width, width / 2, |direction| skybox.radiance(&direction));
+        let mips = build_mips(&table);
+
+        let mut environment = Environment {
+            skybox,
+            table,
+            mips,
+            weights: Vec::new(),
+            row_cdf: Vec::new(),
+            column_cdf: Vec::new(),
+            weight_total: 0.0,
+            irradiance_sh: [LinearColor::BLACK; 9],
+            prefiltered: Vec::new(),
+        };
+        environment.build_distribution();
+        environment.irradiance_sh = project_sh(&environment.table);
+        environment.prefiltered = PREFILTER_LEVELS
+            .iter()
+            .map(|&(exponent, width)| (exponent, environment.prefilter(exponent, width)))
+            .collect();
+        environment
+    }
+
+    // Radiance arriving from `direction`, exactly as camera rays see it
+    pub fn radiance(&self, direction: &Vector3) -> LinearColor {
+        self.skybox.radiance(direction)
+    }
+
+    // Irradiance on a surface facing `normal`, ignoring anything that might
+    // block the sky; a Lambertian surface reflects albedo / π times this
+    pub fn irradiance(&self, normal: &Vector3) -> LinearColor {
+        let basis = sh_basis(&normal.normalized());
+        // Cosine lobe convolution per band (Ramamoorthi & Hanrahan)
+        let band = [PI, 2.0 * PI / 3.0, 2.0 * PI / 3.0, 2.0 * PI / 3.0, PI / 4.0, PI / 4.0, PI / 4.0, PI / 4.0, PI / 4.0];
+        let mut irradiance = LinearColor::BLACK;
+        for i in 0..9 {
+            irradiance += self.irradiance_sh[i] * (band[i] * basis[i]);
+        }
+        irradiance.map(|c| c.max(0.0))
+    }
+
+    // Sky radiance averaged over a normalized Phong lobe cos^exponent around
+    // `direction`: what a glossy surface reflects towards the mirror direction
+    pub fn specular(&self, direction: &Vector3, exponent: f32) -> LinearColor {
+        let (u, v) = direction_to_equirect(&direction.normalized());
+        let levels = &self.prefiltered;
+        let Some(upper) = levels.iter().position(|(e, _)| *e >= exponent) else {
+            return self.table.sample(u, v, true);
+        };
+        if upper == 0 {
+            return levels[0].1.sample(u, v, true);
+        }
+        let ((low_exponent, low), (high_exponent, high)) = (&levels[upper - 1], &levels[upper]);
+        let t = (exponent / low_exponent).ln() / (high_exponent / low_exponent).ln();
+        low.sample(u, v, true) * (1.0 - t) + high.sample(u, v, true) * t
+    }
+
+    // Direction towards the sky for the numbers `s`, `t` in [0, 1), picked
+    // in proportion to brightness, with its solid-angle density. None for a
+    // black sky.
+    pub fn sample(&self, s: f32, t: f32) -> Option<(Vector3, f32)> {
+        if self.weight_total <= 0.0 {
+            return None;
+        }
+        let (width, height) = (self.table.width as usize, self.table.height as usize);
+        let (row, v) = sample_cdf(&self.row_cdf, t);
+        let (column, u) = sample_cdf(&self.column_cdf[row * (width + 1)..(row + 1) * (width + 1)], s);
+        let direction = equirect_to_direction(u / width as f32, v / height as f32);
+        let pdf = self.texel_pdf(column, row);
+        (pdf > 0.0).then_some((direction, pdf))
+    }
+
+    // Solid-angle density of `sample` choosing `direction`
+    pub fn pdf(&self, direction: &Vector3) -> f32 {
+        if self.weight_total <= 0.0 {
+            return 0.0;
+        }
+        let (width, height) = (self.table.width as usize, self.table.height as usize);
+        let (u, v) = direction_to_equirect(&direction.normalized());
+        let column = ((u.rem_euclid(1.0) * width as f32) as usize).min(width - 1);
+        let row = ((v * height as f32) as usize).min(height - 1);
+        self.texel_pdf(column, row)
+    }
+
+    // A texel is picked with probability weight * sin(θ) / total and covers
+    // (2π / width) (π / height) sin(θ) steradians, so the sines cancel
+    fn texel_pdf(&self, column: usize, row: usize) -> f32 {
+        let (width, height) = (self.table.width as f32, self.table.height as f32);
+        self.weights[row * self.table.width as usize + column] * width * height / (2.0 * PI * PI * self.weight_total)
+    }
+
+    fn build_distribution(&mut self) {
+        let (width, height) = (self.table.width as usize, self.table.height as usize);
+        let luminance: Vec<f32> = self.table.texels.iter().map(|t| t.luminance().max(0.0)).collect();
+        // A floor keeps every direction reachable: the table is only an
+        // approximation of the sky between its texel centres
+        let mean = luminance.iter().sum::<f32>() / luminance.len() as f32;
+        let floor = mean * 1e-2;
+        self.weights = luminance.iter().map(|l| if mean > 0.0 { l + floor } else { 0.0 }).collect();
+
+        self.column_cdf = vec![0.0; height * (width + 1)];
+        self.row_cdf = vec![0.0; height + 1];
+        for row in 0..height {
+            let sin_theta = (PI * (row as f32 + 0.5) / height as f32).sin();
+            let cdf = &mut self.column_cdf[row * (width + 1)..(row + 1) * (width + 1)];
+            for column in 0..width {
+                cdf[column + 1] = cdf[column] + self.weights[row * width + column] * sin_theta;
+            }
+            self.row_cdf[row + 1] = self.row_cdf[row] + cdf[width];
+        }
+        self.weight_total = self.row_cdf[height];
+    }
+
+    // Phong-lobe blur of the table at `width` x `width / 2`, by importance
+    // sampling the lobe and reading the mip level whose texels are about as
+    // large as the solid angle each sample stands for
+    fn prefilter(&self, exponent: f32, width: usize) -> HdrImage {
+        let width = width.min(self.table.width as usize);
+        let height = width / 2;
+        let texel_solid_angle = 4.0 * PI / (self.table.width * self.table.height) as f32;
+        let texels = (0..width * height)
+            .into_par_iter()
+            .map(|index| {
+                let (column, row) = (index % width, index / width);
+                let axis = equirect_to_direction((column as f32 + 0.5) / width as f32, (row as f32 + 0.5) / height as f32);
+                let mut rng = Rng::new(index as u64, 0x5eed);
+                let mut sum = LinearColor::BLACK;
+                for i in 0..PREFILTER_SAMPLES {
+                    let (s, t) = stratified(i, PREFILTER_SAMPLES, &mut rng);
+                    let direction = phong_lobe(axis, exponent, s, t);
+                    let cos_alpha = axis.dot(direction).max(1e-4);
+                    let pdf = (exponent + 1.0) / (2.0 * PI) * cos_alpha.powf(exponent);
+                    let sample_solid_angle = 1.0 / (PREFILTER_SAMPLES as f32 * pdf.max(1e-6));
+                    let level = (0.5 * (sample_solid_angle / texel_solid_angle).log2()).max(0.0).round() as usize;
+                    let image = if level == 0 { &self.table } else { &self.mips[(level - 1).min(self.mips.len() - 1)] };
+                    let (u, v) = direction_to_equirect(&direction);
+                    sum += image.sample(u, v, true);
+                }
+                sum * (1.0 / PREFILTER_SAMPLES as f32)
+            })
+            .collect();
+        HdrImage {
+            width: width as u32,
+            height: height as u32,
+            texels,
+        }
+    }
+}
+
+// Evaluate `radiance` at the centre of every texel of a lat-long grid
+fn tabulate(width: usize, height: usize, radiance: impl Fn(Vector3) -> LinearColor + Sync) -> HdrImage {
+    let texels = (0..width * height)
+        .into_par_iter()
+        .map(|index| {
+            let (column, row) = (index % width, index / width);
+            radiance(equirect_to_direction((column as f32 + 0.5) / width as f32, (row as f32 + 0.5) / height as f32))
+        })
+        .collect();
+    HdrImage {
+        width: width as u32,
+        height: height as u32,
+        texels,
+    }
+}
+
+// Box-filtered halvings of `image`, down to 8 texels wide
+fn build_mips(image: &HdrImage) -> Vec<HdrImage> {
+    let mut mips: Vec<HdrImage> = Vec::new();
+    let mut current = image;
+    while current.width >= 16 && current.height >= 2 {
+        let (width, height) = (current.width as usize / 2, current.height as usize / 2);
+        let source_width = current.width as usize;
+        let mut texels = Vec::with_capacity(width * height);
+        for row in 0..height {
+            for column in 0..width {
+                let at = |x: usize, y: usize| current.texels[(2 * row + y) * source_width + 2 * column + x];
+                texels.push((at(0, 0) + at(1, 0) + at(0, 1) + at(1, 1)) * 0.25);
+            }
+        }
+        mips.push(HdrImage {
+            width: width as u32,
+            height: height as u32,
+            texels,
+        });
+        current = mips.last().unwrap();
+    }
+    mips
+}
+
+// Real spherical harmonics up to band 2 at a unit direction
+fn sh_basis(d: &Vector3) -> [f32; 9] {
+    [
+        0.282095,
+        0.488603 * d.y,
+        0.488603 * d.z,
+        0.488603 * d.x,
+        1.092548 * d.x * d.y,
+        1.092548 * d.y * d.z,
+        0.315392 * (3.0 * d.z * d.z - 1.0),
+        1.092548 * d.x * d.z,
+        0.546274 * (d.x * d.x - d.y * d.y),
+    ]
+}
+
+// Project a lat-long table onto the first 9 spherical harmonics
+fn project_sh(table: &HdrImage) -> [LinearColor; 9] {
+    let (width, height) = (table.width as usize, table.height as usize);
+    let mut coefficients = [LinearColor::BLACK; 9];
+    for row in 0..height {
+        let v = (row as f32 + 0.5) / height as f32;
+        let solid_angle = (2.0 * PI / width as f32) * (PI / height as f32) * (PI * v).sin();
+        for column in 0..width {
+            let direction = equirect_to_direction((column as f32 + 0.5) / width as f32, v);
+            let radiance = table.texels[row * width + column] * solid_angle;
+            for (coefficient, basis) in coefficients.iter_mut().zip(sh_basis(&direction)) {
+                *coefficient += radiance * basis;
+            }
+        }
+    }
+    coefficients
+}
+
+// Invert a cumulative distribution (length n + 1, starting at 0) at `u`:
+// the chosen bucket and the continuous position within 0..n
+fn sample_cdf(cdf: &[f32], u: f32) -> (usize, f32) {
+    let total = cdf[cdf.len() - 1];
+    let target = u * total;
+    // Last entry not above the target, skipping empty buckets
+    let bucket = cdf.partition_point(|&c| c <= target).saturating_sub(1).min(cdf.len() - 2);
+    let span = cdf[bucket + 1] - cdf[bucket];
+    let offset = if span > 0.0 { ((target - cdf[bucket]) / span).clamp(0.0, 1.0) } else { 0.5 };
+    (bucket, bucket as f32 + offset)
+}
@@ -0,0 +1,497 @@
+use crate::color::LinearColor;
+use crate::hdr_image::HdrImage;
+use crate::inflate::zlib_decompress;
+
+// OpenEXR reader for single-part scanline images: HALF, FLOAT or UINT
+// channels named R, G, B (or a single Y), stored uncompressed or with RLE,
+// ZIPS or ZIP compression. Other channels (alpha, depth, ...) are skipped.
+// Tiled, deep and multi-part files, and the wavelet/lossy compressions, are
+// reported as errors.
+
+const MAGIC: [u8; 4] = [0x76, 0x2f, 0x31, 0x01];
+const TILED: u32 = 0x200;
+const NON_IMAGE: u32 = 0x800;
+const MULTI_PART: u32 = 0x1000;
+// Largest ratio of decoded to stored bytes any supported compression reaches
+const MAX_EXPANSION: usize = 1032;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum PixelType {
+    Uint,
+    Half,
+    Float,
+}
+
+impl PixelType {
+    fn size(self) -> usize {
+        match self {
+            PixelType::Half => 2,
+            PixelType::Uint | PixelType::Float => 4,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Channel {
+    name: String,
+    pixel_type: PixelType,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Compression {
+    None,
+    Rle,
+    Zips,
+    Zip,
+}
+
+impl Compression {
+    fn lines_per_block(self) -> usize {
+        match self {
+            Compression::Zip => 16,
+            _ => 1,
+        }
+    }
+}
+
+// Little-endian cursor over the file
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, count: usize) -> Result<&'a [u8], String> {
+        let end = self.position.checked_add(count).ok_or("unexpected end of file")?;
+        let slice = self.bytes.get(self.position..end).ok_or("unexpected end of file")?;
+        self.position += count;
+        Ok(slice)
+    }
+
+    fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn i32(&mut self) -> Result<i32, String> {
+        let b = self.take(4)?;
+        Ok(i32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    // Byte count stored as a signed 32-bit integer
+    fn size(&mut self) -> Result<usize, String> {
+        usize::try_from(self.i32()?).map_err(|_| "negative size".to_string())
+    }
+
+    fn u64(&mut self) -> Result<u64, String> {
+        let b = self.take(8)?;
+        Ok(u64::from_le_bytes([b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7]]))
+    }
+
+    // Null-terminated string, empty at the end of a list
+    fn string(&mut self) -> Result<String, String> {
+        let rest = &self.bytes[self.position.min(self.bytes.len())..];
+        let end = rest.iter().position(|&b| b == 0).ok_or("unterminated string")?;
+        self.position += end + 1;
+        Ok(String::from_utf8_lossy(&rest[..end]).into_owned())
+    }
+}
+
+pub fn parse(bytes: &[u8]) -> Result<HdrImage, String> {
+    let mut reader = Reader { bytes, position: 0 };
+    if reader.take(4)? != MAGIC {
+        return Err("not an OpenEXR file".to_string());
+    }
+    let version = reader.i32()? as u32;
+    if version & 0xff != 2 {
+        return Err(format!("unsupported OpenEXR version {}", version & 0xff));
+    }
+    if version & TILED != 0 {
+        return Err("tiled OpenEXR files are not supported".to_string());
+    }
+    if version & (NON_IMAGE | MULTI_PART) != 0 {
+        return Err("deep and multi-part OpenEXR files are not supported".to_string());
+    }
+
+    let mut channels = Vec::new();
+    let mut compression = None;
+    let mut data_window = None;
+    loop {
+        let name = reader.string()?;
+        if name.is_empty() {
+            break;
+        }
+        let kind = reader.string()?;
+        let size = reader.size()?;
+        let mut value = Reader { bytes: reader.take(size)?, position: 0 };
+        match (name.as_str(), kind.as_str()) {
+            ("channels", "chlist") => loop {
+                let name = value.string()?;
+                if name.is_empty() {
+                    break;
+                }
+                let pixel_type = match value.i32()? {
+                    0 => PixelType::Uint,
+                    1 => PixelType::Half,
+                    2 => PixelType::Float,
+                    other => return Err(format!("unknown pixel type {} for channel '{}'", other, name)),
+                };
+                value.take(4)?; // pLinear and reserved bytes
+                let (x_sampling, y_sampling) = (value.i32()?, value.i32()?);
+                if x_sampling != 1 || y_sampling != 1 {
+                    return Err(format!("subsampled channel '{}' is not supported", name));
+                }
+                channels.push(Channel { name, pixel_type });
+            },
+            ("compression", "compression") => {
+                compression = Some(match value.u8()? {
+                    0 => Compression::None,
+                    1 => Compression::Rle,
+                    2 => Compression::Zips,
+                    3 => Compression::Zip,
+                    other => return Err(format!("unsupported compression method {} (use none, RLE or ZIP)", other)),
+                });
+            }
+            ("dataWindow", "box2i") => {
+                data_window = Some([value.i32()?, value.i32()?, value.i32()?, value.i32()?]);
+            }
+            _ => {}
+        }
+    }
+
+    let compression = compression.ok_or("missing 'compression' attribute")?;
+    let [x_min, y_min, x_max, y_max] = data_window.ok_or("missing 'dataWindow' attribute")?;
+    if x_max < x_min || y_max < y_min {
+        return Err("empty data window".to_string());
+    }
+    let width = (x_max as i64 - x_min as i64 + 1) as usize;
+    let height = (y_max as i64 - y_min as i64 + 1) as usize;
+    let too_large = || "data window too large".to_string();
+    let texel_count = width.checked_mul(height).ok_or_else(too_large)?;
+
+    // Which output component each channel feeds; a lone Y fills all three
+    let slot = |name: &str| match name {
+        "R" => Some(0),
+        "G" => Some(1),
+        "B" => Some(2),
+        "Y" => Some(3),
+        _ => None,
+    };
+    if !channels.iter().any(|c| slot(&c.name).is_some()) {
+        return Err("no R, G, B or Y channel".to_string());
+    }
+    let line_size = channels
+        .iter()
+        .try_fold(0usize, |sum, c| sum.checked_add(c.pixel_type.size().checked_mul(width)?))
+        .ok_or_else(too_large)?;
+    // DEFLATE expands data at most about 1032 times, so a header claiming
+    // more than that is corrupt; checked before the image is allocated
+    if line_size.checked_mul(height).ok_or_else(too_large)? / MAX_EXPANSION > bytes.len() {
+        return Err("data window larger than the file can hold".to_string());
+    }
+
+    let lines_per_block = compression.lines_per_block();
+    let blocks = height.div_ceil(lines_per_block);
+    let offsets = (0..blocks).map(|_| reader.u64()).collect::<Result<Vec<_>, _>>()?;
+
+    let mut texels = vec![LinearColor::BLACK; texel_count];
+    for offset in offsets {
+        let mut block = Reader { bytes, position: offset as usize };
+        let first_line = block.i32()? as i64 - y_min as i64;
+        let packed_size = block.size()?;
+        let packed = block.take(packed_size)?;
+
+        if first_line < 0 || first_line as usize >= height {
+            return Err("scanline block outside the data window".to_string());
+        }
+        let first_line = first_line as usize;
+        let lines = lines_per_block.min(height - first_line);
+        let expected = line_size.checked_mul(lines).ok_or_else(too_large)?;
+
+        let data = if packed_size == expected {
+            // Blocks that would not shrink are stored as they are
+            packed.to_vec()
+        } else {
+            match compression {
+                Compression::None => return Err("scanline block has the wrong size".to_string()),
+                Compression::Rle => undo_predictor(rle_decompress(packed, expected)?),
+                Compression::Zips | Compression::Zip => undo_predictor(zlib_decompress(packed, expected)?),
+            }
+        };
+        if data.len() != expected {
+            return Err("scanline block has the wrong size after decompression".to_string());
+        }
+
+        // Each line holds every channel's row in turn
+        let mut row = Reader { bytes: &data, position: 0 };
+        for line in first_line..first_line + lines {
+            for channel in &channels {
+                let values = row.take(channel.pixel_type.size() * width)?;
+                let Some(slot) = slot(&channel.name) else {
+                    continue;
+                };
+                for (x, value) in values.chunks_exact(channel.pixel_type.size()).enumerate() {
+                    let value = match channel.pixel_type {
+                        PixelType::Half => half_to_f32(u16::from_le_bytes([value[0], value[1]])),
+                        PixelType::Float => f32::from_le_bytes([value[0], value[1], value[2], value[3]]),
+                        PixelType::Uint => u32::from_le_bytes([value[0], value[1], value[2], value[3]]) as f32,
+                    };
+                    let texel = &mut texels[line * width + x];
+                    match slot {
+                        0 => texel.r = value,
+                        1 => texel.g = value,
+                        2 => texel.b = value,
+                        _ => *texel = LinearColor::new(value, value, value),
+                    }
+                }
+            }
+        }
+    }
+
+    // Negative or NaN values would poison the sampling
+    for texel in &mut texels {
+        *texel = texel.map(|c| if c.is_finite() { c.max(0.0) } else { 0.0 });
+    }
+
+    Ok(HdrImage {
+        width: width as u32,
+        height: height as u32,
+        texels,
+    })
+}
+
+// Signed run lengths: a negative count is followed by that many literal
+// bytes, a positive one by a byte to repeat count + 1 times
+fn rle_decompress(packed: &[u8], expected: usize) -> Result<Vec<u8>, String> {
+    let mut out = Vec::with_capacity(expected);
+    let mut i = 0;
+    while i < packed.len() {
+        let count = packed[i] as i8;
+        i += 1;
+        if count < 0 {
+            let literal = packed.get(i..i + (-(count as i32)) as usize).ok_or("truncated RLE block")?;
+            out.extend_from_slice(literal);
+            i += literal.len();
+        } else {
+            let value = *packed.get(i).ok_or("truncated RLE block")?;
+            out.extend(std::iter::repeat_n(value, count as usize + 1));
+            i += 1;
+        }
+    }
+    Ok(out)
+}
+
+// RLE and ZIP store byte deltas with the two halves of each value's bytes
+// split apart; rebuild the original interleaved bytes
+fn undo_predictor(mut data: Vec<u8>) -> Vec<u8> {
+    for i in 1..data.len() {
+        data[i] = data[i - 1].wrapping_add(data[i]).wrapping_sub(128);
+    }
+    let half = data.len().div_ceil(2);
+    let (first, second) = data.split_at(half);
+    let mut out = Vec::with_capacity(data.len());
+    for (i, &a) in first.iter().enumerate() {
+        out.push(a);
+        if let Some(&b) = second.get(i) {
+            out.push(b);
+        }
+    }
+    out
+}
+
+fn half_to_f32(bits: u16) -> f32 {
+    let sign = if bits & 0x8000 != 0 { -1.0 } else { 1.0 };
+    let exponent = ((bits >> 10) & 0x1f) as i32;
+    let mantissa = (bits & 0x3ff) as f32;
+    match exponent {
+        0 => sign * mantissa * 2f32.powi(-24),
+        31 if mantissa == 0.0 => sign * f32::INFINITY,
+        31 => f32::NAN,
+        _ => sign * (1.0 + mantissa / 1024.0) * 2f32.powi(exponent - 15),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Inverse of `undo_predictor`: split the bytes of each value apart, then
+    // store differences
+    fn predict(data: &[u8]) -> Vec<u8> {
+        let mut out: Vec<u8> = data.iter().step_by(2).chain(data.iter().skip(1).step_by(2)).copied().collect();
+        for i in (1..out.len()).rev() {
+            out[i] = out[i].wrapping_sub(out[i - 1]).wrapping_add(128);
+        }
+        out
+    }
+
+    // zlib stream holding `data` in a single stored block
+    fn zlib_stored(data: &[u8]) -> Vec<u8> {
+        let len = data.len() as u16;
+        let mut out = vec![0x78, 0x01, 0x01];
+        out.extend_from_slice(&len.to_le_bytes());
+        out.extend_from_slice(&(!len).to_le_bytes());
+        out.extend_from_slice(data);
+        let (mut a, mut b) = (1u32, 0u32);
+        for &byte in data {
+            a = (a + byte as u32) % 65521;
+            b = (b + a) % 65521;
+        }
+        out.extend_from_slice(&(b << 16 | a).to_be_bytes());
+        out
+    }
+
+    fn attribute(out: &mut Vec<u8>, name: &str, kind: &str, value: &[u8]) {
+        for text in [name, kind] {
+            out.extend_from_slice(text.as_bytes());
+            out.push(0);
+        }
+        out.extend_from_slice(&(value.len() as i32).to_le_bytes());
+        out.extend_from_slice(value);
+    }
+
+    // Scanline file with HALF channels B, G and R. `lines` holds each line's
+    // (B, G, R) rows of half bits; `pack` compresses one block of lines.
+    fn exr_file(width: usize, lines: &[[Vec<u16>; 3]], compression: u8, pack: impl Fn(&[u8]) -> Vec<u8>) -> Vec<u8> {
+        let mut out = MAGIC.to_vec();
+        out.extend_from_slice(&2i32.to_le_bytes());
+
+        let mut channels = Vec::new();
+        for name in ["B", "G", "R"] {
+            channels.extend_from_slice(name.as_bytes());
+            channels.push(0);
+            channels.extend_from_slice(&1i32.to_le_bytes());
+            channels.extend_from_slice(&[0; 4]);
+            channels.extend_from_slice(&1i32.to_le_bytes());
+            channels.extend_from_slice(&1i32.to_le_bytes());
+        }
+        channels.push(0);
+        attribute(&mut out, "channels", "chlist", &channels);
+        attribute(&mut out, "compression", "compression", &[compression]);
+        let window: Vec<u8> = [0, 0, width as i32 - 1, lines.len() as i32 - 1]
+            .iter()
+            .flat_map(|v| v.to_le_bytes())
+            .collect();
+        attribute(&mut out, "dataWindow", "box2i", &window);
+        out.push(0);
+
+        let per_block = if compression == 3 { 16 } else { 1 };
+        let blocks: Vec<Vec<u8>> = lines
+            .chunks(per_block)
+            .enumerate()
+            .map(|(i, chunk)| {
+                let raw: Vec<u8> = chunk.iter().flatten().flatten().flat_map(|h| h.to_le_bytes()).collect();
+                let packed = pack(&raw);
+                let mut block = ((i * per_block) as i32).to_le_bytes().to_vec();
+                block.extend_from_slice(&(packed.len() as i32).to_le_bytes());
+                block.extend_from_slice(&packed);
+                block
+            })
+            .collect();
+        let mut offset = out.len() + 8 * blocks.len();
+        for block in &blocks {
+            out.extend_from_slice(&(offset as u64).to_le_bytes());
+            offset += block.len();
+        }
+        for block in blocks {
+            out.extend_from_slice(&block);
+        }
+        out
+    }
+
+    // 2x2 image: (1, 0.5, 0.25) (-2, inf, 0) on top, (2, 1, 0.5) (0, 0, 1) below
+    fn test_lines() -> Vec<[Vec<u16>; 3]> {
+        vec![
+            [vec![0x3400, 0x0000], vec![0x3800, 0x7c00], vec![0x3c00, 0xc000]],
+            [vec![0x3800, 0x3c00], vec![0x3c00, 0x0000], vec![0x4000, 0x0000]],
+        ]
+    }
+
+    fn check_test_image(image: &HdrImage) {
+        assert_eq!((image.width, image.height), (2, 2));
+        let texels: Vec<[f32; 3]> = image.texels.iter().map(|t| [t.r, t.g, t.b]).collect();
+        // Negative and infinite values are cleared
+        assert_eq!(texels, [[1.0, 0.5, 0.25], [0.0, 0.0, 0.0], [2.0, 1.0, 0.5], [0.0, 0.0, 1.0]]);
+    }
+
+    #[test]
+    fn undoes_the_predictor() {
+        assert_eq!(undo_predictor(vec![1, 130, 130, 130, 123, 130, 130]), [1, 2, 3, 4, 5, 6, 7]);
+        // Differences wrap around
+        assert_eq!(undo_predictor(vec![0x00, 0x90, 0x6f, 0x01]), [0x00, 0xff, 0x10, 0x80]);
+        let bytes: Vec<u8> = (0..=255u8).rev().chain(0..100).collect();
+        assert_eq!(undo_predictor(predict(&bytes)), bytes);
+        assert!(undo_predictor(Vec::new()).is_empty());
+    }
+
+    #[test]
+    fn converts_half_floats() {
+        assert_eq!(half_to_f32(0x0000), 0.0);
+        assert!(half_to_f32(0x8000) == 0.0 && half_to_f32(0x8000).is_sign_negative());
+        assert_eq!(half_to_f32(0x0001), 2f32.powi(-24));
+        assert_eq!(half_to_f32(0x03ff), 1023.0 * 2f32.powi(-24));
+        assert_eq!(half_to_f32(0x0400), 2f32.powi(-14));
+        assert_eq!(half_to_f32(0x3c00), 1.0);
+        assert_eq!(half_to_f32(0xc000), -2.0);
+        assert_eq!(half_to_f32(0x7bff), 65504.0);
+        assert_eq!(half_to_f32(0x7c00), f32::INFINITY);
+        assert_eq!(half_to_f32(0xfc00), f32::NEG_INFINITY);
+        assert!(half_to_f32(0x7e00).is_nan());
+    }
+
+    #[test]
+    fn reads_uncompressed_files() {
+        let file = exr_file(2, &test_lines(), 0, |raw| raw.to_vec());
+        check_test_image(&parse(&file).unwrap());
+    }
+
+    #[test]
+    fn reads_rle_files() {
+        // Literal runs only: a negative count followed by that many bytes
+        let file = exr_file(2, &test_lines(), 1, |raw| {
+            let mut packed = vec![(-(raw.len() as i8)) as u8];
+            packed.extend_from_slice(&predict(raw));
+            packed
+        });
+        check_test_image(&parse(&file).unwrap());
+    }
+
+    #[test]
+    fn reads_zip_files() {
+        for compression in [2, 3] {
+            let file = exr_file(2, &test_lines(), compression, |raw| zlib_stored(&predict(raw)));
+            check_test_image(&parse(&file).unwrap());
+        }
+    }
+
+    #[test]
+    fn rejects_corrupt_sizes() {
+        let file = exr_file(2, &test_lines(), 0, |raw| raw.to_vec());
+
+        // Size of the "channels" attribute, just after its name and type
+        let mut negative_attribute = file.clone();
+        let at = 8 + "channels\0chlist\0".len();
+        negative_attribute[at..at + 4].copy_from_slice(&(-4i32).to_le_bytes());
+        assert_eq!(parse(&negative_attribute).unwrap_err(), "negative size");
+
+        // Packed size of the first block; each of the two blocks holds a line
+        // number, a size and 12 bytes of pixels
+        let mut negative_block = file.clone();
+        let at = file.len() - 2 * 20 + 4;
+        negative_block[at..at + 4].copy_from_slice(&(-1i32).to_le_bytes());
+        assert_eq!(parse(&negative_block).unwrap_err(), "negative size");
+
+        let mut huge_window = file.clone();
+        let window = [i32::MIN, i32::MIN, i32::MAX, i32::MAX];
+        let at = file.windows(6).position(|w| w == b"box2i\0").unwrap() + 6 + 4;
+        for (i, v) in window.iter().enumerate() {
+            huge_window[at + 4 * i..at + 4 * i + 4].copy_from_slice(&v.to_le_bytes());
+        }
+        assert_eq!(parse(&huge_window).unwrap_err(), "data window too large");
+        huge_window[at..at + 4].copy_from_slice(&0i32.to_le_bytes());
+        huge_window[at + 4..at + 8].copy_from_slice(&0i32.to_le_bytes());
+        huge_window[at + 12..at + 16].copy_from_slice(&0i32.to_le_bytes());
+        assert_eq!(parse(&huge_window).unwrap_err(), "data window larger than the file can hold");
+
+        assert!(parse(&file[..file.len() - 3]).is_err());
+    }
+}
@@ -1,5 +1,12 @@
 use raylib::prelude::*;
-use crate::color::Color as CustomColor;
+use crate::color::{Color as CustomColor, LinearColor};
+use crate::tonemap::ToneMapping;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+// Extensions raylib's ExportImage can write with its default configuration
+const RAYLIB_EXPORT_FORMATS: [&str; 5] = ["png", "bmp", "jpg", "jpeg", "qoi"];
 
 pub struct Framebuffer {
     width: i32,
@@ -7,6 +14,7 @@ pub struct Framebuffer {
     pub color_buffer:Image,
     background_color: Color,
     current_color: Color,
+    tone_mapping: ToneMapping,
 }
 
 impl Framebuffer {
@@ -18,6 +26,7 @@ impl Framebuffer {
             color_buffer,
             background_color,
             current_color: Color::WHITE,
+            tone_mapping: ToneMapping::default(),
         }
     }
 
@@ -42,6 +51,21 @@ impl Framebuffer {
         }
     }
 
+    // HDR pixel: tone mapped and sRGB encoded on the way in, the only place
+    // where linear radiance becomes 8-bit
+    pub fn set_pixel_hdr(&mut self, x: u32, y: u32, color: LinearColor) {
+        let display = self.tone_mapping.to_display(color);
+        self.set_pixel_with_color(x, y, display);
+    }
+
+    pub fn tone_mapping(&self) -> ToneMapping {
+        self.tone_mapping
+    }
+
+    pub fn set_tone_mapping(&mut self, tone_mapping: ToneMapping) {
+        self.tone_mapping = tone_mapping;
+    }
+
     // setton el color de fondo
     pub fn set_background_color(&mut self, color: Color) {
         self.background_color = color;
@@ -58,6 +82,48 @@ impl Framebuffer {
         self.color_buffer.export_image(file_path);
     }
 
+    // Write the framebuffer to disk. PPM is written directly; the formats the
+    // bundled raylib build can encode go through its image exporter.
+    pub fn save(&self, file_path: &str) -> io::Result<()> {
+        let extension = Path::new(file_path)
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_ascii_lowercase());
+
+        match extension.as_deref() {
+            Some("ppm") => self.write_ppm(file_path),
+            Some(e) if RAYLIB_EXPORT_FORMATS.contains(&e) => {
+                // export_image reports nothing, so clear any earlier render
+                // first: the file only exists afterwards if the export worked
+                match fs::remove_file(file_path) {
+                    Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err),
+                    _ => {}
+                }
+                self.color_buffer.export_image(file_path);
+                if Path::new(file_path).exists() {
+                    Ok(())
+                } else {
+                    Err(io::Error::other("image export failed"))
+                }
+            }
+            Some(e) => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("unsupported output format .{} (use .png, .bmp, .jpg, .qoi or .ppm)", e),
+            )),
+            None => Err(io::Error::new(io::ErrorKind::InvalidInput, "output path needs an extension (.png or .ppm)")),
+        }
+    }
+
+    // Binary PPM (P6)
+    fn write_ppm(&self, file_path: &str) -> io::Result<()> {
+        let mut out = BufWriter::new(File::create(file_path)?);
+        write!(out, "P6\n{} {}\n255\n", self.width, self.height)?;
+        for pixel in self.color_buffer.get_image_data().iter() {
+            out.write_all(&[pixel.r, pixel.g, pixel.b])?;
+        }
+        out.flush()
+    }
+
     pub fn swap_buffers(&self,
        window: &mut RaylibHandle,
        raylib_thread: &RaylibThread,) {
@@ -0,0 +1,276 @@
+use crate::color::LinearColor;
+use crate::exr;
+use crate::texture::Texture;
+use std::fs;
+use std::path::Path;
+
+// Run-length encoded scanlines pack at most 127 pixels into two bytes of
+// each of the four components, about 16 pixels per byte. Old-style repeat
+// markers could do better, but no writer uses them for whole images, so a
+// header promising more pixels than this is taken to be corrupt.
+const MAX_PIXELS_PER_BYTE: usize = 16;
+
+// Floating-point image in linear light, for environment maps whose sun and
+// sky are far brighter than the 0..255 range of a `Texture`
+#[derive(Debug, Clone)]
+pub struct HdrImage {
+    pub width: u32,
+    pub height: u32,
+    pub texels: Vec<LinearColor>,
+}
+
+impl HdrImage {
+    // Radiance .hdr/.pic and OpenEXR .exr keep their full range; any other
+    // format raylib reads is taken as sRGB and converted to linear
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let extension = path
+            .extension()
+            .map(|e| e.to_string_lossy().to_ascii_lowercase())
+            .unwrap_or_default();
+        let error = |e: String| format!("could not load '{}': {}", path.display(), e);
+        match extension.as_str() {
+            "hdr" | "pic" => {
+                let bytes = fs::read(path).map_err(|e| error(e.to_string()))?;
+                parse_rgbe(&bytes).map_err(error)
+            }
+            "exr" => {
+                let bytes = fs::read(path).map_err(|e| error(e.to_string()))?;
+                exr::parse(&bytes).map_err(error)
+            }
+            _ => Ok(HdrImage::from(&Texture::from_file(&path.to_string_lossy())?)),
+        }
+    }
+
+    fn texel(&self, x: i64, y: i64, wrap_x: bool) -> LinearColor {
+        let (width, height) = (self.width as i64, self.height as i64);
+        let x = if wrap_x { x.rem_euclid(width) } else { x.clamp(0, width - 1) };
+        let y = y.clamp(0, height - 1);
+        self.texels[(y * width + x) as usize]
+    }
+
+    // Bilinear lookup with (0, 0) at the top-left corner. `wrap_u` repeats
+    // horizontally (the seam of an equirectangular map); otherwise both
+    // directions clamp to the edge texels.
+    pub fn sample(&self, u: f32, v: f32, wrap_u: bool) -> LinearColor {
+        let x = u * self.width as f32 - 0.5;
+        let y = v * self.height as f32 - 0.5;
+        let (x0, y0) = (x.floor(), y.floor());
+        let (fx, fy) = (x - x0, y - y0);
+        let (x0, y0) = (x0 as i64, y0 as i64);
+
+        let top = self.texel(x0, y0, wrap_u) * (1.0 - fx) + self.texel(x0 + 1, y0, wrap_u) * fx;
+        let bottom = self.texel(x0, y0 + 1, wrap_u) * (1.0 - fx) + self.texel(x0 + 1, y0 + 1, wrap_u) * fx;
+        top * (1.0 - fy) + bottom * fy
+    }
+}
+
+impl From<&Texture> for HdrImage {
+    fn from(texture: &Texture) -> Self {
+        HdrImage {
+            width: texture.width,
+            height: texture.height,
+            texels: texture.data.iter().map(|c| c.to_linear()).collect(),
+        }
+    }
+}
+
+// Radiance RGBE: a text header, a resolution line and run-length encoded
+// scanlines of 8-bit mantissas sharing one exponent per pixel
+fn parse_rgbe(bytes: &[u8]) -> Result<HdrImage, String> {
+    let mut position = 0;
+    let mut line = || -> Result<&str, String> {
+        let rest = &bytes[position.min(bytes.len())..];
+        let end = rest.iter().position(|&b| b == b'\n').ok_or("truncated header")?;
+        position += end + 1;
+        std::str::from_utf8(&rest[..end]).map_err(|_| "header is not text".to_string())
+    };
+
+    let magic = line()?;
+    if !magic.starts_with("#?") {
+        return Err("not a Radiance HDR file".to_string());
+    }
+    loop {
+        let header = line()?;
+        if header.is_empty() {
+            break;
+        }
+        if let Some(format) = header.strip_prefix("FORMAT=").filter(|f| *f != "32-bit_rle_rgbe") {
+            return Err(format!("unsupported pixel format '{}'", format));
+        }
+    }
+
+    // Only the usual top-to-bottom, left-to-right orientation
+    let resolution: Vec<&str> = line()?.split_whitespace().collect();
+    let (height, width) = match resolution.as_slice() {
+        ["-Y", h, "+X", w] => (
+            h.parse::<usize>().map_err(|_| "bad resolution line")?,
+            w.parse::<usize>().map_err(|_| "bad resolution line")?,
+        ),
+        _ => return Err("unsupported image orientation (expected '-Y height +X width')".to_string()),
+    };
+
+    let texel_count = width.checked_mul(height).ok_or("image too large")?;
+    if texel_count / MAX_PIXELS_PER_BYTE > bytes.len() - position {
+        return Err("resolution larger than the file can hold".to_string());
+    }
+    let mut texels = Vec::with_capacity(texel_count);
+    let mut scanline = vec![[0u8; 4]; width];
+    for _ in 0..height {
+        position = read_rgbe_scanline(bytes, position, &mut scanline)?;
+        texels.extend(scanline.iter().map(|&rgbe| rgbe_to_linear(rgbe)));
+    }
+
+    Ok(HdrImage {
+        width: width as u32,
+        height: height as u32,
+        texels,
+    })
+}
+
+// Decode one scanline starting at `position`, returning where the next begins
+fn read_rgbe_scanline(bytes: &[u8], mut position: usize, scanline: &mut [[u8; 4]]) -> Result<usize, String> {
+    let truncated = || "truncated pixel data".to_string();
+    let width = scanline.len();
+    let head = bytes.get(position..position + 4).ok_or_else(truncated)?;
+
+    // New-style RLE: each of the four components run-length encoded apart
+    if (8..0x8000).contains(&width) && head[0] == 2 && head[1] == 2 && head[2] & 0x80 == 0 {
+        if ((head[2] as usize) << 8 | head[3] as usize) != width {
+            return Err("scanline width mismatch".to_string());
+        }
+        position += 4;
+        for component in 0..4 {
+            let mut x = 0;
+            while x < width {
+                let count = *bytes.get(position).ok_or_else(truncated)? as usize;
+                position += 1;
+                if count > 128 {
+                    let run = count - 128;
+                    let value = *bytes.get(position).ok_or_else(truncated)?;
+                    position += 1;
+                    if x + run > width {
+                        return Err("run overflows the scanline".to_string());
+                    }
+                    scanline[x..x + run].iter_mut().for_each(|p| p[component] = value);
+                    x += run;
+                } else {
+                    if count == 0 || x + count > width {
+                        return Err("bad literal run in scanline".to_string());
+                    }
+                    let values = bytes.get(position..position + count).ok_or_else(truncated)?;
+                    for (p, &value) in scanline[x..x + count].iter_mut().zip(values) {
+                        p[component] = value;
+                    }
+                    position += count;
+                    x += count;
+                }
+            }
+        }
+        return Ok(position);
+    }
+
+    // Flat pixels, with the old (1, 1, 1, n) marker repeating the previous one
+    let mut x = 0;
+    let mut shift = 0;
+    while x < width {
+        let pixel = bytes.get(position..position + 4).ok_or_else(truncated)?;
+        position += 4;
+        if pixel[0] == 1 && pixel[1] == 1 && pixel[2] == 1 && x > 0 {
+            if pixel[3] == 0 {
+                return Err("empty run in scanline".to_string());
+            }
+            if shift > usize::BITS - 8 {
+                return Err("run too long in scanline".to_string());
+            }
+            let run = (pixel[3] as usize) << shift;
+            let previous = scanline[x - 1];
+            let end = (x + run).min(width);
+            scanline[x..end].fill(previous);
+            x = end;
+            shift += 8;
+        } else {
+            scanline[x] = [pixel[0], pixel[1], pixel[2], pixel[3]];
+            x += 1;
+            shift = 0;
+        }
+    }
+    Ok(position)
+}
+
+fn rgbe_to_linear([r, g, b, e]: [u8; 4]) -> LinearColor {
+    if e == 0 {
+        return LinearColor::BLACK;
+    }
+    let scale = 2f32.powi(e as i32 - (128 + 8));
+    LinearColor::new((r as f32 + 0.5) * scale, (g as f32 + 0.5) * scale, (b as f32 + 0.5) * scale)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rgbe_file(width: usize, height: usize, pixels: &[u8]) -> Vec<u8> {
+        let mut out = format!("#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y {} +X {}\n", height, width).into_bytes();
+        out.extend_from_slice(pixels);
+        out
+    }
+
+    fn rgb(texel: &LinearColor) -> [f32; 3] {
+        [texel.r, texel.g, texel.b]
+    }
+
+    #[test]
+    fn decodes_rgbe() {
+        assert_eq!(rgb(&rgbe_to_linear([128, 64, 0, 129])), [128.5 / 128.0, 64.5 / 128.0, 0.5 / 128.0]);
+        assert_eq!(rgb(&rgbe_to_linear([255, 255, 255, 0])), [0.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn reads_flat_scanlines() {
+        // Second line repeats its first pixel with the old (1, 1, 1, n) marker
+        let pixels = [128, 64, 0, 129, 0, 0, 0, 0, 0, 128, 0, 130, 1, 1, 1, 1];
+        let image = parse_rgbe(&rgbe_file(2, 2, &pixels)).unwrap();
+        assert_eq!((image.width, image.height), (2, 2));
+        let texels: Vec<[f32; 3]> = image.texels.iter().map(rgb).collect();
+        let green = [0.5 / 64.0, 128.5 / 64.0, 0.5 / 64.0];
+        assert_eq!(texels, [[128.5 / 128.0, 64.5 / 128.0, 0.5 / 128.0], [0.0; 3], green, green]);
+    }
+
+    #[test]
+    fn reads_run_length_scanlines() {
+        let mut pixels = vec![2, 2, 0, 8];
+        pixels.extend_from_slice(&[136, 128]); // R: a run of eight
+        pixels.extend_from_slice(&[8, 0, 16, 32, 48, 64, 80, 96, 112]); // G: eight literals
+        pixels.extend_from_slice(&[132, 0, 132, 255]); // B: two runs of four
+        pixels.extend_from_slice(&[136, 128]); // E
+        let image = parse_rgbe(&rgbe_file(8, 1, &pixels)).unwrap();
+        for (x, texel) in image.texels.iter().enumerate() {
+            let b = if x < 4 { 0.5 } else { 255.5 };
+            assert_eq!(rgb(texel), [128.5 / 256.0, (16 * x) as f32 / 256.0 + 0.5 / 256.0, b / 256.0]);
+        }
+    }
+
+    #[test]
+    fn rejects_bad_files() {
+        assert!(parse_rgbe(b"P6\n").is_err());
+        assert!(parse_rgbe(&rgbe_file(2, 1, &[128, 64, 0])).is_err());
+        assert!(parse_rgbe(&rgbe_file(2, 1, &[0; 4])).is_err());
+        let mut run_too_long = vec![2, 2, 0, 8, 137, 128];
+        run_too_long.extend_from_slice(&[0; 16]);
+        assert!(parse_rgbe(&rgbe_file(8, 1, &run_too_long)).is_err());
+        let huge = format!("#?RADIANCE\n\n-Y {} +X {}\n", usize::MAX, 2);
+        assert!(parse_rgbe(huge.as_bytes()).is_err());
+        // A wide line with almost no data behind it is refused before the
+        // image is allocated
+        let wide = rgbe_file(2147483648, 1, &[0; 32]);
+        assert_eq!(parse_rgbe(&wide).unwrap_err(), "resolution larger than the file can hold");
+
+        // An empty old-style repeat marker leaves the line where it was;
+        // a chain of them used to shift the run length out of range
+        let mut markers = vec![128, 64, 0, 129];
+        for _ in 0..9 {
+            markers.extend_from_slice(&[1, 1, 1, 0]);
+        }
+        assert_eq!(parse_rgbe(&rgbe_file(2, 1, &markers)).unwrap_err(), "empty run in scanline");
+    }
+}
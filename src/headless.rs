@@ -0,0 +1,269 @@
+use raylib::prelude::*;
+use crate::camera::{Focus, Projection};
+use crate::framebuffer::Framebuffer;
+use crate::renderer::{render, Integrator, RenderSettings, Scene, TraceContext};
+use crate::diorama;
+use crate::environment::Environment;
+use crate::tonemap::ToneMapping;
+use std::time::Instant;
+
+const USAGE: &str = "\
+Usage: textured-cubes-raytracer render [options]
+
+Renders the diorama to an image file without opening a window.
+
+Options:
+  -s, --scene <path>       Scene file to render (default: built-in zen garden)
+  -o, --output <path>      Output image: .png, .bmp, .jpg, .qoi or .ppm (default: render.png)
+  -w, --width <pixels>     Image width (default: 800)
+  -H, --height <pixels>    Image height (default: 600)
+      --eye <x,y,z>        Camera position (default: diorama camera)
+      --center <x,y,z>     Point the camera looks at (default: diorama camera)
+      --fov <degrees>      Vertical field of view (default: diorama camera)
+      --projection <name>  perspective, orthographic, panorama or fisheye (default: diorama camera)
+      --aperture <radius>  Lens radius for depth of field, 0 for a pinhole (default: diorama camera)
+      --focus <distance>   Focus distance, 'auto' or 'center' (default: diorama camera)
+  -i, --integrator <name>  Light transport: whitted or path (default: whitted)
+  -d, --depth <n>          Maximum ray recursion depth for whitted (default: 3)
+  -n, --samples <n>        Anti-aliasing samples per pixel (default: 16)
+      --tonemap <name>     Tone mapper: aces, reinhard or exposure (default: aces)
+      --exposure <value>   Exposure multiplier applied before tone mapping (default: 1)
+      --time <hours>       Time of day for a physical sky driven by a clock (default: scene)
+  -h, --help               Show this message
+";
+
+#[derive(Debug, Clone)]
+pub struct RenderOptions {
+    pub scene: Option<String>,
+    pub output: String,
+    pub width: u32,
+    pub height: u32,
+    pub eye: Option<Vector3>,
+    pub center: Option<Vector3>,
+    pub fov: Option<f32>,
+    pub projection: Option<Projection>,
+    pub aperture: Option<f32>,
+    pub focus: Option<Focus>,
+    pub integrator: Integrator,
+    pub max_depth: i32,
+    pub samples: u32,
+    pub tone_mapping: ToneMapping,
+    pub time: Option<f32>,
+}
+
+impl Default for RenderOptions {
+    fn default() -> Self {
+        RenderOptions {
+            scene: None,
+            output: "render.png".to_string(),
+            width: 800,
+            height: 600,
+            eye: None,
+            center: None,
+            fov: None,
+            projection: None,
+            aperture: None,
+            focus: None,
+            integrator: RenderSettings::default().integrator,
+            max_depth: RenderSettings::default().max_depth,
+            samples: 16,
+            tone_mapping: ToneMapping::default(),
+            time: None,
+        }
+    }
+}
+
+impl RenderOptions {
+    // Parse the flags that follow the `render` subcommand. Ok(None) means
+    // --help was requested and nothing should be rendered.
+    pub fn parse(args: &[String]) -> Result<Option<Self>, String> {
+        let mut options = RenderOptions::default();
+        let mut iter = args.iter();
+
+        while let Some(flag) = iter.next() {
+            let mut value = || {
+                iter.next()
+                    .map(String::as_str)
+                    .ok_or_else(|| format!("missing value for '{}'", flag))
+            };
+
+            match flag.as_str() {
+                "-s" | "--scene" => options.scene = Some(value()?.to_string()),
+                "-o" | "--output" => options.output = value()?.to_string(),
+                "-w" | "--width" => options.width = parse_count(flag, value()?)?,
+                "-H" | "--height" => options.height = parse_count(flag, value()?)?,
+                "--eye" => options.eye = Some(parse_vector(flag, value()?)?),
+                "--center" => options.center = Some(parse_vector(flag, value()?)?),
+                "--fov" => {
+                    let raw = value()?;
+                    options.fov = Some(
+                        raw.parse::<f32>()
+                            .ok()
+                            .filter(|f| *f > 0.0 && *f <= 360.0)
+                            .ok_or_else(|| format!("invalid value '{}' for '{}': expected degrees from 0 to 360", raw, flag))?
+                            .to_radians(),
+                    );
+                }
+                "--projection" => options.projection = Some(value()?.parse()?),
+                "--aperture" => {
+                    let raw = value()?;
+                    options.aperture = Some(
+                        raw.parse::<f32>()
+                            .ok()
+                            .filter(|r| r.is_finite() && *r >= 0.0)
+                            .ok_or_else(|| format!("invalid value '{}' for '{}': expected a number >= 0", raw, flag))?,
+                    );
+                }
+                "--focus" => {
+                    let raw = value()?;
+                    options.focus = Some(match raw {
+                        "auto" => Focus::Auto,
+                        "center" => Focus::Center,
+                        _ => Focus::Distance(raw.parse::<f32>().ok().filter(|d| d.is_finite() && *d > 0.0).ok_or_else(
+                            || format!("invalid value '{}' for '{}': expected a distance, 'auto' or 'center'", raw, flag),
+                        )?),
+                    });
+                }
+                "-i" | "--integrator" => options.integrator = value()?.parse()?,
+                "-d" | "--depth" => {
+                    let raw = value()?;
+                    options.max_depth = raw
+                        .parse::<i32>()
+                        .ok()
+                        .filter(|d| *d >= 1)
+                        .ok_or_else(|| format!("invalid value '{}' for '{}': expected an integer >= 1", raw, flag))?;
+                }
+                "-n" | "--samples" => options.samples = parse_count(flag, value()?)?,
+                "--tonemap" => options.tone_mapping.mapper = value()?.parse()?,
+                "--exposure" => {
+                    let raw = value()?;
+                    options.tone_mapping.exposure = raw
+                        .parse::<f32>()
+                        .ok()
+                        .filter(|e| e.is_finite() && *e > 0.0)
+                        .ok_or_else(|| format!("invalid value '{}' for '{}': expected a positive number", raw, flag))?;
+                }
+                "--time" => {
+                    let raw = value()?;
+                    options.time = Some(
+                        raw.parse::<f32>()
+                            .ok()
+                            .filter(|h| (0.0..=24.0).contains(h))
+                            .ok_or_else(|| format!("invalid value '{}' for '{}': expected hours from 0 to 24", raw, flag))?,
+                    );
+                }
+                "-h" | "--help" => return Ok(None),
+                other => return Err(format!("unknown option '{}'\n\n{}", other, USAGE)),
+            }
+        }
+
+        Ok(Some(options))
+    }
+}
+
+fn parse_count(flag: &str, raw: &str) -> Result<u32, String> {
+    raw.parse::<u32>()
+        .ok()
+        .filter(|v| *v > 0)
+        .ok_or_else(|| format!("invalid value '{}' for '{}': expected a positive integer", raw, flag))
+}
+
+fn parse_vector(flag: &str, raw: &str) -> Result<Vector3, String> {
+    let parts: Vec<f32> = raw
+        .split(',')
+        .map(|p| p.trim().parse::<f32>())
+        .collect::<Result<_, _>>()
+        .map_err(|_| format!("invalid value '{}' for '{}': expected x,y,z", raw, flag))?;
+
+    match parts.as_slice() {
+        [x, y, z] => Ok(Vector3::new(*x, *y, *z)),
+        _ => Err(format!("invalid value '{}' for '{}': expected x,y,z", raw, flag)),
+    }
+}
+
+// Entry point for `render`: builds the diorama, traces a single frame into a
+// framebuffer and writes it to disk. Never touches the window system, so it
+// runs on machines without a display.
+pub fn run(args: &[String]) -> Result<(), String> {
+    let Some(options) = RenderOptions::parse(args)? else {
+        print!("{}", USAGE);
+        return Ok(());
+    };
+
+    let diorama = diorama::load_or_default(options.scene.as_deref()).map_err(|e| e.to_string())?;
+    let mut camera = diorama.camera;
+    if let Some(eye) = options.eye {
+        camera.eye = eye;
+    }
+    if let Some(center) = options.center {
+        camera.center = center;
+    }
+    if let Some(projection) = options.projection {
+        // The scene's fov was only checked against its own projection; narrow
+        // it to what the new one allows, as the viewer does
+        camera.projection = projection;
+        camera.change_fov(0.0);
+    }
+    if let Some(fov) = options.fov {
+        if fov > camera.projection.max_fov() {
+            return Err(format!(
+                "--fov must be at most {} degrees for a {} camera",
+                camera.projection.max_fov().to_degrees().round(),
+                camera.projection
+            ));
+        }
+        camera.fov = fov;
+    }
+    if let Some(aperture) = options.aperture {
+        camera.aperture = aperture;
+    }
+    if let Some(focus) = options.focus {
+        camera.focus = focus;
+    }
+
+    let mut skybox = diorama.skybox;
+    if let Some(hours) = options.time {
+        if skybox.time_of_day().is_none() {
+            return Err("--time needs a physical skybox driven by a time of day".to_string());
+        }
+        skybox.set_time_of_day(hours);
+    }
+
+    let scene = Scene::new(diorama.objects);
+    let lights = diorama::lights_with_sun(&diorama.lights, &skybox);
+    let environment = Environment::new(skybox);
+    let settings = RenderSettings {
+        integrator: options.integrator,
+        max_depth: options.max_depth,
+        samples_per_pixel: options.samples,
+        ..RenderSettings::default()
+    };
+
+    let mut framebuffer = Framebuffer::new(options.width, options.height, Color::BLACK);
+    framebuffer.set_tone_mapping(options.tone_mapping);
+
+    let start = Instant::now();
+    let ctx = TraceContext {
+        scene: &scene,
+        lights: &lights,
+        textures: &diorama.textures,
+        environment: &environment,
+    };
+    render(&mut framebuffer, &ctx, &camera, &settings);
+    let elapsed = start.elapsed();
+
+    framebuffer
+        .save(&options.output)
+        .map_err(|e| format!("could not write '{}': {}", options.output, e))?;
+
+    println!(
+        "Rendered {}x{} ({} spp, {}) in {:.2}s -> {}",
+        options.width,
+        options.height,
+        options.samples,
+        options.integrator,
+        elapsed.as_secs_f32(),
+        options.output
+    );
+    Ok(())
+}
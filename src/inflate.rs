@@ -0,0 +1,279 @@
+// Minimal zlib/DEFLATE decoder (RFC 1950/1951), enough for the ZIP-compressed
+// blocks of OpenEXR files. Stored, fixed-Huffman and dynamic-Huffman blocks
+// are all supported; the Adler-32 checksum is not verified.
+
+// Base lengths and extra bits for length codes 257..285
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131, 163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0];
+// Base distances and extra bits for distance codes 0..29
+const DIST_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537, 2049, 3073, 4097, 6145,
+    8193, 12289, 16385, 24577,
+];
+const DIST_EXTRA: [u8; 30] = [0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13];
+// Order in which code length code lengths are stored
+const CODE_LENGTH_ORDER: [usize; 19] = [16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15];
+
+// Decompress a zlib stream. `expected` is only a capacity hint.
+pub fn zlib_decompress(data: &[u8], expected: usize) -> Result<Vec<u8>, String> {
+    if data.len() < 2 {
+        return Err("zlib stream too short".to_string());
+    }
+    let (cmf, flg) = (data[0], data[1]);
+    if cmf & 0x0f != 8 || (u16::from(cmf) << 8 | u16::from(flg)) % 31 != 0 {
+        return Err("not a zlib deflate stream".to_string());
+    }
+    if flg & 0x20 != 0 {
+        return Err("zlib preset dictionaries are not supported".to_string());
+    }
+    inflate(&data[2..], expected)
+}
+
+// Decompress raw DEFLATE data
+pub fn inflate(data: &[u8], expected: usize) -> Result<Vec<u8>, String> {
+    let mut bits = BitReader { data, position: 0 };
+    let mut out = Vec::with_capacity(expected);
+    loop {
+        let last = bits.read(1)? == 1;
+        match bits.read(2)? {
+            0 => {
+                bits.align();
+                let len = bits.read(16)? as usize;
+                let nlen = bits.read(16)? as usize;
+                if len != !nlen & 0xffff {
+                    return Err("corrupt stored deflate block".to_string());
+                }
+                let start = bits.position / 8;
+                let block = data.get(start..start + len).ok_or("truncated stored deflate block")?;
+                out.extend_from_slice(block);
+                bits.position += len * 8;
+            }
+            1 => {
+                let (literals, distances) = fixed_tables();
+                inflate_block(&mut bits, &mut out, &literals, &distances)?;
+            }
+            2 => {
+                let (literals, distances) = dynamic_tables(&mut bits)?;
+                inflate_block(&mut bits, &mut out, &literals, &distances)?;
+            }
+            _ => return Err("invalid deflate block type".to_string()),
+        }
+        if last {
+            return Ok(out);
+        }
+    }
+}
+
+// LSB-first bit stream over a byte slice
+struct BitReader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl BitReader<'_> {
+    fn bit(&mut self) -> Result<u32, String> {
+        let byte = self.data.get(self.position / 8).ok_or("truncated deflate stream")?;
+        let bit = (byte >> (self.position % 8)) & 1;
+        self.position += 1;
+        Ok(u32::from(bit))
+    }
+
+    fn read(&mut self, count: u32) -> Result<u32, String> {
+        let mut value = 0;
+        for i in 0..count {
+            value |= self.bit()? << i;
+        }
+        Ok(value)
+    }
+
+    fn align(&mut self) {
+        self.position = self.position.div_ceil(8) * 8;
+    }
+}
+
+// Canonical Huffman code: symbols sorted by code, and how many codes each
+// length has
+struct Huffman {
+    counts: [u16; 16],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Huffman {
+        let mut counts = [0u16; 16];
+        for &length in lengths {
+            counts[length as usize] += 1;
+        }
+        counts[0] = 0;
+        let mut offsets = [0u16; 16];
+        for length in 1..16 {
+            offsets[length] = offsets[length - 1] + counts[length - 1];
+        }
+        let mut symbols = vec![0u16; lengths.len()];
+        for (symbol, &length) in lengths.iter().enumerate() {
+            if length > 0 {
+                symbols[offsets[length as usize] as usize] = symbol as u16;
+                offsets[length as usize] += 1;
+            }
+        }
+        Huffman { counts, symbols }
+    }
+
+    // Read one symbol, walking the code one bit at a time
+    fn decode(&self, bits: &mut BitReader) -> Result<u16, String> {
+        let mut code: i32 = 0;
+        let mut first: i32 = 0;
+        let mut index: i32 = 0;
+        for length in 1..16 {
+            code |= bits.bit()? as i32;
+            let count = i32::from(self.counts[length]);
+            if code - first < count {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err("invalid Huffman code in deflate stream".to_string())
+    }
+}
+
+fn fixed_tables() -> (Huffman, Huffman) {
+    let mut lengths = [0u8; 288];
+    lengths[..144].fill(8);
+    lengths[144..256].fill(9);
+    lengths[256..280].fill(7);
+    lengths[280..].fill(8);
+    (Huffman::new(&lengths), Huffman::new(&[5u8; 30]))
+}
+
+fn dynamic_tables(bits: &mut BitReader) -> Result<(Huffman, Huffman), String> {
+    let literal_count = bits.read(5)? as usize + 257;
+    let distance_count = bits.read(5)? as usize + 1;
+    let code_length_count = bits.read(4)? as usize + 4;
+
+    let mut code_lengths = [0u8; 19];
+    for &index in &CODE_LENGTH_ORDER[..code_length_count] {
+        code_lengths[index] = bits.read(3)? as u8;
+    }
+    let code_length_code = Huffman::new(&code_lengths);
+
+    let mut lengths = vec![0u8; literal_count + distance_count];
+    let mut i = 0;
+    while i < lengths.len() {
+        let symbol = code_length_code.decode(bits)?;
+        let (value, repeat) = match symbol {
+            0..=15 => (symbol as u8, 1),
+            16 => {
+                let previous = *lengths[..i].last().ok_or("deflate length repeat with nothing before it")?;
+                (previous, 3 + bits.read(2)? as usize)
+            }
+            17 => (0, 3 + bits.read(3)? as usize),
+            _ => (0, 11 + bits.read(7)? as usize),
+        };
+        if i + repeat > lengths.len() {
+            return Err("too many code lengths in deflate stream".to_string());
+        }
+        lengths[i..i + repeat].fill(value);
+        i += repeat;
+    }
+
+    Ok((Huffman::new(&lengths[..literal_count]), Huffman::new(&lengths[literal_count..])))
+}
+
+fn inflate_block(bits: &mut BitReader, out: &mut Vec<u8>, literals: &Huffman, distances: &Huffman) -> Result<(), String> {
+    loop {
+        let symbol = literals.decode(bits)? as usize;
+        match symbol {
+            0..=255 => out.push(symbol as u8),
+            256 => return Ok(()),
+            257..=285 => {
+                let index = symbol - 257;
+                let length = LENGTH_BASE[index] as usize + bits.read(u32::from(LENGTH_EXTRA[index]))? as usize;
+                let code = distances.decode(bits)? as usize;
+                if code >= DIST_BASE.len() {
+                    return Err("invalid distance code in deflate stream".to_string());
+                }
+                let distance = DIST_BASE[code] as usize + bits.read(u32::from(DIST_EXTRA[code]))? as usize;
+                if distance > out.len() {
+                    return Err("deflate distance reaches before the start of the output".to_string());
+                }
+                // Byte by byte: the copy may overlap what it is producing
+                let start = out.len() - distance;
+                for k in 0..length {
+                    out.push(out[start + k]);
+                }
+            }
+            _ => return Err("invalid literal/length code in deflate stream".to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Streams below were produced by zlib (raw DEFLATE, window bits -15)
+    const TEXT: &[u8] = b"abcabcabcabc hello hello";
+    // Z_FIXED strategy: a single fixed-Huffman block with back-references
+    const FIXED: [u8; 15] = [0x4b, 0x4c, 0x4a, 0x4e, 0x84, 0x21, 0x85, 0x8c, 0xd4, 0x9c, 0x9c, 0x7c, 0x08, 0x09, 0x00];
+
+    // 800 bytes from a skewed alphabet, which zlib packs into a dynamic block
+    fn skewed_text() -> Vec<u8> {
+        (0..800usize).map(|i| b"aaaabbcd"[(i * i + i / 5) % 8]).collect()
+    }
+    const DYNAMIC: [u8; 39] = [
+        0xed, 0xca, 0x81, 0x09, 0x00, 0x30, 0x0c, 0x02, 0xc1, 0x59, 0x5f, 0xdd, 0x7f, 0x86, 0xda, 0xd0, 0x2d, 0x1a, 0x50,
+        0x41, 0x38, 0x10, 0x4c, 0x7d, 0x2b, 0x14, 0xa9, 0x2b, 0xf7, 0x98, 0x38, 0xd0, 0x3c, 0xb3, 0x6e, 0xdd, 0x5f, 0xee,
+        0x00,
+    ];
+
+    #[test]
+    fn stored_block() {
+        let data = [0x01, 0x06, 0x00, 0xf9, 0xff, b's', b't', b'o', b'r', b'e', b'd'];
+        assert_eq!(inflate(&data, 0).unwrap(), b"stored");
+    }
+
+    #[test]
+    fn fixed_block() {
+        assert_eq!(inflate(&FIXED, TEXT.len()).unwrap(), TEXT);
+    }
+
+    #[test]
+    fn dynamic_block() {
+        assert_eq!(DYNAMIC[0] >> 1 & 3, 2, "test data should start with a dynamic block");
+        assert_eq!(inflate(&DYNAMIC, 800).unwrap(), skewed_text());
+    }
+
+    #[test]
+    fn stored_then_fixed_block() {
+        // A non-final stored block ends on a byte boundary, so the fixed block
+        // can follow it directly
+        let mut data = vec![0x00, 0x03, 0x00, 0xfc, 0xff, b'x', b'y', b'z'];
+        data.extend_from_slice(&FIXED);
+        let mut expected = b"xyz".to_vec();
+        expected.extend_from_slice(TEXT);
+        assert_eq!(inflate(&data, 0).unwrap(), expected);
+    }
+
+    #[test]
+    fn zlib_stream() {
+        let mut data = vec![0x78, 0xda];
+        data.extend_from_slice(&FIXED);
+        data.extend_from_slice(&[0x70, 0x12, 0x09, 0x01]);
+        assert_eq!(zlib_decompress(&data, TEXT.len()).unwrap(), TEXT);
+        assert!(zlib_decompress(&[0x78, 0xdb], 0).is_err());
+    }
+
+    #[test]
+    fn corrupt_data_is_an_error() {
+        assert!(inflate(&[], 0).is_err());
+        assert!(inflate(&FIXED[..6], 0).is_err());
+        // Stored block whose length check does not match
+        assert!(inflate(&[0x01, 0x06, 0x00, 0x00, 0x00, b's'], 0).is_err());
+        // Reserved block type 3
+        assert!(inflate(&[0x07], 0).is_err());
+    }
+}
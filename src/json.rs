@@ -0,0 +1,388 @@
+use std::fmt;
+
+// Minimal JSON reader for scene files. Every value remembers the line and
+// column where it starts so the loader can point at the offending entry.
+
+#[derive(Debug, Clone)]
+pub enum JsonKind {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<JsonValue>),
+    // Keys keep their source order and position for error reporting
+    Object(Vec<(JsonKey, JsonValue)>),
+}
+
+#[derive(Debug, Clone)]
+pub struct JsonKey {
+    pub name: String,
+    pub line: usize,
+    pub column: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct JsonValue {
+    pub kind: JsonKind,
+    pub line: usize,
+    pub column: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct JsonError {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for JsonError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.column, self.message)
+    }
+}
+
+impl JsonValue {
+    // What the value is, for "expected ..., found ..." messages. Scene files
+    // have no boolean settings, so a boolean is named by its value.
+    pub fn type_name(&self) -> &'static str {
+        match self.kind {
+            JsonKind::Null => "null",
+            JsonKind::Bool(true) => "true",
+            JsonKind::Bool(false) => "false",
+            JsonKind::Number(_) => "a number",
+            JsonKind::String(_) => "a string",
+            JsonKind::Array(_) => "an array",
+            JsonKind::Object(_) => "an object",
+        }
+    }
+
+    pub fn error(&self, message: impl Into<String>) -> JsonError {
+        JsonError {
+            line: self.line,
+            column: self.column,
+            message: message.into(),
+        }
+    }
+}
+
+pub fn parse(source: &str) -> Result<JsonValue, JsonError> {
+    let mut parser = Parser {
+        chars: source.chars().collect(),
+        pos: 0,
+        line: 1,
+        column: 1,
+    };
+    parser.skip_whitespace();
+    let value = parser.parse_value()?;
+    parser.skip_whitespace();
+    if parser.pos < parser.chars.len() {
+        return Err(parser.error("unexpected trailing characters after the top-level value"));
+    }
+    Ok(value)
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+    line: usize,
+    column: usize,
+}
+
+impl Parser {
+    fn error(&self, message: impl Into<String>) -> JsonError {
+        JsonError {
+            line: self.line,
+            column: self.column,
+            message: message.into(),
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += 1;
+        if c == '\n' {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
+        Some(c)
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), JsonError> {
+        match self.peek() {
+            Some(c) if c == expected => {
+                self.bump();
+                Ok(())
+            }
+            Some(c) => Err(self.error(format!("expected '{}' but found '{}'", expected, c))),
+            None => Err(self.error(format!("expected '{}' but reached the end of the file", expected))),
+        }
+    }
+
+    // Whitespace plus `//` line comments, which JSON lacks but hand-written
+    // scene files badly need.
+    fn skip_whitespace(&mut self) {
+        while let Some(c) = self.peek() {
+            if c.is_whitespace() {
+                self.bump();
+            } else if c == '/' && self.chars.get(self.pos + 1) == Some(&'/') {
+                while let Some(c) = self.peek() {
+                    if c == '\n' {
+                        break;
+                    }
+                    self.bump();
+                }
+            } else {
+                break;
+            }
+        }
+    }
+
+    fn parse_value(&mut self) -> Result<JsonValue, JsonError> {
+        let (line, column) = (self.line, self.column);
+        let kind = match self.peek() {
+            Some('{') => self.parse_object()?,
+            Some('[') => self.parse_array()?,
+            Some('"') => JsonKind::String(self.parse_string()?),
+            Some(c) if c == '-' || c.is_ascii_digit() => JsonKind::Number(self.parse_number()?),
+            Some(c) if c.is_ascii_alphabetic() => {
+                let word = self.parse_word();
+                match word.as_str() {
+                    "true" => JsonKind::Bool(true),
+                    "false" => JsonKind::Bool(false),
+                    "null" => JsonKind::Null,
+                    _ => {
+                        return Err(JsonError {
+                            line,
+                            column,
+                            message: format!("unexpected word '{}' (strings must be quoted)", word),
+                        });
+                    }
+                }
+            }
+            Some(c) => return Err(self.error(format!("unexpected character '{}'", c))),
+            None => return Err(self.error("unexpected end of file")),
+        };
+        Ok(JsonValue { kind, line, column })
+    }
+
+    fn parse_word(&mut self) -> String {
+        let mut word = String::new();
+        while let Some(c) = self.peek() {
+            if !c.is_ascii_alphanumeric() && c != '_' {
+                break;
+            }
+            word.push(c);
+            self.bump();
+        }
+        word
+    }
+
+    fn parse_number(&mut self) -> Result<f64, JsonError> {
+        let (line, column) = (self.line, self.column);
+        let mut text = String::new();
+        while let Some(c) = self.peek() {
+            if c.is_ascii_digit() || matches!(c, '-' | '+' | '.' | 'e' | 'E') {
+                text.push(c);
+                self.bump();
+            } else {
+                break;
+            }
+        }
+        text.parse::<f64>().map_err(|_| JsonError {
+            line,
+            column,
+            message: format!("invalid number '{}'", text),
+        })
+    }
+
+    fn parse_string(&mut self) -> Result<String, JsonError> {
+        self.expect('"')?;
+        let mut out = String::new();
+        loop {
+            match self.bump() {
+                Some('"') => return Ok(out),
+                Some('\\') => {
+                    let escaped = match self.bump() {
+                        Some('"') => '"',
+                        Some('\\') => '\\',
+                        Some('/') => '/',
+                        Some('n') => '\n',
+                        Some('t') => '\t',
+                        Some('r') => '\r',
+                        Some('b') => '\u{8}',
+                        Some('f') => '\u{c}',
+                        Some('u') => {
+                            let mut code = 0u32;
+                            for _ in 0..4 {
+                                let digit = self
+                                    .bump()
+                                    .and_then(|c| c.to_digit(16))
+                                    .ok_or_else(|| self.error("invalid \\u escape"))?;
+                                code = code * 16 + digit;
+                            }
+                            char::from_u32(code).unwrap_or('\u{fffd}')
+                        }
+                        Some(c) => return Err(self.error(format!("invalid escape '\\{}'", c))),
+                        None => return Err(self.error("unterminated string")),
+                    };
+                    out.push(escaped);
+                }
+                Some('\n') | None => return Err(self.error("unterminated string")),
+                Some(c) => out.push(c),
+            }
+        }
+    }
+
+    fn parse_array(&mut self) -> Result<JsonKind, JsonError> {
+        self.expect('[')?;
+        let mut items = Vec::new();
+        self.skip_whitespace();
+        if self.peek() == Some(']') {
+            self.bump();
+            return Ok(JsonKind::Array(items));
+        }
+        loop {
+            self.skip_whitespace();
+            items.push(self.parse_value()?);
+            self.skip_whitespace();
+            match self.peek() {
+                Some(',') => {
+                    self.bump();
+                    // Allow a trailing comma before the closing bracket
+                    self.skip_whitespace();
+                    if self.peek() == Some(']') {
+                        self.bump();
+                        return Ok(JsonKind::Array(items));
+                    }
+                }
+                Some(']') => {
+                    self.bump();
+                    return Ok(JsonKind::Array(items));
+                }
+                _ => return Err(self.error("expected ',' or ']' in array")),
+            }
+        }
+    }
+
+    fn parse_object(&mut self) -> Result<JsonKind, JsonError> {
+        self.expect('{')?;
+        let mut entries: Vec<(JsonKey, JsonValue)> = Vec::new();
+        self.skip_whitespace();
+        if self.peek() == Some('}') {
+            self.bump();
+            return Ok(JsonKind::Object(entries));
+        }
+        loop {
+            self.skip_whitespace();
+            let (line, column) = (self.line, self.column);
+            if self.peek() != Some('"') {
+                return Err(self.error("expected a quoted key"));
+            }
+            let name = self.parse_string()?;
+            if entries.iter().any(|(k, _)| k.name == name) {
+                return Err(JsonError {
+                    line,
+                    column,
+                    message: format!("duplicate key '{}'", name),
+                });
+            }
+            self.skip_whitespace();
+            self.expect(':')?;
+            self.skip_whitespace();
+            let value = self.parse_value()?;
+            entries.push((JsonKey { name, line, column }, value));
+            self.skip_whitespace();
+            match self.peek() {
+                Some(',') => {
+                    self.bump();
+                    self.skip_whitespace();
+                    if self.peek() == Some('}') {
+                        self.bump();
+                        return Ok(JsonKind::Object(entries));
+                    }
+                }
+                Some('}') => {
+                    self.bump();
+                    return Ok(JsonKind::Object(entries));
+                }
+                _ => return Err(self.error("expected ',' or '}' in object")),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entries(value: &JsonValue) -> &[(JsonKey, JsonValue)] {
+        match &value.kind {
+            JsonKind::Object(entries) => entries,
+            _ => panic!("expected an object, found {}", value.type_name()),
+        }
+    }
+
+    #[test]
+    fn comments_and_trailing_commas() {
+        let source = "// scene\n{\n  \"a\": [1, 2, 3,], // three\n  \"b\": { \"c\": true, },\n}\n";
+        let root = parse(source).unwrap();
+        let fields = entries(&root);
+        assert_eq!(fields.len(), 2);
+
+        let (key, value) = &fields[0];
+        assert_eq!((key.name.as_str(), key.line, key.column), ("a", 3, 3));
+        match &value.kind {
+            JsonKind::Array(items) => {
+                let numbers: Vec<f64> = items
+                    .iter()
+                    .map(|item| match item.kind {
+                        JsonKind::Number(n) => n,
+                        _ => panic!("expected a number"),
+                    })
+                    .collect();
+                assert_eq!(numbers, [1.0, 2.0, 3.0]);
+            }
+            _ => panic!("expected an array"),
+        }
+
+        let (key, value) = &fields[1];
+        assert_eq!((key.name.as_str(), key.line, key.column), ("b", 4, 3));
+        assert!(matches!(entries(value)[0].1.kind, JsonKind::Bool(true)));
+    }
+
+    #[test]
+    fn values_remember_where_they_start() {
+        let root = parse("{\n  \"s\": \"a\\u00e9\\n\",\n  \"n\": -1.5e2\n}").unwrap();
+        let fields = entries(&root);
+        let (_, s) = &fields[0];
+        assert!(matches!(&s.kind, JsonKind::String(text) if text == "a\u{e9}\n"));
+        assert_eq!((s.line, s.column), (2, 8));
+        let (_, n) = &fields[1];
+        assert!(matches!(n.kind, JsonKind::Number(x) if x == -150.0));
+        assert_eq!((n.line, n.column), (3, 8));
+    }
+
+    #[test]
+    fn errors_point_at_the_problem() {
+        let error = parse("{\n  \"a\": 1\n  \"b\": 2\n}").unwrap_err();
+        assert_eq!(error.to_string(), "3:3: expected ',' or '}' in object");
+
+        let error = parse("{ \"a\": 1, \"a\": 2 }").unwrap_err();
+        assert_eq!(error.to_string(), "1:11: duplicate key 'a'");
+
+        let error = parse("[1, grass]").unwrap_err();
+        assert_eq!(error.to_string(), "1:5: unexpected word 'grass' (strings must be quoted)");
+
+        let error = parse("\"open").unwrap_err();
+        assert_eq!(error.to_string(), "1:6: unterminated string");
+
+        let error = parse("{} {}").unwrap_err();
+        assert_eq!(error.to_string(), "1:4: unexpected trailing characters after the top-level value");
+    }
+}
@@ -1,10 +1,109 @@
 use raylib::prelude::*;
-use crate::color::Color;
+use crate::color::{Color, LinearColor};
+use crate::sampling::{concentric_disk, tangent_frame};
+use std::f32::consts::PI;
 
+// Shadow rays per shading point for area lights unless a scene asks otherwise
+pub const DEFAULT_AREA_SAMPLES: u32 = 16;
+
+// Closest distance used by inverse-square falloff, so surfaces touching a
+// light do not receive infinite irradiance
+const MIN_FALLOFF_DISTANCE: f32 = 0.01;
+
+// Units: `Light::intensity` is a radiant intensity (W/sr) for point, spot and
+// area lights, measured along the normal for flat emitters, and an irradiance
+// (W/m²) for directional lights. Multiplied by the linear light color it gives
+// the radiance reaching a surface once attenuation is applied, so one value
+// means the same thing in every scene.
+
+// How irradiance from a positional light decreases with distance
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Attenuation {
+    // Same irradiance at any distance, the renderer's original behaviour
+    None,
+    // Physically correct 1 / d²
+    InverseSquare,
+    // Classic 1 / (constant + linear d + quadratic d²)
+    Polynomial { constant: f32, linear: f32, quadratic: f32 },
+    // Inverse square windowed to reach exactly zero at `range`, so a light
+    // can be given a hard limit without a visible edge
+    Range { range: f32 },
+}
+
+impl Attenuation {
+    pub fn factor(&self, distance: f32) -> f32 {
+        let d2 = distance.max(MIN_FALLOFF_DISTANCE).powi(2);
+        match *self {
+            Attenuation::None => 1.0,
+            Attenuation::InverseSquare => 1.0 / d2,
+            Attenuation::Polynomial { constant, linear, quadratic } => {
+                let denominator = constant + linear * distance + quadratic * distance * distance;
+                if denominator > 0.0 { 1.0 / denominator } else { 0.0 }
+            }
+            Attenuation::Range { range } => {
+                let window = (1.0 - (distance / range).powi(4)).clamp(0.0, 1.0);
+                window * window / d2
+            }
+        }
+    }
+}
+
+// How a light emits: from a position in every direction, from infinitely far
+// away along one direction, or from a position inside a cone
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LightKind {
+    Omni,
+    // `direction` is where the light travels; `angular_radius` (radians) is
+    // the apparent size of the source, 0 for perfectly parallel light
+    Directional { direction: Vector3, angular_radius: f32 },
+    // Full intensity inside the inner cone, smoothly fading to zero at the
+    // outer one. Angles are stored as cosines of the half-angles.
+    Spot { direction: Vector3, cos_inner: f32, cos_outer: f32 },
+}
+
+// One shadow-ray target on a light as seen from a shading point
+#[derive(Debug, Clone, Copy)]
+pub struct LightSample {
+    // Unit vector from the shading point towards the light
+    pub direction: Vector3,
+    // Distance to the sampled point, infinite for directional lights
+    pub distance: f32,
+    // Fraction of the intensity arriving along this ray: distance
+    // attenuation, spot cone falloff and emitter foreshortening
+    pub weight: f32,
+    // Solid-angle density of `direction`, for lights that rays can also hit
+    // (see `Light::hit`); `None` for lights only reachable by sampling
+    pub pdf: Option<f32>,
+}
+
+
+// Emitting surface of a light, centred on `Light::position`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LightShape {
+    // Infinitely small: one shadow ray, hard shadows
+    Point,
+    // Parallelogram spanned by the two full edge vectors. It emits towards
+    // `u x v` only.
+    Rect { u: Vector3, v: Vector3 },
+    // Flat disk emitting towards `normal` only
+    Disk { normal: Vector3, radius: f32 },
+    // Ball emitting in every direction
+    Sphere { radius: f32 },
+}
+
+#[derive(Debug, Clone)]
 pub struct Light {
     pub position: Vector3,
     pub color: Color,
+    // See the note on units above `Attenuation`
     pub intensity: f32,
+    pub kind: LightKind,
+    // Ignored by directional lights
+    pub attenuation: Attenuation,
+    // Ignored by directional lights
+    pub shape: LightShape,
+    // Shadow rays traced per shading point (ignored for hard-edged lights)
+    pub samples: u32,
 }
 
 impl Light {
@@ -13,6 +112,396 @@ impl Light {
             position,
             color,
             intensity,
+            kind: LightKind::Omni,
+            attenuation: Attenuation::InverseSquare,
+            shape: LightShape::Point,
+            samples: 1,
         }
     }
-}
\ No newline at end of file
+
+    // Parallel light such as the sun. `direction` is where the light travels.
+    pub fn directional(direction: Vector3, color: Color, intensity: f32) -> Self {
+        let mut light = Light::new(Vector3::zero(), color, intensity);
+        light.kind = LightKind::Directional {
+            direction: direction.normalized(),
+            angular_radius: 0.0,
+        };
+        light
+    }
+
+    // Apparent radius of a directional light's source, for soft shadows
+    pub fn with_angular_radius(mut self, radians: f32) -> Self {
+        if let LightKind::Directional { angular_radius, .. } = &mut self.kind {
+            *angular_radius = radians.max(0.0);
+            self.samples = DEFAULT_AREA_SAMPLES;
+        }
+        self
+    }
+
+    // Turn the light into a spot aimed along `direction`. Angles are
+    // half-angles in radians; `outer` is clamped to be at least `inner`.
+    pub fn with_spot(mut self, direction: Vector3, inner: f32, outer: f32) -> Self {
+        self.kind = LightKind::Spot {
+            direction: direction.normalized(),
+            cos_inner: inner.cos(),
+            cos_outer: outer.max(inner).cos(),
+        };
+        self
+    }
+
+    pub fn with_rect(mut self, u: Vector3, v: Vector3) -> Self {
+        self.shape = LightShape::Rect { u, v };
+        self.samples = DEFAULT_AREA_SAMPLES;
+        self
+    }
+
+    pub fn with_disk(mut self, normal: Vector3, radius: f32) -> Self {
+        self.shape = LightShape::Disk { normal: normal.normalized(), radius };
+        self.samples = DEFAULT_AREA_SAMPLES;
+        self
+    }
+
+    pub fn with_sphere(mut self, radius: f32) -> Self {
+        self.shape = LightShape::Sphere { radius };
+        self.samples = DEFAULT_AREA_SAMPLES;
+        self
+    }
+
+    pub fn with_attenuation(mut self, attenuation: Attenuation) -> Self {
+        self.attenuation = attenuation;
+        self
+    }
+
+    pub fn with_samples(mut self, samples: u32) -> Self {
+        self.samples = samples.max(1);
+        self
+    }
+
+    // How many shadow rays to trace towards this light
+    pub fn shadow_samples(&self) -> u32 {
+        match (self.kind, self.shape) {
+            (LightKind::Directional { angular_radius, .. }, _) if angular_radius > 0.0 => self.samples,
+            (LightKind::Directional { .. }, _) | (_, LightShape::Point) => 1,
+            _ => self.samples,
+        }
+    }
+
+    // Shadow ray towards the light for the stratified sample `(s, t)` in
+    // [0, 1)^2. `None` when this part of the light cannot reach `from`.
+    pub fn sample(&self, from: Vector3, s: f32, t: f32) -> Option<LightSample> {
+        if let LightKind::Directional { direction, angular_radius } = self.kind {
+            // Jitter inside the cone the source covers, then aim at it
+            let spread = disk_offset(direction, angular_radius.tan(), s, t);
+            return Some(LightSample {
+                direction: (spread - direction).normalized(),
+                distance: f32::INFINITY,
+                weight: 1.0,
+                pdf: self.is_hittable().then(|| 1.0 / self.area()),
+            });
+        }
+
+        let falloff = self.cone_falloff(from);
+        if falloff <= 0.0 {
+            return None;
+        }
+        let (point, cos_emitter) = self.sample_point(from, s, t)?;
+        let to_light = point - from;
+        let distance = to_light.length();
+        if distance <= 0.0 {
+            return None;
+        }
+        Some(LightSample {
+            direction: to_light / distance,
+            distance,
+            weight: falloff * cos_emitter * self.attenuation.factor(distance),
+            pdf: self.is_hittable().then(|| distance * distance / (self.area() * cos_emitter)),
+        })
+    }
+
+    // Whether rays can run into the light's surface. Only then can a path
+    // tracer weigh sampling the light against sampling the BSDF; that also
+    // needs physical falloff, or the two estimates would disagree.
+    pub fn is_hittable(&self) -> bool {
+        match self.kind {
+            LightKind::Directional { angular_radius, .. } => angular_radius > 0.0,
+            _ => self.shape != LightShape::Point && self.attenuation == Attenuation::InverseSquare,
+        }
+    }
+
+    // Emitting area; for directional lights the solid angle of the source.
+    // Spheres count their silhouette, so that `intensity` is the same seen
+    // from any side.
+    fn area(&self) -> f32 {
+        if let LightKind::Directional { angular_radius, .. } = self.kind {
+            return 2.0 * PI * (1.0 - angular_radius.cos());
+        }
+        match self.shape {
+            LightShape::Point => 0.0,
+            LightShape::Rect { u, v } => u.cross(v).length(),
+            LightShape::Disk { radius, .. } | LightShape::Sphere { radius } => PI * radius * radius,
+        }
+    }
+
+    // Radiance leaving a hittable light towards `from`. Spread over the
+    // area, the intensity gives back the same light `sample` delivers.
+    pub fn emitted(&self, from: Vector3) -> LinearColor {
+        self.color.to_linear() * (self.intensity * self.cone_falloff(from) / self.area())
+    }
+
+    // Whether a ray from `from` meets the light closer than `max_distance`,
+    // and if so the solid-angle density `sample` would have picked that
+    // direction with. Directional lights are met by rays that escape the scene.
+    pub fn hit(&self, from: Vector3, direction: Vector3, max_distance: f32) -> Option<f32> {
+        if !self.is_hittable() {
+            return None;
+        }
+        if let LightKind::Directional { direction: travel, angular_radius } = self.kind {
+            let inside = max_distance == f32::INFINITY && -direction.dot(travel) >= angular_radius.cos();
+            return inside.then(|| 1.0 / self.area());
+        }
+
+        let (cos_emitter, distance) = match self.shape {
+            LightShape::Point => return None,
+            LightShape::Rect { u, v } => {
+                let normal = u.cross(v).normalized();
+                let (cos_emitter, distance, point) = hit_plane(self.position, normal, from, direction)?;
+                // Coordinates along the (possibly skewed) edges via the dual basis
+                let offset = point - self.position;
+                let a = offset.dot(v.cross(normal)) / u.dot(v.cross(normal));
+                let b = offset.dot(normal.cross(u)) / v.dot(normal.cross(u));
+                if a.abs() > 0.5 || b.abs() > 0.5 {
+                    return None;
+                }
+                (cos_emitter, distance)
+            }
+            LightShape::Disk { normal, radius } => {
+                let (cos_emitter, distance, point) = hit_plane(self.position, normal, from, direction)?;
+                if (point - self.position).length() > radius {
+                    return None;
+                }
+                (cos_emitter, distance)
+            }
+            LightShape::Sphere { radius } => {
+                // Near side of the ball, which `sample` picks by direction
+                let offset = self.position - from;
+                let along = offset.dot(direction);
+                let miss2 = offset.dot(offset) - along * along;
+                if along <= 0.0 || miss2 >= radius * radius || offset.length() <= radius {
+                    return None;
+                }
+                let distance = along - (radius * radius - miss2).sqrt();
+                let (_, solid_angle) = sphere_cone(radius, offset.length());
+                return (distance < max_distance).then(|| 1.0 / solid_angle);
+            }
+        };
+        (distance < max_distance).then(|| distance * distance / (self.area() * cos_emitter))
+    }
+
+    // Spot attenuation for a point lit by this light, 1 for other kinds.
+    // Smoothstep between the outer and inner cones.
+    fn cone_falloff(&self, from: Vector3) -> f32 {
+        let LightKind::Spot { direction, cos_inner, cos_outer } = self.kind else {
+            return 1.0;
+        };
+        let cos_angle = (from - self.position).normalized().dot(direction);
+        if cos_inner <= cos_outer {
+            return if cos_angle >= cos_inner { 1.0 } else { 0.0 };
+        }
+        let x = ((cos_angle - cos_outer) / (cos_inner - cos_outer)).clamp(0.0, 1.0);
+        x * x * (3.0 - 2.0 * x)
+    }
+
+    // Point on the light's shape for the sample `(s, t)` and its
+    // foreshortening: the cosine between the emitter normal and `from` (1
+    // for points), or for spheres, which are sampled by direction, the
+    // factor that gives each sample an equal share of the solid angle.
+    // Returns `None` when that point faces away from `from`.
+    fn sample_point(&self, from: Vector3, s: f32, t: f32) -> Option<(Vector3, f32)> {
+        match self.shape {
+            LightShape::Point => Some((self.position, 1.0)),
+            LightShape::Rect { u, v } => {
+                let point = self.position + u * (s - 0.5) + v * (t - 0.5);
+                facing(u.cross(v).normalized(), point, from)
+            }
+            LightShape::Disk { normal, radius } => {
+                let point = self.position + disk_offset(normal, radius, s, t);
+                facing(normal, point, from)
+            }
+            LightShape::Sphere { radius } => {
+                // Uniform over the cone of directions the ball covers, aimed
+                // at its near side
+                let axis = self.position - from;
+                let distance = axis.length();
+                if distance <= radius {
+                    return Some((self.position, 1.0));
+                }
+                let (cos_max, solid_angle) = sphere_cone(radius, distance);
+                let cos_theta = 1.0 - s * (1.0 - cos_max);
+                let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+                let phi = 2.0 * PI * t;
+                let (tangent, bitangent) = tangent_frame(axis / distance);
+                let direction = tangent * (sin_theta * phi.cos())
+                    + bitangent * (sin_theta * phi.sin())
+                    + axis / distance * cos_theta;
+                let along = distance * cos_theta
+                    - (radius * radius - distance * distance * sin_theta * sin_theta).max(0.0).sqrt();
+                Some((from + direction * along, along * along * solid_angle / self.area()))
+            }
+        }
+    }
+}
+
+// Front-side hit of a ray with the plane through `center`: the cosine to the
+// normal, the distance and the point
+fn hit_plane(center: Vector3, normal: Vector3, from: Vector3, direction: Vector3) -> Option<(f32, f32, Vector3)> {
+    let cos_emitter = -normal.dot(direction);
+    if cos_emitter <= 1e-6 {
+        return None;
+    }
+    let distance = (from - center).dot(normal) / cos_emitter;
+    (distance > 0.0).then(|| (cos_emitter, distance, from + direction * distance))
+}
+
+// Cosine of the half-angle of the cone a ball of `radius` fills seen from
+// `distance` away, and that cone's solid angle
+fn sphere_cone(radius: f32, distance: f32) -> (f32, f32) {
+    let sin2 = (radius / distance).powi(2).min(1.0);
+    let cos_max = (1.0 - sin2).sqrt();
+    // 1 - cos_max, without the cancellation of small, distant spheres
+    (cos_max, 2.0 * PI * sin2 / (1.0 + cos_max))
+}
+
+// A point on a one-sided emitter with its cosine towards `from`, if lit
+fn facing(normal: Vector3, point: Vector3, from: Vector3) -> Option<(Vector3, f32)> {
+    let cos_emitter = normal.dot((from - point).normalized());
+    (cos_emitter > 0.0).then_some((point, cos_emitter))
+}
+
+// Offset from the centre of a disk with the given normal and radius
+fn disk_offset(normal: Vector3, radius: f32, s: f32, t: f32) -> Vector3 {
+    let (tangent, bitangent) = tangent_frame(normal);
+    let (x, y) = concentric_disk(s, t);
+    (tangent * x + bitangent * y) * radius
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn white_light(position: Vector3) -> Light {
+        Light::new(position, Color::new(255, 255, 255), 1.0)
+    }
+
+    // Weights of an n x n grid of samples, averaged and spread over the
+    // light's area. Each sample also has to agree with `hit` on its density.
+    fn sampled_solid_angle(light: &Light, from: Vector3) -> f32 {
+        let n = 128;
+        let mut sum = 0.0;
+        for i in 0..n {
+            for j in 0..n {
+                let (s, t) = ((i as f32 + 0.5) / n as f32, (j as f32 + 0.5) / n as f32);
+                let Some(sample) = light.sample(from, s, t) else {
+                    continue;
+                };
+                let hit = light.hit(from, sample.direction, f32::INFINITY);
+                let (pdf, hit) = (sample.pdf.unwrap(), hit.unwrap());
+                assert!((pdf - hit).abs() <= 1e-3 * pdf, "sampled pdf {pdf}, hit pdf {hit}");
+                sum += sample.weight;
+            }
+        }
+        sum / (n * n) as f32 * light.area()
+    }
+
+    #[test]
+    fn shape_samples_cover_their_solid_angle() {
+        let center = Vector3::new(0.0, 3.0, 0.0);
+        let below = |height: f32| center - Vector3::new(0.0, height, 0.0);
+        let down = Vector3::new(0.0, -1.0, 0.0);
+
+        // Rectangle of sides a and b seen from height h above its centre
+        let (a, b) = (2.0f32, 1.0f32);
+        let rect = white_light(center).with_rect(Vector3::new(a, 0.0, 0.0), Vector3::new(0.0, 0.0, b));
+        for h in [0.5f32, 1.0, 3.0] {
+            let expected = 4.0 * (a * b / ((a * a + 4.0 * h * h) * (b * b + 4.0 * h * h)).sqrt()).asin();
+            let measured = sampled_solid_angle(&rect, below(h));
+            assert!((measured - expected).abs() < 2e-3 * expected, "rect at {h}: {measured} != {expected}");
+        }
+
+        let radius = 0.8f32;
+        let disk = white_light(center).with_disk(down, radius);
+        for h in [0.3f32, 1.0, 4.0] {
+            let expected = 2.0 * PI * (1.0 - h / (h * h + radius * radius).sqrt());
+            let measured = sampled_solid_angle(&disk, below(h));
+            assert!((measured - expected).abs() < 2e-3 * expected, "disk at {h}: {measured} != {expected}");
+        }
+
+        let sphere = white_light(center).with_sphere(radius);
+        for d in [0.9f32, 1.5, 6.0] {
+            let expected = 2.0 * PI * (1.0 - (1.0 - (radius / d).powi(2)).sqrt());
+            let measured = sampled_solid_angle(&sphere, below(d));
+            assert!((measured - expected).abs() < 2e-3 * expected, "sphere at {d}: {measured} != {expected}");
+        }
+    }
+
+    #[test]
+    fn spot_is_full_inside_the_inner_cone_and_dark_outside_the_outer() {
+        let position = Vector3::new(0.0, 4.0, 0.0);
+        let down = Vector3::new(0.0, -1.0, 0.0);
+        let (inner, outer) = (20f32.to_radians(), 30f32.to_radians());
+        let spot = white_light(position).with_spot(down, inner, outer);
+        let hard = white_light(position).with_spot(down, inner, inner);
+        // Point 2 m away at `degrees` from the spot's axis
+        let at = |degrees: f32| {
+            let angle = degrees.to_radians();
+            position + Vector3::new(angle.sin(), -angle.cos(), 0.0) * 2.0
+        };
+
+        for degrees in [0.0, 10.0, 19.9] {
+            for light in [&spot, &hard] {
+                let sample = light.sample(at(degrees), 0.5, 0.5).unwrap();
+                assert!((sample.weight * 4.0 - 1.0).abs() < 1e-5, "{degrees}°: weight {}", sample.weight);
+            }
+        }
+        let penumbra = spot.sample(at(25.0), 0.5, 0.5).unwrap().weight * 4.0;
+        assert!(penumbra > 0.0 && penumbra < 1.0, "25°: weight {penumbra}");
+        for degrees in [30.1, 45.0, 90.0, 180.0] {
+            assert!(spot.sample(at(degrees), 0.5, 0.5).is_none(), "{degrees}° is lit");
+        }
+        for degrees in [20.1, 25.0] {
+            assert!(hard.sample(at(degrees), 0.5, 0.5).is_none(), "{degrees}° is lit by the hard spot");
+        }
+    }
+
+    #[test]
+    fn point_lights_fall_off_with_the_square_of_distance() {
+        let light = white_light(Vector3::zero());
+        for distance in [0.1f32, 0.5, 1.0, 2.0, 8.0, 50.0] {
+            let sample = light.sample(Vector3::new(0.0, 0.0, distance), 0.5, 0.5).unwrap();
+            let scaled = sample.weight * distance * distance;
+            assert!((scaled - 1.0).abs() < 1e-4, "at {distance}: weight {} is not 1 / d²", sample.weight);
+        }
+    }
+
+    #[test]
+    fn range_falloff_reaches_zero_at_the_range_without_a_step() {
+        let range = 5.0;
+        let attenuation = Attenuation::Range { range };
+        assert_eq!(attenuation.factor(range), 0.0);
+        assert_eq!(attenuation.factor(range + 0.5), 0.0);
+        assert!(attenuation.factor(range * 0.999) < 1e-6);
+
+        // Well inside the range it still follows the inverse square
+        let near = attenuation.factor(0.5) * 0.25;
+        assert!((near - 1.0).abs() < 1e-2, "at 0.5: {near}");
+
+        let step = 1e-4;
+        let mut previous = attenuation.factor(1.0);
+        let mut distance = 1.0 + step;
+        while distance < range + 1.0 {
+            let factor = attenuation.factor(distance);
+            assert!((factor - previous).abs() < 1e-3, "jumps from {previous} to {factor} at {distance}");
+            previous = factor;
+            distance += step;
+        }
+    }
+}
@@ -8,19 +8,77 @@ mod color;
 mod light;
 mod material;
 mod camera;
+mod bvh;
+mod diorama;
+mod headless;
+mod json;
+mod scene_file;
+mod sampling;
+mod accumulator;
+mod tonemap;
+mod mesh;
+mod obj;
+mod transform;
+mod path_tracer;
+mod emitter;
+mod pbr;
+mod assets;
+mod skybox;
+mod hdr_image;
+mod exr;
+mod inflate;
+mod environment;
+mod physical_sky;
 
 use raylib::prelude::*;
 use framebuffer::Framebuffer;
-use renderer::{render, Object};
-use cube::Cube;
-use texture::Texture;
-use camera::CustomCamera;
-use light::Light;
-use material::Material;
-use color::Color;
+use environment::Environment;
+use renderer::{find_hit, render_progressive, RenderSettings, Scene, TraceContext};
+use accumulator::Accumulator;
+use diorama::Diorama;
 use std::f32::consts::PI;
 
+// One third of a stop per key press
+const EXPOSURE_STEP: f32 = 1.26;
+// Field of view change per frame while the key is held (one degree)
+const FOV_SPEED: f32 = PI / 180.0;
+// Hours per key press when moving the sun of a physical sky
+const TIME_STEP: f32 = 0.25;
+// Free-fly camera: radians of turn per pixel of mouse movement, world units
+// per second, and the factor Shift multiplies (and Ctrl divides) it by
+const MOUSE_SENSITIVITY: f32 = 0.003;
+const FLY_SPEED: f32 = 2.0;
+const FLY_SPEED_MODIFIER: f32 = 4.0;
+
+// How the keyboard and mouse move the camera
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Control {
+    // Circle the looked-at point and zoom towards it
+    Orbit,
+    // First-person flight with mouse-look
+    FreeFly,
+}
+
 fn main() {
+    // `render` subcommand: headless rendering straight to an image file
+    let args: Vec<String> = std::env::args().collect();
+    if args.get(1).map(String::as_str) == Some("render") {
+        if let Err(err) = headless::run(&args[2..]) {
+            eprintln!("error: {}", err);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    // Optional scene file: `cargo run -- scenes/my_diorama.json`
+    let diorama = match diorama::load_or_default(args.get(1).map(String::as_str)) {
+        Ok(diorama) => diorama,
+        Err(err) => {
+            eprintln!("error: {}", err);
+            std::process::exit(1);
+        }
+    };
+
     let window_width = 800;
     let window_height = 600;
     let framebuffer_width = 600; // Reducido para mejor rendimiento
@@ -38,393 +96,149 @@ fn main() {
     framebuffer.set_background_color(raylib::color::Color::new(135, 206, 235, 255));
     framebuffer.clear();
 
-    // Create textures for the Zen Garden diorama (6 materials: 5 unique + concrete base)
-    let textures = vec![
-        Texture::checkerboard(64, 64, Color::new(255, 255, 255), Color::new(0, 0, 0)),     // 0: Reference checkerboard
-        Texture::zen_moss(64, 64),                                                          // 1: Natural moss vegetation
-        Texture::brushed_metal(64, 64),                                                     // 2: Tech metal panels
-        Texture::zen_water(64, 64),                                                         // 3: Calm water surface
-        Texture::crystal_glass(64, 64),                                                     // 4: Prismatic crystal
-        Texture::chrome_mirror(64, 64),                                                     // 5: Reflective chrome
-        Texture::concrete_base(64, 64),                                                     // 6: Concrete foundation
-    ];
+    let Diorama { textures, objects, lights: scene_lights, skybox, mut camera } = diorama;
 
-    // === UNIFIED ZEN GARDEN - COHERENT MATERIAL GROUPS ===
-    let mut objects = vec![];
-    
-    // === SOLID CONCRETE BASE (Foundation for everything) ===
-    
-    // Large concrete foundation platform (11x11)
-    for x in -5..6 {
-        for z in -5..6 {
-            objects.push(Object::Cube(
-                Cube::new(Vector3::new(x as f32, -1.0, z as f32), 1.0, Material::concrete_base())
-                    .with_texture(6)
-            ));
-        }
-    }
-    
-    // === INSTALLATION 1: CENTRAL REFLECTION POND ===
-    // Combines: Water + Crystal + Chrome + Metal
-    // Theme: Central focal point showing water reflections and crystal refractions
-    
-    // Water pond with integrated elements (3x3 arrangement)
-    for x in -1..2 {
-        for z in -1..2 {
-            objects.push(Object::Cube(
-                Cube::new(Vector3::new(x as f32, -0.5, z as f32), 1.0, Material::zen_water())
-                    .with_texture(3)
-            ));
-        }
-    }
-    
-    // Central crystal formation emerging from water
-    objects.push(Object::Cube(
-        Cube::new(Vector3::new(0.0, -0.2, 0.0), 0.8, Material::crystal_glass())
-            .with_texture(4)
-    ));
-    
-    // Chrome reflection panels around pond (N, S, E, W)
-    objects.push(Object::Cube(
-        Cube::new(Vector3::new(0.0, -0.3, -2.2), 1.5, Material::chrome_mirror())
-            .with_texture(5)
-    ));
-    objects.push(Object::Cube(
-        Cube::new(Vector3::new(0.0, -0.3, 2.2), 1.5, Material::chrome_mirror())
-            .with_texture(5)
-    ));
-    objects.push(Object::Cube(
-        Cube::new(Vector3::new(-2.2, -0.3, 0.0), 1.5, Material::chrome_mirror())
-            .with_texture(5)
-    ));
-    objects.push(Object::Cube(
-        Cube::new(Vector3::new(2.2, -0.3, 0.0), 1.5, Material::chrome_mirror())
-            .with_texture(5)
-    ));
-    
-    // Metal support structures for chrome panels
-    objects.push(Object::Cube(
-        Cube::new(Vector3::new(0.0, -0.5, -2.5), 0.6, Material::brushed_metal())
-            .with_texture(2)
-    ));
-    objects.push(Object::Cube(
-        Cube::new(Vector3::new(0.0, -0.5, 2.5), 0.6, Material::brushed_metal())
-            .with_texture(2)
-    ));
-    objects.push(Object::Cube(
-        Cube::new(Vector3::new(-2.5, -0.5, 0.0), 0.6, Material::brushed_metal())
-            .with_texture(2)
-    ));
-    objects.push(Object::Cube(
-        Cube::new(Vector3::new(2.5, -0.5, 0.0), 0.6, Material::brushed_metal())
-            .with_texture(2)
-    ));
-    
-    // === INSTALLATION 2: NORTHEAST ZEN GARDEN ===
-    // Combines: Moss + Crystal + Water + Metal + Vertical Bamboo
-    // Theme: Natural meditation area with technological accents and bamboo grove
-    
-    // Moss garden cluster
-    objects.push(Object::Cube(
-        Cube::new(Vector3::new(3.5, -0.5, 3.5), 0.8, Material::zen_moss())
-            .with_texture(1)
-    ));
-    objects.push(Object::Cube(
-        Cube::new(Vector3::new(4.0, -0.5, 3.0), 0.6, Material::zen_moss())
-            .with_texture(1)
-    ));
-    objects.push(Object::Cube(
-        Cube::new(Vector3::new(3.0, -0.5, 4.0), 0.6, Material::zen_moss())
-            .with_texture(1)
-    ));
-    
-    // === BAMBOO GROVE - Vertical Elements ===
-    // Tall bamboo stalks using elongated cubes with zen_moss material - CONNECTED SEGMENTS
-    
-    // Main bamboo stalk (tallest - 6 segments, properly stacked)
-    objects.push(Object::Cube(
-        Cube::new(Vector3::new(4.2, -0.3, 3.8), 0.4, Material::zen_moss())
-            .with_texture(1)
-    ));
-    objects.push(Object::Cube(
-        Cube::new(Vector3::new(4.2, 0.1, 3.8), 0.4, Material::zen_moss())
-            .with_texture(1)
-    ));
-    objects.push(Object::Cube(
-        Cube::new(Vector3::new(4.2, 0.5, 3.8), 0.35, Material::zen_moss())
-            .with_texture(1)
-    ));
-    objects.push(Object::Cube(
-        Cube::new(Vector3::new(4.2, 0.875, 3.8), 0.35, Material::zen_moss())
-            .with_texture(1)
-    ));
-    objects.push(Object::Cube(
-        Cube::new(Vector3::new(4.2, 1.225, 3.8), 0.3, Material::zen_moss())
-            .with_texture(1)
-    ));
-    objects.push(Object::Cube(
-        Cube::new(Vector3::new(4.2, 1.525, 3.8), 0.25, Material::zen_moss())
-            .with_texture(1)
-    ));
-    
-    // Second bamboo stalk (medium height - 4 segments, properly stacked)
-    objects.push(Object::Cube(
-        Cube::new(Vector3::new(3.8, -0.3, 4.2), 0.35, Material::zen_moss())
-            .with_texture(1)
-    ));
-    objects.push(Object::Cube(
-        Cube::new(Vector3::new(3.8, 0.05, 4.2), 0.35, Material::zen_moss())
-            .with_texture(1)
-    ));
-    objects.push(Object::Cube(
-        Cube::new(Vector3::new(3.8, 0.4, 4.2), 0.3, Material::zen_moss())
-            .with_texture(1)
-    ));
-    objects.push(Object::Cube(
-        Cube::new(Vector3::new(3.8, 0.7, 4.2), 0.25, Material::zen_moss())
-            .with_texture(1)
-    ));
-    
-    // Third bamboo stalk (shorter - 3 segments, properly stacked)
-    objects.push(Object::Cube(
-        Cube::new(Vector3::new(3.2, -0.3, 3.7), 0.4, Material::zen_moss())
-            .with_texture(1)
-    ));
-    objects.push(Object::Cube(
-        Cube::new(Vector3::new(3.2, 0.1, 3.7), 0.35, Material::zen_moss())
-            .with_texture(1)
-    ));
-    objects.push(Object::Cube(
-        Cube::new(Vector3::new(3.2, 0.425, 3.7), 0.3, Material::zen_moss())
-            .with_texture(1)
-    ));
-    
-    // Small water feature integrated with moss
-    objects.push(Object::Cube(
-        Cube::new(Vector3::new(3.5, -0.3, 3.0), 0.4, Material::zen_water())
-            .with_texture(3)
-    ));
-    
-    // Crystal meditation point in moss
-    objects.push(Object::Cube(
-        Cube::new(Vector3::new(4.0, -0.2, 3.5), 0.3, Material::crystal_glass())
-            .with_texture(4)
-    ));
-    
-    // Metal accent element
-    objects.push(Object::Cube(
-        Cube::new(Vector3::new(3.0, -0.5, 3.5), 0.4, Material::brushed_metal())
-            .with_texture(2)
-    ));
-    
-    // === INSTALLATION 3: SOUTHWEST TECH GROVE ===
-    // Combines: Metal + Chrome + Crystal + Moss + Vertical Tech Spire
-    // Theme: Technology integrated with nature
-    
-    // Metal platform base
-    objects.push(Object::Cube(
-        Cube::new(Vector3::new(-3.5, -0.5, -3.5), 1.2, Material::brushed_metal())
-            .with_texture(2)
-    ));
-    
-    // Chrome tech panel
-    objects.push(Object::Cube(
-        Cube::new(Vector3::new(-3.5, -0.2, -3.5), 1.0, Material::chrome_mirror())
-            .with_texture(5)
-    ));
-    
-    // === TECH SPIRE - Vertical Metal Antenna ===
-    // Sleek metal spire for tech aesthetics - CONNECTED SEGMENTS
-    objects.push(Object::Cube(
-        Cube::new(Vector3::new(-3.8, -0.375, -3.8), 0.25, Material::brushed_metal())
-            .with_texture(2)
-    ));
-    objects.push(Object::Cube(
-        Cube::new(Vector3::new(-3.8, -0.125, -3.8), 0.25, Material::brushed_metal())
-            .with_texture(2)
-    ));
-    objects.push(Object::Cube(
-        Cube::new(Vector3::new(-3.8, 0.125, -3.8), 0.25, Material::brushed_metal())
-            .with_texture(2)
-    ));
-    objects.push(Object::Cube(
-        Cube::new(Vector3::new(-3.8, 0.375, -3.8), 0.2, Material::brushed_metal())
-            .with_texture(2)
-    ));
-    objects.push(Object::Cube(
-        Cube::new(Vector3::new(-3.8, 0.575, -3.8), 0.2, Material::chrome_mirror())
-            .with_texture(5)
-    ));
-    objects.push(Object::Cube(
-        Cube::new(Vector3::new(-3.8, 0.75, -3.8), 0.15, Material::chrome_mirror())
-            .with_texture(5)
-    ));
-    
-    // Moss growing around tech
-    objects.push(Object::Cube(
-        Cube::new(Vector3::new(-3.0, -0.5, -3.0), 0.5, Material::zen_moss())
-            .with_texture(1)
-    ));
-    objects.push(Object::Cube(
-        Cube::new(Vector3::new(-4.0, -0.5, -3.0), 0.5, Material::zen_moss())
-            .with_texture(1)
-    ));
-    objects.push(Object::Cube(
-        Cube::new(Vector3::new(-3.0, -0.5, -4.0), 0.5, Material::zen_moss())
-            .with_texture(1)
-    ));
-    
-    // Crystal energy core
-    objects.push(Object::Cube(
-        Cube::new(Vector3::new(-3.5, 0.1, -3.5), 0.4, Material::crystal_glass())
-            .with_texture(4)
-    ));
-    
-    // === INSTALLATION 4: SOUTHEAST WATER CASCADE ===
-    // Combines: Water + Crystal + Chrome + Metal
-    // Theme: Flowing water with reflective and refractive elements
-    
-    // Stepped water pools
-    objects.push(Object::Cube(
-        Cube::new(Vector3::new(3.5, -0.4, -3.0), 0.8, Material::zen_water())
-            .with_texture(3)
-    ));
-    objects.push(Object::Cube(
-        Cube::new(Vector3::new(3.5, -0.5, -3.8), 0.6, Material::zen_water())
-            .with_texture(3)
-    ));
-    objects.push(Object::Cube(
-        Cube::new(Vector3::new(3.5, -0.3, -2.2), 0.6, Material::zen_water())
-            .with_texture(3)
-    ));
-    
-    // Crystal formations in water
-    objects.push(Object::Cube(
-        Cube::new(Vector3::new(3.5, -0.1, -3.0), 0.3, Material::crystal_glass())
-            .with_texture(4)
-    ));
-    objects.push(Object::Cube(
-        Cube::new(Vector3::new(3.5, -0.2, -3.8), 0.2, Material::crystal_glass())
-            .with_texture(4)
-    ));
-    
-    // Chrome reflection surface
-    objects.push(Object::Cube(
-        Cube::new(Vector3::new(4.2, -0.3, -3.0), 0.4, Material::chrome_mirror())
-            .with_texture(5)
-    ));
-    
-    // Metal support structure
-    objects.push(Object::Cube(
-        Cube::new(Vector3::new(4.2, -0.5, -3.0), 0.3, Material::brushed_metal())
-            .with_texture(2)
-    ));
-    
-    // === INSTALLATION 5: NORTHWEST HARMONY POINT ===
-    // Combines: All 5 materials in one balanced composition
-    // Theme: Complete material harmony showcase
-    
-    // Base moss platform
-    objects.push(Object::Cube(
-        Cube::new(Vector3::new(-3.5, -0.5, 3.5), 1.0, Material::zen_moss())
-            .with_texture(1)
-    ));
-    
-    // Metal support frame
-    objects.push(Object::Cube(
-        Cube::new(Vector3::new(-3.5, -0.3, 3.5), 0.8, Material::brushed_metal())
-            .with_texture(2)
-    ));
-    
-    // Small water feature
-    objects.push(Object::Cube(
-        Cube::new(Vector3::new(-3.5, -0.1, 3.5), 0.5, Material::zen_water())
-            .with_texture(3)
-    ));
-    
-    // Crystal centerpiece
-    objects.push(Object::Cube(
-        Cube::new(Vector3::new(-3.5, 0.1, 3.5), 0.3, Material::crystal_glass())
-            .with_texture(4)
-    ));
-    
-    // Chrome reflection accent
-    objects.push(Object::Cube(
-        Cube::new(Vector3::new(-3.0, -0.2, 3.0), 0.4, Material::chrome_mirror())
-            .with_texture(5)
-    ));
+    // Build the BVH once the object list is complete
+    let scene = Scene::new(objects);
+    // Tabulate the sky for image-based lighting
+    let mut lights = diorama::lights_with_sun(&scene_lights, &skybox);
+    let mut environment = Environment::new(skybox);
 
-    // Camera positioned for optimal zen garden viewing
-    let mut camera = CustomCamera::new(
-        Vector3::new(8.0, 4.0, 8.0),     // Elevated position to see the full composition
-        Vector3::new(0.0, 1.0, 0.0),     // Looking at the center of the garden
-        Vector3::new(0.0, 1.0, 0.0)      // Up vector
-    );
+    let mut control = Control::Orbit;
     let rotation_speed = PI / 60.0; // Smooth rotation for zen experience
     let zoom_speed = 0.3;
+    let mut settings = RenderSettings::default();
 
-    // ZEN GARDEN LIGHTING - Serene and balanced illumination
-    let lights = [
-        // Overhead ambient light (soft white for natural feel)
-        Light::new(
-            Vector3::new(0.0, 8.0, 0.0),
-            Color::new(240, 245, 255), // Cool white daylight
-            1.8
-        ),
-        // Central water pool glow (blue-green reflection enhancer)
-        Light::new(
-            Vector3::new(0.0, 2.5, 0.0),
-            Color::new(120, 180, 220), // Soft blue-cyan
-            1.2
-        ),
-        // Corner tech tower lights (warm tech glow)
-        Light::new(
-            Vector3::new(-2.5, 2.5, 2.5),
-            Color::new(200, 220, 255), // Cool tech blue
-            0.8
-        ),
-        Light::new(
-            Vector3::new(2.5, 2.5, 2.5),
-            Color::new(200, 220, 255), // Cool tech blue
-            0.8
-        ),
-        Light::new(
-            Vector3::new(-2.5, 2.5, -2.5),
-            Color::new(200, 220, 255), // Cool tech blue
-            0.8
-        ),
-        Light::new(
-            Vector3::new(2.5, 2.5, -2.5),
-            Color::new(200, 220, 255), // Cool tech blue
-            0.8
-        )
-    ];
+    // Samples keep accumulating while the camera is still
+    let mut accumulator = Accumulator::new(framebuffer_width as usize, framebuffer_height as usize);
 
     while !window.window_should_close() {
-        // Smooth camera controls for zen garden exploration
-        if window.is_key_down(KeyboardKey::KEY_LEFT) || window.is_key_down(KeyboardKey::KEY_A) {
-            camera.orbit(rotation_speed, 0.0);
+        // F switches between orbiting the looked-at point and flying
+        // freely. The view itself doesn't move; only the controls change.
+        if window.is_key_pressed(KeyboardKey::KEY_F) {
+            control = match control {
+                Control::Orbit => {
+                    window.disable_cursor();
+                    Control::FreeFly
+                }
+                Control::FreeFly => {
+                    window.enable_cursor();
+                    // Orbit around whatever is straight ahead, so a detail
+                    // flown up to can be circled; the view direction is kept
+                    let forward = (camera.center - camera.eye).normalized();
+                    if let Some((hit, _)) = find_hit(&scene, &camera.eye, &forward) {
+                        camera.center = camera.eye + forward * hit.distance;
+                    }
+                    Control::Orbit
+                }
+            };
         }
-        if window.is_key_down(KeyboardKey::KEY_RIGHT) || window.is_key_down(KeyboardKey::KEY_D) {
-            camera.orbit(-rotation_speed, 0.0);
+
+        match control {
+            Control::Orbit => {
+                // Smooth camera controls for zen garden exploration
+                if window.is_key_down(KeyboardKey::KEY_LEFT) || window.is_key_down(KeyboardKey::KEY_A) {
+                    camera.orbit(rotation_speed, 0.0);
+                }
+                if window.is_key_down(KeyboardKey::KEY_RIGHT) || window.is_key_down(KeyboardKey::KEY_D) {
+                    camera.orbit(-rotation_speed, 0.0);
+                }
+                if window.is_key_down(KeyboardKey::KEY_UP) || window.is_key_down(KeyboardKey::KEY_W) {
+                    camera.orbit(0.0, -rotation_speed);
+                }
+                if window.is_key_down(KeyboardKey::KEY_DOWN) || window.is_key_down(KeyboardKey::KEY_S) {
+                    camera.orbit(0.0, rotation_speed);
+                }
+
+                // Zoom controls
+                if window.is_key_down(KeyboardKey::KEY_Q) || window.is_key_down(KeyboardKey::KEY_Z) {
+                    // Zoom in - move camera closer to center
+                    let direction = (camera.center - camera.eye).normalized();
+                    camera.eye = camera.eye + direction * zoom_speed;
+                }
+                if window.is_key_down(KeyboardKey::KEY_E) || window.is_key_down(KeyboardKey::KEY_X) {
+                    // Zoom out - move camera away from center
+                    let direction = (camera.center - camera.eye).normalized();
+                    camera.eye = camera.eye - direction * zoom_speed;
+                }
+            }
+            Control::FreeFly => {
+                // Mouse-look with the captured cursor
+                let mouse = window.get_mouse_delta();
+                if mouse.x != 0.0 || mouse.y != 0.0 {
+                    camera.look(mouse.x * MOUSE_SENSITIVITY, -mouse.y * MOUSE_SENSITIVITY);
+                }
+
+                // WASD moves in the view's frame, Space/E and C/Q go up and
+                // down. Shift speeds up, Ctrl slows down for close-up detail.
+                // Frames take a variable time to trace, so movement follows
+                // the clock; long frames are capped to avoid jumps.
+                let key = |k: KeyboardKey| if window.is_key_down(k) { 1.0 } else { 0.0 };
+                let forward = key(KeyboardKey::KEY_W) + key(KeyboardKey::KEY_UP) - key(KeyboardKey::KEY_S) - key(KeyboardKey::KEY_DOWN);
+                let right = key(KeyboardKey::KEY_D) + key(KeyboardKey::KEY_RIGHT) - key(KeyboardKey::KEY_A) - key(KeyboardKey::KEY_LEFT);
+                let up = key(KeyboardKey::KEY_SPACE) + key(KeyboardKey::KEY_E) - key(KeyboardKey::KEY_C) - key(KeyboardKey::KEY_Q);
+                if forward != 0.0 || right != 0.0 || up != 0.0 {
+                    let mut speed = FLY_SPEED * window.get_frame_time().min(0.1);
+                    if window.is_key_down(KeyboardKey::KEY_LEFT_SHIFT) {
+                        speed *= FLY_SPEED_MODIFIER;
+                    }
+                    if window.is_key_down(KeyboardKey::KEY_LEFT_CONTROL) {
+                        speed /= FLY_SPEED_MODIFIER;
+                    }
+                    camera.fly(forward * speed, right * speed, up * speed);
+                }
+            }
         }
-        if window.is_key_down(KeyboardKey::KEY_UP) || window.is_key_down(KeyboardKey::KEY_W) {
-            camera.orbit(0.0, -rotation_speed);
+
+        // Minus and equals narrow and widen the field of view; V cycles the
+        // projection. The camera changes, so the accumulation restarts.
+        if window.is_key_down(KeyboardKey::KEY_MINUS) {
+            camera.change_fov(-FOV_SPEED);
         }
-        if window.is_key_down(KeyboardKey::KEY_DOWN) || window.is_key_down(KeyboardKey::KEY_S) {
-            camera.orbit(0.0, rotation_speed);
+        if window.is_key_down(KeyboardKey::KEY_EQUAL) {
+            camera.change_fov(FOV_SPEED);
         }
-        
-        // Zoom controls
-        if window.is_key_down(KeyboardKey::KEY_Q) || window.is_key_down(KeyboardKey::KEY_Z) {
-            // Zoom in - move camera closer to center
-            let direction = (camera.center - camera.eye).normalized();
-            camera.eye = camera.eye + direction * zoom_speed;
+        if window.is_key_pressed(KeyboardKey::KEY_V) {
+            camera.projection = camera.projection.next();
+            camera.change_fov(0.0);
+        }
+
+        // Tone mapping: T cycles the operator, [ and ] change the exposure.
+        // Samples are stored in linear HDR, so no re-render is needed.
+        let mut tone_mapping = framebuffer.tone_mapping();
+        if window.is_key_pressed(KeyboardKey::KEY_T) {
+            tone_mapping.mapper = tone_mapping.mapper.next();
+        }
+        if window.is_key_pressed(KeyboardKey::KEY_LEFT_BRACKET) {
+            tone_mapping.exposure /= EXPOSURE_STEP;
+        }
+        if window.is_key_pressed(KeyboardKey::KEY_RIGHT_BRACKET) {
+            tone_mapping.exposure *= EXPOSURE_STEP;
         }
-        if window.is_key_down(KeyboardKey::KEY_E) || window.is_key_down(KeyboardKey::KEY_X) {
-            // Zoom out - move camera away from center
-            let direction = (camera.center - camera.eye).normalized();
-            camera.eye = camera.eye - direction * zoom_speed;
+        framebuffer.set_tone_mapping(tone_mapping);
+
+        // Comma and period move a physical sky's clock by a quarter of an
+        // hour; the sky, its lighting and the sun are rebuilt
+        let time_step = if window.is_key_pressed(KeyboardKey::KEY_COMMA) {
+            -TIME_STEP
+        } else if window.is_key_pressed(KeyboardKey::KEY_PERIOD) {
+            TIME_STEP
+        } else {
+            0.0
+        };
+        if let Some(hours) = environment.skybox.time_of_day().filter(|_| time_step != 0.0) {
+            let mut skybox = environment.skybox.clone();
+            skybox.set_time_of_day(hours + time_step);
+            lights = diorama::lights_with_sun(&scene_lights, &skybox);
+            environment = Environment::new(skybox);
+            accumulator.reset();
+        }
+
+        // P switches between the Whitted tracer and the path tracer. The
+        // image changes, so the accumulated samples are thrown away.
+        if window.is_key_pressed(KeyboardKey::KEY_P) {
+            settings.integrator = settings.integrator.next();
+            accumulator.reset();
         }
 
         // Check if window was resized
@@ -436,8 +250,9 @@ fn main() {
             framebuffer.clear();
         }
 
-        // Render the scene
-        render(&mut framebuffer, &objects, &camera, &lights, &textures);
+        // Refine the image with one more jittered pass
+        let ctx = TraceContext { scene: &scene, lights: &lights, textures: &textures, environment: &environment };
+        render_progressive(&mut framebuffer, &mut accumulator, &ctx, &camera, &settings);
         
         framebuffer.swap_buffers(&mut window, &raylib_thread);
     }
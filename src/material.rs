@@ -1,4 +1,5 @@
-use crate::color::Color;
+use crate::color::{Color, LinearColor};
+use crate::pbr::Pbr;
 
 #[derive(Debug, Clone, Copy)]
 pub struct Material {
@@ -7,6 +8,13 @@ pub struct Material {
     pub albedo: [f32; 4], // [diffuse, specular, reflective, refractive]
     pub refractive_index: f32,
     pub transparency: f32, // 0.0 = opaque, 1.0 = fully transparent
+    // Glow in sRGB and its radiance multiplier; emissive objects are seen
+    // directly and light the scene like area lights
+    pub emission: Color,
+    pub emission_strength: f32,
+    // Metallic-roughness parameters; when set they replace the Phong weights
+    // above (`diffuse` stays the base colour)
+    pub pbr: Option<Pbr>,
 }
 
 impl Material {
@@ -17,9 +25,60 @@ impl Material {
             albedo,
             refractive_index,
             transparency,
+            emission: Color::new(0, 0, 0),
+            emission_strength: 0.0,
+            pbr: None,
         }
     }
 
+    pub fn with_emission(mut self, emission: Color, strength: f32) -> Self {
+        self.emission = emission;
+        self.emission_strength = strength;
+        self
+    }
+
+    pub fn with_pbr(mut self, pbr: Pbr) -> Self {
+        self.pbr = Some(pbr);
+        self
+    }
+
+    pub fn is_emissive(&self) -> bool {
+        self.emission_strength > 0.0 && (self.emission.r, self.emission.g, self.emission.b) != (0, 0, 0)
+    }
+
+    // Radiance leaving the surface on its own, in linear light
+    pub fn emitted(&self) -> LinearColor {
+        self.emission.to_linear() * self.emission_strength
+    }
+
+    // Look up a preset by the name of its constructor (used by scene files)
+    pub fn preset(name: &str) -> Option<Self> {
+        let material = match name {
+            "rubber" => Self::rubber(),
+            "ivory" => Self::ivory(),
+            "mirror" => Self::mirror(),
+            "glass" => Self::glass(),
+            "water" => Self::water(),
+            "crystal_glass" => Self::crystal_glass(),
+            "chrome_mirror" => Self::chrome_mirror(),
+            "zen_water" => Self::zen_water(),
+            "zen_moss" => Self::zen_moss(),
+            "brushed_metal" => Self::brushed_metal(),
+            "concrete_base" => Self::concrete_base(),
+            "concrete" => Self::concrete(),
+            "polished_metal" => Self::polished_metal(),
+            "rusted_metal" => Self::rusted_metal(),
+            "dark_water" => Self::dark_water(),
+            "red_crystal" => Self::red_crystal(),
+            "ancient_stone" => Self::ancient_stone(),
+            "blood_water" => Self::blood_water(),
+            "dark_crystal" => Self::dark_crystal(),
+            "charred_wood" => Self::charred_wood(),
+            _ => return None,
+        };
+        Some(material)
+    }
+
     // Material presets
     pub fn rubber() -> Self {
         Material::new(
@@ -128,6 +187,9 @@ impl Material {
             1.0,
             0.0, // Opaque
         )
+        // Anisotropic GGX: highlights stretch along the grain, which runs
+        // along U like the stripes of `Texture::brushed_metal`
+        .with_pbr(Pbr::new(1.0, 0.35).with_anisotropy(0.8, None))
     }
 
     // 6. CONCRETE BASE - Solid foundation material
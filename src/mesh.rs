@@ -0,0 +1,264 @@
+use raylib::prelude::*;
+use crate::ray_intersect::{Intersect, RayIntersect};
+use crate::material::Material;
+use crate::bvh::{Aabb, Bvh};
+use crate::emitter::SurfaceSample;
+use crate::assets::TextureHandle;
+
+// Below this the ray is treated as parallel to the triangle's plane
+const DETERMINANT_EPSILON: f32 = 1e-10;
+
+// Shading parameters of one material group plus its optional diffuse map
+#[derive(Debug, Clone, Copy)]
+pub struct MeshMaterial {
+    pub material: Material,
+    pub texture_id: Option<TextureHandle>,
+}
+
+// Indices into the mesh's vertex arrays. Normals and UVs are optional per
+// triangle since OBJ faces may leave them out.
+#[derive(Debug, Clone, Copy)]
+pub struct Triangle {
+    pub positions: [usize; 3],
+    pub normals: Option<[usize; 3]>,
+    pub uvs: Option<[usize; 3]>,
+    pub material: usize,
+}
+
+// Indexed triangle mesh with its own BVH over the triangles, so a model with
+// thousands of faces is still a single entry in the scene hierarchy.
+#[derive(Debug, Clone)]
+pub struct Mesh {
+    positions: Vec<Vector3>,
+    normals: Vec<Vector3>,
+    uvs: Vec<(f32, f32)>,
+    triangles: Vec<Triangle>,
+    // Indexed by `Triangle::material`; editing the slots never invalidates the BVH
+    pub materials: Vec<MeshMaterial>,
+    // Per triangle: UV units per world unit, for texture filtering
+    uv_density: Vec<f32>,
+    // Running total of triangle areas, for sampling points on the surface
+    area_cdf: Vec<f32>,
+    bounds: Aabb,
+    bvh: Bvh,
+}
+
+impl Mesh {
+    // Every index in `triangles` must be valid for the given arrays
+    pub fn new(
+        positions: Vec<Vector3>,
+        normals: Vec<Vector3>,
+        uvs: Vec<(f32, f32)>,
+        triangles: Vec<Triangle>,
+        materials: Vec<MeshMaterial>,
+    ) -> Self {
+        let mut mesh = Mesh {
+            positions,
+            normals,
+            uvs,
+            triangles,
+            materials,
+            uv_density: Vec::new(),
+            area_cdf: Vec::new(),
+            bounds: Aabb::empty(),
+            bvh: Bvh::build(&[]),
+        };
+        mesh.rebuild();
+        mesh
+    }
+
+    fn rebuild(&mut self) {
+        let triangle_bounds: Vec<Aabb> = self.triangles.iter().map(|t| self.triangle_bounds(t)).collect();
+        self.bounds = triangle_bounds.iter().fold(Aabb::empty(), |acc, b| acc.union(b));
+        self.bvh = Bvh::build(&triangle_bounds);
+        self.uv_density = self.triangles.iter().map(|t| self.triangle_uv_density(t)).collect();
+        let mut total = 0.0;
+        self.area_cdf = self
+            .triangles
+            .iter()
+            .map(|t| {
+                total += self.triangle_normal(t).length() * 0.5;
+                total
+            })
+            .collect();
+    }
+
+    // Unnormalized face normal, twice the triangle's area long
+    fn triangle_normal(&self, triangle: &Triangle) -> Vector3 {
+        let [i0, i1, i2] = triangle.positions;
+        (self.positions[i1] - self.positions[i0]).cross(self.positions[i2] - self.positions[i0])
+    }
+
+    pub fn area(&self) -> f32 {
+        self.area_cdf.last().copied().unwrap_or(0.0)
+    }
+
+    // Uniformly distributed point on the surface: `pick` chooses a triangle
+    // in proportion to its area, `s` and `t` the position on it
+    pub fn sample_surface(&self, s: f32, t: f32, pick: f32) -> Option<SurfaceSample> {
+        let target = pick * self.area();
+        let index = self.area_cdf.partition_point(|&a| a <= target).min(self.triangles.len().checked_sub(1)?);
+        let triangle = &self.triangles[index];
+        let [i0, i1, i2] = triangle.positions;
+
+        let root = s.sqrt();
+        let (b1, b2) = (root * (1.0 - t), root * t);
+        let p0 = self.positions[i0];
+        let point = p0 + (self.positions[i1] - p0) * b1 + (self.positions[i2] - p0) * b2;
+        Some(SurfaceSample {
+            point,
+            normal: self.triangle_normal(triangle).normalized(),
+            material: self.materials[triangle.material].material,
+        })
+    }
+
+    // Whether any material group glows
+    pub fn is_emissive(&self) -> bool {
+        self.materials.iter().any(|slot| slot.material.is_emissive())
+    }
+
+    // Direction of increasing u across the triangle (dP/du), or None when its
+    // UVs are degenerate
+    fn triangle_tangent(&self, triangle: &Triangle) -> Option<Vector3> {
+        let [t0, t1, t2] = triangle.uvs?;
+        let [i0, i1, i2] = triangle.positions;
+        let (e1, e2) = (self.positions[i1] - self.positions[i0], self.positions[i2] - self.positions[i0]);
+        let (du1, dv1) = (self.uvs[t1].0 - self.uvs[t0].0, self.uvs[t1].1 - self.uvs[t0].1);
+        let (du2, dv2) = (self.uvs[t2].0 - self.uvs[t0].0, self.uvs[t2].1 - self.uvs[t0].1);
+        let determinant = du1 * dv2 - du2 * dv1;
+        if determinant.abs() < DETERMINANT_EPSILON {
+            return None;
+        }
+        let tangent = (e1 * dv2 - e2 * dv1) / determinant;
+        (tangent.length() > 1e-8).then(|| tangent.normalized())
+    }
+
+    // Square root of the UV area over the world area of the triangle
+    fn triangle_uv_density(&self, triangle: &Triangle) -> f32 {
+        let Some([t0, t1, t2]) = triangle.uvs else {
+            return 0.0;
+        };
+        let [i0, i1, i2] = triangle.positions;
+        let world_area = (self.positions[i1] - self.positions[i0])
+            .cross(self.positions[i2] - self.positions[i0])
+            .length();
+        let (du1, dv1) = (self.uvs[t1].0 - self.uvs[t0].0, self.uvs[t1].1 - self.uvs[t0].1);
+        let (du2, dv2) = (self.uvs[t2].0 - self.uvs[t0].0, self.uvs[t2].1 - self.uvs[t0].1);
+        let uv_area = (du1 * dv2 - du2 * dv1).abs();
+        if world_area > 0.0 { (uv_area / world_area).sqrt() } else { 0.0 }
+    }
+
+    fn triangle_bounds(&self, triangle: &Triangle) -> Aabb {
+        let mut bounds = Aabb::empty();
+        for &i in &triangle.positions {
+            bounds.grow(self.positions[i]);
+        }
+        bounds
+    }
+
+    pub fn bounds(&self) -> Aabb {
+        self.bounds
+    }
+
+    // Replace the material of every group, keeping their textures
+    pub fn with_material(mut self, material: Material) -> Self {
+        for slot in &mut self.materials {
+            slot.material = material;
+        }
+        self
+    }
+
+    // Use one texture for every group
+    pub fn with_texture(mut self, texture_id: TextureHandle) -> Self {
+        for slot in &mut self.materials {
+            slot.texture_id = Some(texture_id);
+        }
+        self
+    }
+
+    // Scale uniformly around the model origin, then move by `offset`
+    pub fn placed(mut self, offset: Vector3, scale: f32) -> Self {
+        for p in &mut self.positions {
+            *p = *p * scale + offset;
+        }
+        self.rebuild();
+        self
+    }
+
+    // Möller-Trumbore; returns the distance and the barycentrics of vertices 1 and 2
+    fn intersect_triangle(&self, triangle: &Triangle, ray_origin: &Vector3, ray_direction: &Vector3) -> Option<(f32, f32, f32)> {
+        let [i0, i1, i2] = triangle.positions;
+        let p0 = self.positions[i0];
+        let edge1 = self.positions[i1] - p0;
+        let edge2 = self.positions[i2] - p0;
+
+        let pvec = ray_direction.cross(edge2);
+        let determinant = edge1.dot(pvec);
+        if determinant.abs() < DETERMINANT_EPSILON {
+            return None;
+        }
+        let inv_determinant = 1.0 / determinant;
+
+        let tvec = *ray_origin - p0;
+        let b1 = tvec.dot(pvec) * inv_determinant;
+        if !(0.0..=1.0).contains(&b1) {
+            return None;
+        }
+
+        let qvec = tvec.cross(edge1);
+        let b2 = ray_direction.dot(qvec) * inv_determinant;
+        if b2 < 0.0 || b1 + b2 > 1.0 {
+            return None;
+        }
+
+        let t = edge2.dot(qvec) * inv_determinant;
+        if t > 0.0 {
+            Some((t, b1, b2))
+        } else {
+            None
+        }
+    }
+}
+
+impl RayIntersect for Mesh {
+    fn ray_intersect(&self, ray_origin: &Vector3, ray_direction: &Vector3) -> Intersect {
+        let hit = self.bvh.closest_hit_with(ray_origin, ray_direction, |i| {
+            self.intersect_triangle(&self.triangles[i], ray_origin, ray_direction)
+                .map(|(t, b1, b2)| (t, (t, b1, b2)))
+        });
+        let Some(((t, b1, b2), index)) = hit else {
+            return Intersect::empty();
+        };
+
+        let triangle = &self.triangles[index];
+        let b0 = 1.0 - b1 - b2;
+        let point = *ray_origin + *ray_direction * t;
+
+        let geometric_normal = self.triangle_normal(triangle).normalized();
+
+        // Smooth shading from the vertex normals, falling back to the face
+        // normal when the file has none or they cancel out
+        let normal = match triangle.normals {
+            Some([n0, n1, n2]) => {
+                let n = self.normals[n0] * b0 + self.normals[n1] * b1 + self.normals[n2] * b2;
+                if n.length() > 1e-6 { n.normalized() } else { geometric_normal }
+            }
+            None => geometric_normal,
+        };
+
+        let slot = self.materials[triangle.material];
+        let mut intersect = Intersect::new(point, normal, t, slot.material).with_texture(slot.texture_id);
+
+        if let Some([t0, t1, t2]) = triangle.uvs {
+            let u = self.uvs[t0].0 * b0 + self.uvs[t1].0 * b1 + self.uvs[t2].0 * b2;
+            let v = self.uvs[t0].1 * b0 + self.uvs[t1].1 * b1 + self.uvs[t2].1 * b2;
+            // OBJ puts v = 0 at the bottom of the image, textures at the top
+            intersect = intersect.with_uv(u, 1.0 - v, self.uv_density[index]);
+            if let Some(tangent) = self.triangle_tangent(triangle) {
+                intersect = intersect.with_tangent(tangent);
+            }
+        }
+
+        intersect
+    }
+}
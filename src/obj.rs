@@ -0,0 +1,367 @@
+use raylib::prelude::*;
+use crate::mesh::{Mesh, MeshMaterial, Triangle};
+use crate::material::Material;
+use crate::pbr::Pbr;
+use crate::texture::{Filter, WrapMode};
+use crate::assets::TextureRegistry;
+use crate::color::{linear_to_srgb, Color};
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// Wavefront OBJ/MTL loader. Supports positions, normals, UVs, polygonal faces
+// (triangulated as fans), negative indices, `mtllib`/`usemtl` and the MTL
+// statements that map onto `Material`. Statements that don't affect the
+// render (o, g, s, l, ...) are skipped.
+
+#[derive(Debug)]
+pub struct ObjError {
+    pub path: String,
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+impl ObjError {
+    fn new(path: &Path, line: Option<usize>, message: impl Into<String>) -> Self {
+        ObjError {
+            path: path.display().to_string(),
+            line,
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for ObjError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "{}:{}: {}", self.path, line, self.message),
+            None => write!(f, "{}: {}", self.path, self.message),
+        }
+    }
+}
+
+impl std::error::Error for ObjError {}
+
+// One `newmtl` block, with the defaults the MTL spec gives missing statements
+#[derive(Debug, Clone)]
+struct MtlEntry {
+    diffuse: [f32; 3],
+    specular: [f32; 3],
+    shininess: f32,
+    refractive_index: f32,
+    dissolve: f32,
+    illum: u32,
+    emission: [f32; 3],
+    // PBR extension (Pr/Pm); either one switches the material to GGX
+    roughness: Option<f32>,
+    metallic: Option<f32>,
+    diffuse_map: Option<PathBuf>,
+}
+
+impl Default for MtlEntry {
+    fn default() -> Self {
+        MtlEntry {
+            diffuse: [0.8, 0.8, 0.8],
+            specular: [0.0, 0.0, 0.0],
+            shininess: 10.0,
+            refractive_index: 1.0,
+            dissolve: 1.0,
+            illum: 2,
+            emission: [0.0, 0.0, 0.0],
+            roughness: None,
+            metallic: None,
+            diffuse_map: None,
+        }
+    }
+}
+
+impl MtlEntry {
+    // Map the MTL illumination model onto the Phong + reflection/refraction
+    // weights used by the renderer
+    fn to_material(&self) -> Material {
+        let encode = |c: f32| (linear_to_srgb(c.clamp(0.0, 1.0)) * 255.0).round() as u8;
+        let diffuse = Color::new(encode(self.diffuse[0]), encode(self.diffuse[1]), encode(self.diffuse[2]));
+        let specular_weight = self.specular.iter().copied().fold(0.0, f32::max).min(1.0);
+
+        // illum 3, 5 and 7 add ray traced reflections
+        let reflective = if matches!(self.illum, 3 | 5 | 7) { specular_weight } else { 0.0 };
+        let transparency = (1.0 - self.dissolve).clamp(0.0, 1.0);
+        let refractive = transparency.min(1.0 - reflective);
+
+        let mut material = Material::new(
+            diffuse,
+            self.shininess.max(1.0),
+            [1.0, specular_weight, reflective, refractive],
+            self.refractive_index.max(1.0),
+            transparency,
+        );
+        if self.roughness.is_some() || self.metallic.is_some() {
+            let mut pbr = Pbr::from_phong(&material);
+            pbr.roughness = self.roughness.unwrap_or(pbr.roughness).clamp(0.0, 1.0);
+            pbr.metallic = self.metallic.unwrap_or(pbr.metallic).clamp(0.0, 1.0);
+            material = material.with_pbr(pbr);
+        }
+
+        // Ke is linear and may exceed 1: the brightest channel becomes the
+        // strength and the normalised colour is stored as sRGB
+        let strength = self.emission.iter().copied().fold(0.0, f32::max);
+        if strength <= 0.0 {
+            return material;
+        }
+        let [r, g, b] = self.emission.map(|c| c / strength);
+        material.with_emission(Color::new(encode(r), encode(g), encode(b)), strength)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Corner {
+    position: usize,
+    uv: Option<usize>,
+    normal: Option<usize>,
+}
+
+// Load an OBJ file. Diffuse maps referenced by its materials are loaded
+// through `textures` (each file once per scene) and the mesh keeps their handles.
+pub fn load(path: &str, textures: &mut TextureRegistry) -> Result<Mesh, ObjError> {
+    let path = Path::new(path);
+    let source = fs::read_to_string(path).map_err(|e| ObjError::new(path, None, e.to_string()))?;
+    let base = path.parent().unwrap_or(Path::new(""));
+
+    let mut positions = Vec::new();
+    let mut normals = Vec::new();
+    let mut uvs = Vec::new();
+    let mut triangles = Vec::new();
+
+    let mut library: HashMap<String, MtlEntry> = HashMap::new();
+    let mut materials: Vec<MeshMaterial> = Vec::new();
+    let mut material_ids: HashMap<String, usize> = HashMap::new();
+    let mut current: Option<usize> = None;
+
+    for (index, raw) in source.lines().enumerate() {
+        let line = index + 1;
+        let error = |message: String| ObjError::new(path, Some(line), message);
+        let Some((keyword, args)) = split_statement(raw) else {
+            continue;
+        };
+
+        match keyword {
+            "v" => positions.push(parse_vector(&args).map_err(error)?),
+            "vn" => normals.push(parse_vector(&args).map_err(error)?),
+            "vt" => {
+                let values = parse_floats(&args, 1, 3).map_err(error)?;
+                uvs.push((values[0], values.get(1).copied().unwrap_or(0.0)));
+            }
+            "f" => {
+                if args.len() < 3 {
+                    return Err(error(format!("face needs at least 3 vertices, found {}", args.len())));
+                }
+                let corners = args
+                    .iter()
+                    .map(|a| parse_corner(a, positions.len(), uvs.len(), normals.len()))
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(error)?;
+
+                // Faces before any `usemtl` get the MTL default material
+                let material = match current {
+                    Some(id) => id,
+                    None => {
+                        let id = slot_for(&mut materials, &mut material_ids, "", MtlEntry::default(), textures)
+                            .map_err(error)?;
+                        current = Some(id);
+                        id
+                    }
+                };
+
+                for k in 1..corners.len() - 1 {
+                    triangles.push(triangle(corners[0], corners[k], corners[k + 1], material));
+                }
+            }
+            "mtllib" => {
+                for file in &args {
+                    parse_mtl(&base.join(file), &mut library)?;
+                }
+            }
+            "usemtl" => {
+                let name = args.join(" ");
+                let entry = library
+                    .get(&name)
+                    .cloned()
+                    .ok_or_else(|| error(format!("unknown material '{}'", name)))?;
+                let id = slot_for(&mut materials, &mut material_ids, &name, entry, textures)
+                    .map_err(error)?;
+                current = Some(id);
+            }
+            _ => {}
+        }
+    }
+
+    if triangles.is_empty() {
+        return Err(ObjError::new(path, None, "no faces found"));
+    }
+
+    Ok(Mesh::new(positions, normals, uvs, triangles, materials))
+}
+
+// Keyword plus arguments, or None for blank and comment-only lines
+fn split_statement(raw: &str) -> Option<(&str, Vec<&str>)> {
+    let content = raw.split('#').next().unwrap_or("");
+    let mut parts = content.split_whitespace();
+    let keyword = parts.next()?;
+    Some((keyword, parts.collect()))
+}
+
+// Material slot for `name`, creating it (and loading its diffuse map) on first use
+fn slot_for(
+    materials: &mut Vec<MeshMaterial>,
+    material_ids: &mut HashMap<String, usize>,
+    name: &str,
+    entry: MtlEntry,
+    textures: &mut TextureRegistry,
+) -> Result<usize, String> {
+    if let Some(&id) = material_ids.get(name) {
+        return Ok(id);
+    }
+
+    // Models tile their UVs freely and are often seen from afar
+    let texture_id = match &entry.diffuse_map {
+        Some(file) => Some(textures.load_file(file, Filter::Trilinear, WrapMode::Repeat)?),
+        None => None,
+    };
+
+    materials.push(MeshMaterial {
+        material: entry.to_material(),
+        texture_id,
+    });
+    material_ids.insert(name.to_string(), materials.len() - 1);
+    Ok(materials.len() - 1)
+}
+
+fn triangle(a: Corner, b: Corner, c: Corner, material: usize) -> Triangle {
+    let normals = match (a.normal, b.normal, c.normal) {
+        (Some(na), Some(nb), Some(nc)) => Some([na, nb, nc]),
+        _ => None,
+    };
+    let uvs = match (a.uv, b.uv, c.uv) {
+        (Some(ta), Some(tb), Some(tc)) => Some([ta, tb, tc]),
+        _ => None,
+    };
+    Triangle {
+        positions: [a.position, b.position, c.position],
+        normals,
+        uvs,
+        material,
+    }
+}
+
+fn parse_mtl(path: &Path, library: &mut HashMap<String, MtlEntry>) -> Result<(), ObjError> {
+    let source = fs::read_to_string(path).map_err(|e| ObjError::new(path, None, e.to_string()))?;
+    let base = path.parent().unwrap_or(Path::new(""));
+    let mut current: Option<(String, MtlEntry)> = None;
+
+    for (index, raw) in source.lines().enumerate() {
+        let line = index + 1;
+        let error = |message: String| ObjError::new(path, Some(line), message);
+        let Some((keyword, args)) = split_statement(raw) else {
+            continue;
+        };
+
+        if keyword == "newmtl" {
+            if let Some((name, entry)) = current.take() {
+                library.insert(name, entry);
+            }
+            current = Some((args.join(" "), MtlEntry::default()));
+            continue;
+        }
+
+        let Some((_, entry)) = current.as_mut() else {
+            return Err(error(format!("'{}' before any newmtl", keyword)));
+        };
+
+        match keyword {
+            "Kd" => entry.diffuse = parse_rgb(&args).map_err(error)?,
+            "Ks" => entry.specular = parse_rgb(&args).map_err(error)?,
+            "Ke" => entry.emission = parse_rgb(&args).map_err(error)?,
+            "Ns" => entry.shininess = parse_floats(&args, 1, 1).map_err(error)?[0],
+            "Ni" => entry.refractive_index = parse_floats(&args, 1, 1).map_err(error)?[0],
+            "d" => entry.dissolve = parse_floats(&args, 1, 1).map_err(error)?[0],
+            "Pr" => entry.roughness = Some(parse_floats(&args, 1, 1).map_err(error)?[0]),
+            "Pm" => entry.metallic = Some(parse_floats(&args, 1, 1).map_err(error)?[0]),
+            "Tr" => entry.dissolve = 1.0 - parse_floats(&args, 1, 1).map_err(error)?[0],
+            "illum" => {
+                entry.illum = args
+                    .first()
+                    .and_then(|a| a.parse().ok())
+                    .ok_or_else(|| error("illum expects an integer".to_string()))?;
+            }
+            // Options such as `-s 1 1 1` may precede the file name
+            "map_Kd" => {
+                let file = args.last().ok_or_else(|| error("map_Kd expects a file name".to_string()))?;
+                entry.diffuse_map = Some(base.join(file));
+            }
+            _ => {}
+        }
+    }
+
+    if let Some((name, entry)) = current {
+        library.insert(name, entry);
+    }
+    Ok(())
+}
+
+fn parse_floats(args: &[&str], min: usize, max: usize) -> Result<Vec<f32>, String> {
+    if args.len() < min || args.len() > max {
+        let expected = if min == max { min.to_string() } else { format!("{} to {}", min, max) };
+        return Err(format!("expected {} numbers, found {}", expected, args.len()));
+    }
+    args.iter()
+        .map(|a| a.parse::<f32>().map_err(|_| format!("invalid number '{}'", a)))
+        .collect()
+}
+
+// `v` may carry an optional w component, which is ignored
+fn parse_vector(args: &[&str]) -> Result<Vector3, String> {
+    let v = parse_floats(args, 3, 4)?;
+    Ok(Vector3::new(v[0], v[1], v[2]))
+}
+
+// Kd/Ks/Ke with a single value mean a grey
+fn parse_rgb(args: &[&str]) -> Result<[f32; 3], String> {
+    let v = parse_floats(args, 1, 3)?;
+    match v.as_slice() {
+        [g] => Ok([*g, *g, *g]),
+        [r, g, b] => Ok([*r, *g, *b]),
+        _ => Err("expected 1 or 3 numbers".to_string()),
+    }
+}
+
+// `v`, `v/vt`, `v//vn` or `v/vt/vn`
+fn parse_corner(token: &str, position_count: usize, uv_count: usize, normal_count: usize) -> Result<Corner, String> {
+    let mut parts = token.split('/');
+    let position = match parts.next() {
+        Some(p) if !p.is_empty() => resolve_index(p, position_count, "vertex")?,
+        _ => return Err(format!("face vertex '{}' has no position index", token)),
+    };
+    let uv = match parts.next() {
+        Some(t) if !t.is_empty() => Some(resolve_index(t, uv_count, "texture coordinate")?),
+        _ => None,
+    };
+    let normal = match parts.next() {
+        Some(n) if !n.is_empty() => Some(resolve_index(n, normal_count, "normal")?),
+        _ => None,
+    };
+    if parts.next().is_some() {
+        return Err(format!("malformed face vertex '{}'", token));
+    }
+    Ok(Corner { position, uv, normal })
+}
+
+// OBJ indices are 1-based; negative ones count back from the latest element
+fn resolve_index(raw: &str, count: usize, what: &str) -> Result<usize, String> {
+    let index: i64 = raw.parse().map_err(|_| format!("invalid {} index '{}'", what, raw))?;
+    let resolved = if index > 0 { index - 1 } else { count as i64 + index };
+    if index == 0 || resolved < 0 || resolved >= count as i64 {
+        return Err(format!("{} index {} out of range ({} defined so far)", what, index, count));
+    }
+    Ok(resolved as usize)
+}
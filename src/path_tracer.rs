@@ -0,0 +1,316 @@
+use raylib::prelude::*;
+use crate::color::LinearColor;
+use crate::light::LightSample;
+use crate::material::Material;
+use crate::pbr::Bsdf;
+use crate::ray_intersect::Intersect;
+use crate::renderer::{
+    cast_shadow, find_hit, fresnel, phong_brdf, reflect, refract, surface_color, RayCone, TraceContext,
+    SHADOW_BIAS,
+};
+use crate::sampling::{cosine_hemisphere, phong_lobe, power_heuristic, Rng};
+use std::f32::consts::PI;
+
+// Bounces that always happen before Russian roulette may end a path
+const ROULETTE_START: u32 = 3;
+// Safety net for paths trapped between mirrors; roulette ends nearly all
+// paths long before this
+const MAX_BOUNCES: u32 = 32;
+
+// Monte Carlo estimate of the radiance arriving along one camera ray.
+//
+// Materials keep the Whitted tracer's split into a local Phong part, a perfect
+// mirror and a refraction, weighted the same way, but light now bounces
+// between surfaces:
+// - the local part samples cosine-weighted (diffuse) or Phong-lobe (glossy)
+//   directions;
+// - every local vertex also samples each light and the sky directly
+//   (next-event estimation), and lights that rays can hit combine both
+//   estimates with multiple importance sampling;
+// - after a few bounces Russian roulette ends paths in proportion to the
+//   light they can still carry, which keeps the estimate unbiased.
+//
+// Metallic-roughness materials (`pbr.rs`) replace that split with their GGX
+// lobes: diffuse, glossy reflection and rough transmission.
+//
+// Lights stay invisible to camera rays, as in the Whitted tracer; glowing
+// materials are seen directly and sampled like area lights.
+pub fn trace_path(
+    ray_origin: &Vector3,
+    ray_direction: &Vector3,
+    ctx: &TraceContext,
+    rng: &mut Rng,
+    cone: RayCone,
+) -> LinearColor {
+    let mut radiance = LinearColor::BLACK;
+    let mut throughput = LinearColor::new(1.0, 1.0, 1.0);
+    let mut origin = *ray_origin;
+    let mut direction = ray_direction.normalized();
+    let mut cone = cone;
+    // Density of the BSDF sample that chose `direction`; `None` for camera
+    // rays and after mirror or glass bounces, which light sampling can't reach
+    let mut bsdf_pdf: Option<f32> = None;
+
+    for bounce in 0..MAX_BOUNCES {
+        let hit = find_hit(ctx.scene, &origin, &direction);
+        let hit_distance = hit.as_ref().map_or(f32::INFINITY, |(intersect, _)| intersect.distance);
+
+        // Light surfaces crossed before the next hit. Lights don't block
+        // rays, so the path carries on behind them.
+        if bounce > 0 {
+            for light in ctx.lights {
+                if let Some(light_pdf) = light.hit(origin, direction, hit_distance) {
+                    let weight = bsdf_pdf.map_or(1.0, |pdf| power_heuristic(pdf, light_pdf));
+                    radiance += throughput * light.emitted(origin) * weight;
+                }
+            }
+        }
+
+        // Escaping paths see the sky, which NEE also samples after a local
+        // bounce
+        let Some((intersect, object)) = hit else {
+            let weight = bsdf_pdf.map_or(1.0, |pdf| power_heuristic(pdf, ctx.environment.pdf(&direction)));
+            radiance += throughput * ctx.environment.radiance(&direction) * weight;
+            break;
+        };
+
+        let material = intersect.material;
+
+        // Glowing surfaces seen from outside. After a local bounce, NEE
+        // could have found the same point, so the two are MIS-weighted.
+        let cos_emitter = -direction.dot(intersect.normal);
+        if material.is_emissive() && cos_emitter > 0.0 {
+            let weight = match (bsdf_pdf, ctx.scene.emitter_of(object)) {
+                (Some(pdf), Some(emitter)) => power_heuristic(pdf, emitter.pdf(intersect.distance, cos_emitter)),
+                _ => 1.0,
+            };
+            radiance += throughput * material.emitted() * weight;
+        }
+
+        let albedo = surface_color(object, &intersect, &direction, ctx.textures, cone);
+        let intersect = Intersect {
+            normal: object.shading_normal(&intersect, ctx.textures),
+            ..intersect
+        };
+        cone = cone.advanced(intersect.distance);
+
+        // Shade with the normal on the side the ray arrived from
+        let entering = direction.dot(intersect.normal) < 0.0;
+        let normal = if entering { intersect.normal } else { -intersect.normal };
+        let view_dir = -direction;
+
+        // Metallic-roughness materials sample their own lobes
+        if let Some(pbr) = material.pbr {
+            let bsdf = Bsdf::new(pbr, albedo, normal, intersect.tangent, view_dir, entering);
+            let local = |light_dir: &Vector3| (bsdf.eval(light_dir), Some(bsdf.pdf(light_dir)));
+            radiance += throughput * direct_light(&intersect, &normal, local, ctx, rng);
+
+            let Some(sample) = bsdf.sample(rng) else {
+                break;
+            };
+            throughput = throughput * sample.weight;
+            bsdf_pdf = sample.pdf;
+            let side = if sample.direction.dot(normal) < 0.0 { -normal } else { normal };
+            origin = intersect.point + side * SHADOW_BIAS;
+            direction = sample.direction;
+        } else {
+            let lobes = Lobes::new(&material, albedo, &direction, &intersect.normal);
+            if lobes.total <= 0.0 {
+                break;
+            }
+
+            if lobes.local > 0.0 {
+                // Lights that rays can hit are also found by BSDF sampling
+                let local = |light_dir: &Vector3| {
+                    let brdf = phong_brdf(&material, albedo, &normal, light_dir, &view_dir);
+                    let pdf = lobes.local_pdf(&normal, &view_dir, light_dir) * lobes.local / lobes.total;
+                    (brdf * lobes.local, Some(pdf))
+                };
+                radiance += throughput * direct_light(&intersect, &normal, local, ctx, rng);
+            }
+
+            let pick = rng.next_f32() * lobes.total;
+            if pick < lobes.local {
+                let Some((next, pdf)) = lobes.sample_local(&normal, &view_dir, rng) else {
+                    break;
+                };
+                let brdf = phong_brdf(&material, albedo, &normal, &next, &view_dir);
+                // BSDF = local * brdf, chosen with probability local / total
+                throughput = throughput * brdf * (normal.dot(next) * lobes.total / pdf);
+                bsdf_pdf = Some(pdf * lobes.local / lobes.total);
+                origin = intersect.point + normal * SHADOW_BIAS;
+                direction = next;
+            } else if pick < lobes.local + lobes.reflect {
+                throughput = throughput * lobes.total;
+                bsdf_pdf = None;
+                origin = intersect.point + normal * SHADOW_BIAS;
+                direction = reflect(&direction, &normal);
+            } else {
+                throughput = throughput * lobes.total;
+                bsdf_pdf = None;
+                // Same convention as the Whitted tracer: entering uses 1 / ior
+                let eta = if entering { 1.0 / material.refractive_index } else { material.refractive_index };
+                match refract(&direction, &normal, eta) {
+                    Some(refracted) => {
+                        origin = intersect.point - normal * SHADOW_BIAS;
+                        direction = refracted.normalized();
+                    }
+                    None => {
+                        origin = intersect.point + normal * SHADOW_BIAS;
+                        direction = reflect(&direction, &normal);
+                    }
+                }
+            }
+        }
+
+        if bounce >= ROULETTE_START {
+            let survival = throughput.r.max(throughput.g).max(throughput.b).clamp(0.05, 0.95);
+            if rng.next_f32() >= survival {
+                break;
+            }
+            throughput = throughput * (1.0 / survival);
+        }
+    }
+
+    radiance
+}
+
+// How a material's response splits into the local Phong part, the mirror and
+// the refraction, with the same weights the Whitted tracer blends them with
+struct Lobes {
+    local: f32,
+    reflect: f32,
+    total: f32,
+    // Within the local part: chance of sampling the diffuse lobe
+    diffuse_probability: f32,
+    exponent: f32,
+}
+
+impl Lobes {
+    fn new(material: &Material, albedo: LinearColor, direction: &Vector3, normal: &Vector3) -> Self {
+        let transparent = material.albedo[3] > 0.01 && material.transparency > 0.01;
+        let kr = if material.transparency > 0.0 {
+            fresnel(direction, normal, material.refractive_index)
+        } else {
+            material.albedo[2]
+        };
+        let refract = if transparent { (1.0 - kr) * material.transparency } else { 0.0 };
+
+        let diffuse_weight = material.albedo[0] * albedo.luminance();
+        let specular_weight = material.albedo[1];
+        let mut local = (1.0 - material.albedo[2] - material.albedo[3]).max(0.0);
+        if diffuse_weight + specular_weight <= 0.0 {
+            local = 0.0;
+        }
+        let diffuse_probability = if local > 0.0 { diffuse_weight / (diffuse_weight + specular_weight) } else { 0.0 };
+
+        Lobes {
+            local,
+            reflect: kr,
+            total: local + kr + refract,
+            diffuse_probability,
+            exponent: material.specular,
+        }
+    }
+
+    // Solid-angle density of `sample_local` choosing `direction`
+    fn local_pdf(&self, normal: &Vector3, view_dir: &Vector3, direction: &Vector3) -> f32 {
+        let cos_theta = normal.dot(*direction).max(0.0);
+        let mirror = reflect(&-*view_dir, normal);
+        let cos_alpha = mirror.dot(*direction).max(0.0);
+        self.diffuse_probability * cos_theta / PI
+            + (1.0 - self.diffuse_probability) * (self.exponent + 1.0) / (2.0 * PI) * cos_alpha.powf(self.exponent)
+    }
+
+    // Direction for the local part and its density, or `None` below the surface
+    fn sample_local(&self, normal: &Vector3, view_dir: &Vector3, rng: &mut Rng) -> Option<(Vector3, f32)> {
+        let (u, v) = (rng.next_f32(), rng.next_f32());
+        let direction = if rng.next_f32() < self.diffuse_probability {
+            cosine_hemisphere(*normal, u, v)
+        } else {
+            phong_lobe(reflect(&-*view_dir, normal), self.exponent, u, v)
+        };
+        if normal.dot(direction) <= 0.0 {
+            return None;
+        }
+        let pdf = self.local_pdf(normal, view_dir, &direction);
+        (pdf > 1e-8).then_some((direction, pdf))
+    }
+}
+
+// Next-event estimation: one shadow ray per light, per glowing object and
+// towards the sky from a surface vertex. `local` gives the BSDF towards a light direction and the
+// density BSDF sampling would pick it with, used to MIS-weight lights that
+// rays can hit.
+fn direct_light(
+    intersect: &Intersect,
+    normal: &Vector3,
+    local: impl Fn(&Vector3) -> (LinearColor, Option<f32>),
+    ctx: &TraceContext,
+    rng: &mut Rng,
+) -> LinearColor {
+    let mut total = LinearColor::BLACK;
+    for light in ctx.lights {
+        let Some(sample) = light.sample(intersect.point, rng.next_f32(), rng.next_f32()) else {
+            continue;
+        };
+        let cos_incoming = normal.dot(sample.direction);
+        if cos_incoming <= 0.0 || cast_shadow(intersect, &sample, ctx.scene) {
+            continue;
+        }
+
+        let (bsdf, bsdf_pdf) = local(&sample.direction);
+        let weight = match (sample.pdf, bsdf_pdf) {
+            (Some(light_pdf), Some(bsdf_pdf)) => power_heuristic(light_pdf, bsdf_pdf),
+            _ => 1.0,
+        };
+        total += bsdf * light.color.to_linear() * (light.intensity * sample.weight * cos_incoming * weight);
+    }
+
+    for emitter in ctx.scene.emitters() {
+        let (s, t, pick) = (rng.next_f32(), rng.next_f32(), rng.next_f32());
+        let Some((sample, emitted)) = emitter.sample(ctx.scene, intersect.point, s, t, pick) else {
+            continue;
+        };
+        let cos_incoming = normal.dot(sample.direction);
+        if cos_incoming <= 0.0 || cast_shadow(intersect, &sample, ctx.scene) {
+            continue;
+        }
+
+        let (bsdf, bsdf_pdf) = local(&sample.direction);
+        let light_pdf = sample.pdf.unwrap_or(0.0);
+        let weight = bsdf_pdf.map_or(1.0, |bsdf_pdf| power_heuristic(light_pdf, bsdf_pdf));
+        total += bsdf * emitted * (sample.weight * cos_incoming * weight);
+    }
+
+    // The sky, picked in proportion to its brightness
+    if let Some((direction, light_pdf)) = ctx.environment.sample(rng.next_f32(), rng.next_f32()) {
+        let cos_incoming = normal.dot(direction);
+        let sample = LightSample { direction, distance: f32::INFINITY, weight: 1.0, pdf: Some(light_pdf) };
+        if cos_incoming > 0.0 && !cast_shadow(intersect, &sample, ctx.scene) {
+            let (bsdf, bsdf_pdf) = local(&direction);
+            let weight = bsdf_pdf.map_or(1.0, |bsdf_pdf| power_heuristic(light_pdf, bsdf_pdf));
+            total += bsdf * ctx.environment.radiance(&direction) * (cos_incoming * weight / light_pdf);
+        }
+    }
+    total
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::sampling::power_heuristic;
+
+    #[test]
+    fn mis_weights_of_both_strategies_sum_to_one() {
+        let pdfs = [1e-4, 0.05, 0.3, 1.0, 2.5, 40.0, 3e4];
+        for light_pdf in pdfs {
+            for bsdf_pdf in pdfs {
+                let sum = power_heuristic(light_pdf, bsdf_pdf) + power_heuristic(bsdf_pdf, light_pdf);
+                assert!((sum - 1.0).abs() < 1e-5, "pdfs {light_pdf} and {bsdf_pdf}: weights sum to {sum}");
+            }
+            // A strategy that can't produce the direction leaves it all to the other
+            assert_eq!(power_heuristic(light_pdf, 0.0), 1.0);
+            assert_eq!(power_heuristic(0.0, light_pdf), 0.0);
+        }
+    }
+}
@@ -0,0 +1,520 @@
+use raylib::prelude::*;
+use crate::color::LinearColor;
+use crate::material::Material;
+use crate::renderer::{reflect, refract};
+use crate::sampling::{cosine_hemisphere, tangent_frame, Rng};
+use std::f32::consts::PI;
+
+// Below this GGX alpha the highlight of a point light is narrower than a
+// float can resolve; smoother surfaces are clamped to it
+const MIN_ALPHA: f32 = 1e-3;
+// Index of refraction used when a material doesn't have one (F0 = 4%)
+const DEFAULT_IOR: f32 = 1.5;
+
+// Metallic-roughness material, the model of glTF and most game engines. The
+// base colour comes from the texture or the material's diffuse colour.
+// - metallic: 0 = dielectric (diffuse under a clear coat of Fresnel
+//   reflection), 1 = metal (coloured reflection only)
+// - roughness: perceptual, squared into the GGX alpha; 0 is a mirror
+// - transmission: share of the dielectric's diffuse replaced by (rough)
+//   refraction, tinted by the base colour
+// - anisotropy: 0 = round highlights; towards 1 the surface gets rougher
+//   along its tangent and highlights stretch that way, as on brushed metal
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Pbr {
+    pub metallic: f32,
+    pub roughness: f32,
+    pub transmission: f32,
+    pub ior: f32,
+    pub anisotropy: f32,
+    // Brushing direction in the object's space. Without one the tangent
+    // follows the surface's U axis (see `Intersect::tangent`).
+    pub tangent: Option<Vector3>,
+}
+
+impl Pbr {
+    pub fn new(metallic: f32, roughness: f32) -> Self {
+        Pbr {
+            metallic: metallic.clamp(0.0, 1.0),
+            roughness: roughness.clamp(0.0, 1.0),
+            transmission: 0.0,
+            ior: DEFAULT_IOR,
+            anisotropy: 0.0,
+            tangent: None,
+        }
+    }
+
+    pub fn with_transmission(mut self, transmission: f32, ior: f32) -> Self {
+        self.transmission = transmission.clamp(0.0, 1.0);
+        self.ior = ior.max(1.0);
+        self
+    }
+
+    pub fn with_anisotropy(mut self, anisotropy: f32, tangent: Option<Vector3>) -> Self {
+        self.anisotropy = anisotropy.clamp(0.0, 1.0);
+        self.tangent = tangent.map(|t| t.normalized());
+        self
+    }
+
+    // Best-effort equivalent of a Phong material. The weights of the old
+    // model don't carry over directly (`mirror` has a specular weight of 5),
+    // so they are only read as hints:
+    // - transparent materials become clear dielectrics with transmission
+    //   equal to their transparency (the old refraction was perfect);
+    // - opaque ones are metallic when mirror reflection and highlights
+    //   dominate the diffuse weight (chrome_mirror, brushed_metal, mirror);
+    // - roughness follows the Phong exponent (Walter's alpha = sqrt(2 /
+    //   (n + 2))), sharpened by how much of the non-highlight light the
+    //   mirror reflects and pushed towards 1 by the diffuse share of
+    //   dielectrics (zen_moss, concrete_base).
+    pub fn from_phong(material: &Material) -> Self {
+        let [diffuse, specular, reflective, refractive] = material.albedo.map(|w| w.max(0.0));
+        let ior = if material.refractive_index > 1.0 { material.refractive_index } else { DEFAULT_IOR };
+
+        if refractive > 0.01 && material.transparency > 0.01 {
+            return Pbr::new(0.0, 0.0).with_transmission(material.transparency, ior);
+        }
+
+        let total = (diffuse + specular + reflective).max(1e-6);
+        let shine = (reflective + 0.5 * specular) / (diffuse + 0.5 * specular + reflective).max(1e-6);
+        let metallic = smoothstep(0.3, 0.4, shine);
+
+        let phong_roughness = (2.0 / (material.specular.max(0.0) + 2.0)).powf(0.25);
+        let sharpened = phong_roughness * (1.0 - reflective / (diffuse + reflective).max(1e-6));
+        let diffuse_share = diffuse / total * (1.0 - metallic);
+        let roughness = sharpened + (1.0 - sharpened) * diffuse_share;
+
+        Pbr {
+            ior,
+            ..Pbr::new(metallic, roughness)
+        }
+    }
+
+    // GGX alphas along the tangent and the bitangent. The aspect ratio
+    // follows Burley's remapping, which keeps the highlight's area roughly
+    // constant as anisotropy grows.
+    fn alphas(&self) -> (f32, f32) {
+        let alpha = self.roughness * self.roughness;
+        let aspect = (1.0 - 0.9 * self.anisotropy).sqrt();
+        ((alpha / aspect).max(MIN_ALPHA), (alpha * aspect).max(MIN_ALPHA))
+    }
+}
+
+fn smoothstep(edge0: f32, edge1: f32, x: f32) -> f32 {
+    let t = ((x - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+// Unpolarised Fresnel reflectance of a dielectric. `eta` is the ratio of
+// the indices on the incident and transmitted sides, as in `refract`.
+pub fn fresnel_dielectric(cos_incident: f32, eta: f32) -> f32 {
+    let cos_i = cos_incident.clamp(0.0, 1.0);
+    let sin_t2 = eta * eta * (1.0 - cos_i * cos_i);
+    if sin_t2 >= 1.0 {
+        return 1.0; // Reflexión total interna
+    }
+    let cos_t = (1.0 - sin_t2).sqrt();
+    let r_s = (eta * cos_i - cos_t) / (eta * cos_i + cos_t);
+    let r_p = (cos_i - eta * cos_t) / (cos_i + eta * cos_t);
+    (r_s * r_s + r_p * r_p) / 2.0
+}
+
+// Schlick's approximation, used for metals whose F0 is their colour
+fn fresnel_schlick(f0: LinearColor, cos_theta: f32) -> LinearColor {
+    let m = (1.0 - cos_theta.clamp(0.0, 1.0)).powi(5);
+    f0 * (1.0 - m) + LinearColor::new(m, m, m)
+}
+
+// Shading frame with the GGX roughness along each tangent axis. Directions
+// are world-space unit vectors; the microfacet terms read them in the frame.
+#[derive(Debug, Clone, Copy)]
+struct Microfacet {
+    tangent: Vector3,
+    bitangent: Vector3,
+    normal: Vector3,
+    alpha_x: f32,
+    alpha_y: f32,
+}
+
+impl Microfacet {
+    fn new(normal: Vector3, tangent: Option<Vector3>, (alpha_x, alpha_y): (f32, f32)) -> Self {
+        // Gram-Schmidt against the shading normal, or any tangent when the
+        // surface has none (isotropic materials don't care which)
+        let tangent = tangent
+            .map(|t| t - normal * normal.dot(t))
+            .filter(|t| t.length() > 1e-6)
+            .map_or_else(|| tangent_frame(normal).0, |t| t.normalized());
+        Microfacet {
+            tangent,
+            bitangent: normal.cross(tangent),
+            normal,
+            alpha_x,
+            alpha_y,
+        }
+    }
+
+    fn local(&self, w: Vector3) -> Vector3 {
+        Vector3::new(w.dot(self.tangent), w.dot(self.bitangent), w.dot(self.normal))
+    }
+
+    fn world(&self, w: Vector3) -> Vector3 {
+        (self.tangent * w.x + self.bitangent * w.y + self.normal * w.z).normalized()
+    }
+
+    // Anisotropic GGX (Trowbridge-Reitz) density of microfacet normal `m`
+    fn d(&self, m: Vector3) -> f32 {
+        let m = self.local(m);
+        if m.z <= 0.0 {
+            return 0.0;
+        }
+        let (x, y) = (m.x / self.alpha_x, m.y / self.alpha_y);
+        let k = x * x + y * y + m.z * m.z;
+        1.0 / (PI * self.alpha_x * self.alpha_y * k * k)
+    }
+
+    // Smith's Lambda for GGX
+    fn lambda(&self, w: Vector3) -> f32 {
+        let w = self.local(w);
+        let cos2 = (w.z * w.z).max(1e-8);
+        let tan2_alpha2 = (self.alpha_x * self.alpha_x * w.x * w.x + self.alpha_y * self.alpha_y * w.y * w.y) / cos2;
+        ((1.0 + tan2_alpha2).sqrt() - 1.0) / 2.0
+    }
+
+    // Masking of one direction
+    fn g1(&self, w: Vector3) -> f32 {
+        1.0 / (1.0 + self.lambda(w))
+    }
+
+    // Height-correlated masking-shadowing of the view and light directions
+    fn g2(&self, view: Vector3, light: Vector3) -> f32 {
+        1.0 / (1.0 + self.lambda(view) + self.lambda(light))
+    }
+
+    // Microfacet normal drawn from the normals visible from `view` (Heitz
+    // 2018), so that samples never face away from the viewer
+    fn sample_visible(&self, view: Vector3, u: f32, v: f32) -> Vector3 {
+        let local = self.local(view);
+
+        // Stretch to the hemisphere configuration
+        let vh = Vector3::new(self.alpha_x * local.x, self.alpha_y * local.y, local.z).normalized();
+        let length2 = vh.x * vh.x + vh.y * vh.y;
+        let t1 = if length2 > 0.0 {
+            Vector3::new(-vh.y, vh.x, 0.0) / length2.sqrt()
+        } else {
+            Vector3::new(1.0, 0.0, 0.0)
+        };
+        let t2 = vh.cross(t1);
+
+        // Point on the projected half disk
+        let r = u.sqrt();
+        let phi = 2.0 * PI * v;
+        let p1 = r * phi.cos();
+        let s = 0.5 * (1.0 + vh.z);
+        let p2 = (1.0 - s) * (1.0 - p1 * p1).max(0.0).sqrt() + s * r * phi.sin();
+        let nh = t1 * p1 + t2 * p2 + vh * (1.0 - p1 * p1 - p2 * p2).max(0.0).sqrt();
+
+        // Unstretch back to the ellipsoid
+        self.world(Vector3::new(self.alpha_x * nh.x, self.alpha_y * nh.y, nh.z.max(0.0)))
+    }
+}
+
+// A direction scattered by `Bsdf::sample` and its Monte Carlo weight (BSDF *
+// cosine / pdf). `pdf` is the solid-angle density for MIS, or `None` for
+// transmission, which light sampling can't reach.
+#[derive(Debug, Clone, Copy)]
+pub struct BsdfSample {
+    pub direction: Vector3,
+    pub weight: LinearColor,
+    pub pdf: Option<f32>,
+}
+
+// A `Pbr` material at one shading point. `normal` faces the viewer and
+// `eta` is the ratio of indices across the surface (1 / ior when entering).
+// `tangent` orients anisotropic highlights.
+#[derive(Debug, Clone, Copy)]
+pub struct Bsdf {
+    pbr: Pbr,
+    base_color: LinearColor,
+    normal: Vector3,
+    view: Vector3,
+    eta: f32,
+    microfacet: Microfacet,
+    // Chance of sampling each lobe
+    diffuse_probability: f32,
+    specular_probability: f32,
+    transmission_probability: f32,
+}
+
+impl Bsdf {
+    pub fn new(
+        pbr: Pbr,
+        base_color: LinearColor,
+        normal: Vector3,
+        tangent: Option<Vector3>,
+        view: Vector3,
+        entering: bool,
+    ) -> Self {
+        let eta = if entering { 1.0 / pbr.ior } else { pbr.ior };
+        let cos_view = normal.dot(view).max(0.0);
+
+        // Lobes weighted by roughly the energy they reflect towards the viewer
+        let fresnel = fresnel_dielectric(cos_view, eta);
+        let dielectric = 1.0 - pbr.metallic;
+        let specular = dielectric * fresnel + pbr.metallic * fresnel_schlick(base_color, cos_view).luminance();
+        let diffuse = dielectric * (1.0 - pbr.transmission) * (1.0 - fresnel) * base_color.luminance();
+        let transmission = dielectric * pbr.transmission * (1.0 - fresnel);
+        let total = (diffuse + specular + transmission).max(1e-6);
+
+        Bsdf {
+            pbr,
+            base_color,
+            normal,
+            view,
+            eta,
+            microfacet: Microfacet::new(normal, tangent, pbr.alphas()),
+            diffuse_probability: diffuse / total,
+            specular_probability: specular / total,
+            transmission_probability: transmission / total,
+        }
+    }
+
+    // Reflected part of the BSDF for light arriving from `light`: Lambert
+    // under the Fresnel-weighted GGX coat for dielectrics, coloured GGX
+    // reflection for metals
+    pub fn eval(&self, light: &Vector3) -> LinearColor {
+        let (diffuse, glossy) = self.eval_lobes(light);
+        diffuse + glossy
+    }
+
+    // `eval` split into the diffuse base and the glossy reflection, which the
+    // Whitted tracer also reaches through `sample_reflection`
+    pub fn eval_lobes(&self, light: &Vector3) -> (LinearColor, LinearColor) {
+        let cos_view = self.normal.dot(self.view);
+        let cos_light = self.normal.dot(*light);
+        if cos_view <= 0.0 || cos_light <= 0.0 {
+            return (LinearColor::BLACK, LinearColor::BLACK);
+        }
+        let half = (self.view + *light).normalized();
+        let view_half = self.view.dot(half).max(0.0);
+
+        let mf = &self.microfacet;
+        let specular = mf.d(half) * mf.g2(self.view, *light) / (4.0 * cos_view * cos_light);
+        let fresnel = fresnel_dielectric(view_half, self.eta);
+        // Light entering the diffuse base crosses the coat twice; weighing
+        // by the macro-surface Fresnel of both directions keeps grazing
+        // angles from reflecting more than they receive
+        let coat = (1.0 - fresnel_dielectric(cos_view, self.eta)) * (1.0 - fresnel_dielectric(cos_light, self.eta));
+        let diffuse = self.base_color * ((1.0 - self.pbr.transmission) * coat / PI);
+        let dielectric = LinearColor::new(1.0, 1.0, 1.0) * (specular * fresnel);
+        let metal = fresnel_schlick(self.base_color, view_half) * specular;
+
+        let dielectric_share = 1.0 - self.pbr.metallic;
+        (diffuse * dielectric_share, dielectric * dielectric_share + metal * self.pbr.metallic)
+    }
+
+    // Density of `sample` choosing `light` among reflected directions
+    pub fn pdf(&self, light: &Vector3) -> f32 {
+        let cos_view = self.normal.dot(self.view);
+        let cos_light = self.normal.dot(*light);
+        if cos_view <= 0.0 || cos_light <= 0.0 {
+            return 0.0;
+        }
+        self.diffuse_probability * cos_light / PI + self.specular_probability * self.reflection_pdf(light)
+    }
+
+    // Density of `sample_reflection` choosing `light`: the visible-normal
+    // density, converted from the half vector to `light`
+    pub fn reflection_pdf(&self, light: &Vector3) -> f32 {
+        let cos_view = self.normal.dot(self.view);
+        if cos_view <= 0.0 || self.normal.dot(*light) <= 0.0 {
+            return 0.0;
+        }
+        let half = (self.view + *light).normalized();
+        self.microfacet.g1(self.view) * self.microfacet.d(half) / (4.0 * cos_view)
+    }
+
+    // Glossy reflection around the mirror direction and the share of light it
+    // carries (Fresnel times masking), for tracers that follow every lobe
+    pub fn sample_reflection(&self, u: f32, v: f32) -> Option<(Vector3, LinearColor)> {
+        let micro = self.microfacet.sample_visible(self.view, u, v);
+        let direction = reflect(&-self.view, &micro);
+        let cos_light = self.normal.dot(direction);
+        if cos_light <= 0.0 {
+            return None;
+        }
+        let view_half = self.view.dot(micro).max(0.0);
+        let dielectric = fresnel_dielectric(view_half, self.eta) * (1.0 - self.pbr.metallic);
+        let metal = fresnel_schlick(self.base_color, view_half) * self.pbr.metallic;
+        let weight = (metal + LinearColor::new(dielectric, dielectric, dielectric)) * self.microfacet.g1(direction);
+        Some((direction, weight))
+    }
+
+    // Rough refraction through a microfacet and the share of light it
+    // carries, or `None` for opaque materials. Total internal reflection
+    // sends the light back instead.
+    pub fn sample_transmission(&self, u: f32, v: f32) -> Option<(Vector3, LinearColor)> {
+        let share = (1.0 - self.pbr.metallic) * self.pbr.transmission;
+        if share <= 0.0 {
+            return None;
+        }
+        let micro = self.microfacet.sample_visible(self.view, u, v);
+        let view_half = self.view.dot(micro).max(0.0);
+        let (direction, fresnel) = match refract(&-self.view, &micro, self.eta) {
+            Some(refracted) if self.normal.dot(refracted) < 0.0 => (refracted.normalized(), fresnel_dielectric(view_half, self.eta)),
+            _ => (reflect(&-self.view, &micro), 0.0),
+        };
+        // Lambda only depends on the squared components, so the far side of
+        // the surface masks the same way
+        let masking = self.microfacet.g1(direction);
+        Some((direction, self.base_color * (share * (1.0 - fresnel) * masking)))
+    }
+
+    // Pick a lobe and a direction from it, for the path tracer. Reflected
+    // directions are weighted with the density of both reflection lobes, so
+    // `pdf` can be used for MIS against light sampling.
+    pub fn sample(&self, rng: &mut Rng) -> Option<BsdfSample> {
+        let pick = rng.next_f32();
+        let (u, v) = (rng.next_f32(), rng.next_f32());
+
+        if pick >= self.diffuse_probability + self.specular_probability {
+            let (direction, weight) = self.sample_transmission(u, v)?;
+            return Some(BsdfSample {
+                direction,
+                weight: weight * (1.0 / self.transmission_probability.max(1e-6)),
+                pdf: None,
+            });
+        }
+
+        let direction = if pick < self.diffuse_probability {
+            cosine_hemisphere(self.normal, u, v)
+        } else {
+            reflect(&-self.view, &self.microfacet.sample_visible(self.view, u, v))
+        };
+        let pdf = self.pdf(&direction);
+        if pdf <= 1e-8 {
+            return None;
+        }
+        let weight = self.eval(&direction) * (self.normal.dot(direction) / pdf);
+        Some(BsdfSample { direction, weight, pdf: Some(pdf) })
+    }
+
+    // Share of the sky's diffuse irradiance the Whitted tracer adds
+    pub fn ambient_share(&self) -> f32 {
+        (1.0 - self.pbr.metallic) * (1.0 - self.pbr.transmission)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NORMAL: Vector3 = Vector3::new(0.0, 1.0, 0.0);
+    const TANGENT: Vector3 = Vector3::new(1.0, 0.0, 0.0);
+
+    // Direction at `cos_theta` from the normal and `phi` around it, measured
+    // from the tangent
+    fn direction(cos_theta: f32, phi: f32) -> Vector3 {
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let bitangent = NORMAL.cross(TANGENT);
+        TANGENT * (sin_theta * phi.cos()) + bitangent * (sin_theta * phi.sin()) + NORMAL * cos_theta
+    }
+
+    fn bsdf(pbr: Pbr, base_color: LinearColor, cos_view: f32) -> Bsdf {
+        let view = direction(cos_view, 0.7);
+        Bsdf::new(pbr, base_color, NORMAL, Some(TANGENT), view, true)
+    }
+
+    // Integral of `f` over the hemisphere, with cells crowded towards the
+    // normal where GGX peaks (cos theta = 1 - t^2)
+    fn integrate(steps: usize, f: impl Fn(f32, f32) -> f32) -> f32 {
+        let (dt, dphi) = (1.0 / steps as f32, 2.0 * PI / steps as f32);
+        let mut sum = 0.0f64;
+        for i in 0..steps {
+            let t = (i as f32 + 0.5) * dt;
+            for j in 0..steps {
+                let phi = (j as f32 + 0.5) * dphi;
+                sum += (f(1.0 - t * t, phi) * 2.0 * t * dt * dphi) as f64;
+            }
+        }
+        sum as f32
+    }
+
+    // Which of the 4 x 4 bins over cos theta and phi a direction falls in
+    fn bin(cos_theta: f32, phi: f32) -> usize {
+        let row = ((cos_theta * 4.0) as usize).min(3);
+        let column = ((phi.rem_euclid(2.0 * PI) / (PI / 2.0)) as usize).min(3);
+        row * 4 + column
+    }
+
+    #[test]
+    fn pdf_matches_sampled_density() {
+        let configurations = [
+            (Pbr::new(1.0, 0.5).with_anisotropy(0.6, None), LinearColor::new(0.9, 0.6, 0.3), 0.8),
+            (Pbr::new(0.0, 0.6), LinearColor::new(0.5, 0.5, 0.5), 0.4),
+            (Pbr::new(0.3, 0.7).with_anisotropy(0.9, None), LinearColor::new(0.8, 0.8, 0.8), 0.2),
+        ];
+        for (pbr, base_color, cos_view) in configurations {
+            let bsdf = bsdf(pbr, base_color, cos_view);
+            let bitangent = NORMAL.cross(TANGENT);
+
+            let draws = 400_000;
+            let mut counts = [0usize; 16];
+            let mut rng = Rng::new(7, 1);
+            for _ in 0..draws {
+                if let Some(sample) = bsdf.sample(&mut rng) {
+                    let d = sample.direction;
+                    let phi = d.dot(bitangent).atan2(d.dot(TANGENT));
+                    counts[bin(d.dot(NORMAL), phi)] += 1;
+                }
+            }
+
+            for (index, &count) in counts.iter().enumerate() {
+                let expected = integrate(1024, |cos_theta, phi| {
+                    if bin(cos_theta, phi) == index { bsdf.pdf(&direction(cos_theta, phi)) } else { 0.0 }
+                });
+                let measured = count as f32 / draws as f32;
+                assert!(
+                    (measured - expected).abs() < 0.003,
+                    "{pbr:?} bin {index}: sampled {measured}, pdf integrates to {expected}"
+                );
+            }
+        }
+    }
+
+    // Single scattering only loses light to masking between microfacets, a
+    // lot of it on the roughest lobes, but never gains any
+    #[test]
+    fn white_metal_reflects_at_most_the_light_it_receives() {
+        let white = LinearColor::new(1.0, 1.0, 1.0);
+        for roughness in [0.1, 0.3, 0.6, 1.0] {
+            for anisotropy in [0.0, 0.5, 0.9] {
+                for cos_view in [0.9, 0.5, 0.15] {
+                    let pbr = Pbr::new(1.0, roughness).with_anisotropy(anisotropy, None);
+                    let bsdf = bsdf(pbr, white, cos_view);
+                    let mut rng = Rng::new(3, 5);
+                    let draws = 100_000;
+                    let mut sum = 0.0;
+                    for _ in 0..draws {
+                        if let Some(sample) = bsdf.sample(&mut rng) {
+                            sum += sample.weight.g;
+                        }
+                    }
+                    let albedo = sum / draws as f32;
+                    assert!(
+                        albedo <= 1.01 && albedo > 0.1,
+                        "roughness {roughness}, anisotropy {anisotropy}, cos {cos_view}: albedo {albedo}"
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn microfacet_normals_cover_the_surface_once() {
+        for alphas in [(0.1, 0.1), (0.3, 0.3), (0.6, 0.2), (1.0, 1.0)] {
+            let microfacet = Microfacet::new(NORMAL, Some(TANGENT), alphas);
+            let projected = integrate(2048, |cos_theta, phi| microfacet.d(direction(cos_theta, phi)) * cos_theta);
+            assert!((projected - 1.0).abs() < 0.01, "alphas {alphas:?}: D integrates to {projected}");
+        }
+    }
+}
@@ -0,0 +1,294 @@
+use raylib::prelude::*;
+use crate::color::LinearColor;
+use crate::light::Light;
+use std::f32::consts::PI;
+
+// Scene units per kcd/m² of sky luminance and per klux of sun illuminance.
+// A clear noon sun then gives a couple of W/m², in the range of the
+// diorama's own lights; "intensity" on the skybox rescales both together.
+const SKY_SCALE: f32 = 1.0 / 40.0;
+// Illuminance of the sun above the atmosphere, klux
+const SOLAR_ILLUMINANCE: f32 = 128.0;
+// Apparent radius of the sun's disk, radians
+const SUN_ANGULAR_RADIUS: f32 = 0.00465;
+// The sky fades to black over civil twilight, as the sun sinks 6° below
+// the horizon
+const TWILIGHT: f32 = 6.0 * PI / 180.0;
+// Wavelengths (µm) standing in for the red, green and blue channels when
+// attenuating sunlight
+const WAVELENGTHS: [f32; 3] = [0.680, 0.550, 0.440];
+
+// Where the sun stands: radians above the horizon, and compass azimuth in
+// radians from north (-Z) towards east (+X)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SunPosition {
+    pub elevation: f32,
+    pub azimuth: f32,
+}
+
+impl SunPosition {
+    pub fn new(elevation: f32, azimuth: f32) -> Self {
+        SunPosition { elevation, azimuth }
+    }
+
+    // Unit vector from the scene towards the sun
+    pub fn direction(&self) -> Vector3 {
+        let (sin_el, cos_el) = self.elevation.sin_cos();
+        let (sin_az, cos_az) = self.azimuth.sin_cos();
+        Vector3::new(sin_az * cos_el, sin_el, -cos_az * cos_el)
+    }
+}
+
+// Local solar time at a place and date, which fixes the sun's position
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SolarClock {
+    // Hours since midnight, 12 being solar noon
+    pub hours: f32,
+    // Radians, positive north of the equator
+    pub latitude: f32,
+    // Day of the year, 1 = January 1st
+    pub day: f32,
+}
+
+impl Default for SolarClock {
+    // Noon at 40° north on the March equinox, when the sun rises at 6:00
+    // and sets at 18:00
+    fn default() -> Self {
+        SolarClock {
+            hours: 12.0,
+            latitude: 40f32.to_radians(),
+            day: 80.0,
+        }
+    }
+}
+
+impl SolarClock {
+    pub fn sun_position(&self) -> SunPosition {
+        let declination = -23.44f32.to_radians() * (2.0 * PI * (self.day + 10.0) / 365.0).cos();
+        let hour_angle = (self.hours - 12.0) * PI / 12.0;
+        let (sin_lat, cos_lat) = self.latitude.sin_cos();
+        let (sin_dec, cos_dec) = declination.sin_cos();
+        let (sin_hour, cos_hour) = hour_angle.sin_cos();
+
+        let east = -cos_dec * sin_hour;
+        let north = sin_dec * cos_lat - cos_dec * cos_hour * sin_lat;
+        let up = sin_dec * sin_lat + cos_dec * cos_hour * cos_lat;
+        SunPosition::new(up.clamp(-1.0, 1.0).asin(), east.atan2(north))
+    }
+}
+
+// Clear daylight sky after Preetham, Shirley and Smits, "A Practical
+// Analytic Model for Daylight" (1999). Luminance and chromaticity follow the
+// Perez distribution around the sun; turbidity measures haze, from about 2
+// (very clear) to 10 (hazy). The model has no ground, so below the horizon
+// the sky shows a Lambertian ground of `ground_albedo` lit by the sky and sun.
+//
+// The sun itself is not part of the radiance: it is the directional light
+// `sun_light` returns, so it casts sharp shadows and can be sampled.
+#[derive(Debug, Clone)]
+pub struct PhysicalSky {
+    sun: SunPosition,
+    clock: Option<SolarClock>,
+    turbidity: f32,
+    ground_albedo: f32,
+    // Derived from the settings above by `update`
+    perez: [[f32; 5]; 3],
+    // Luminance Y and chromaticity x, y at the zenith, over their Perez
+    // value at the zenith
+    zenith: [f32; 3],
+    twilight: f32,
+    sun_color: LinearColor,
+    ground: LinearColor,
+}
+
+impl Default for PhysicalSky {
+    fn default() -> Self {
+        PhysicalSky::at_time(SolarClock::default())
+    }
+}
+
+impl PhysicalSky {
+    pub fn new(sun: SunPosition) -> Self {
+        let mut sky = PhysicalSky {
+            sun,
+            clock: None,
+            turbidity: 3.0,
+            ground_albedo: 0.3,
+            perez: [[0.0; 5]; 3],
+            zenith: [0.0; 3],
+            twilight: 1.0,
+            sun_color: LinearColor::BLACK,
+            ground: LinearColor::BLACK,
+        };
+        sky.update();
+        sky
+    }
+
+    // Sky whose sun follows the clock, so changing the time moves it
+    pub fn at_time(clock: SolarClock) -> Self {
+        let mut sky = PhysicalSky::new(clock.sun_position());
+        sky.clock = Some(clock);
+        sky.update();
+        sky
+    }
+
+    pub fn with_turbidity(mut self, turbidity: f32) -> Self {
+        self.turbidity = turbidity;
+        self.update();
+        self
+    }
+
+    pub fn with_ground_albedo(mut self, albedo: f32) -> Self {
+        self.ground_albedo = albedo;
+        self.update();
+        self
+    }
+
+    // Hours of the clock driving the sun, if any
+    pub fn time(&self) -> Option<f32> {
+        self.clock.map(|clock| clock.hours)
+    }
+
+    // Move the clock to `hours` (wrapped into a day). Skies given a fixed
+    // sun position have no clock and stay as they are.
+    pub fn set_time(&mut self, hours: f32) {
+        if let Some(clock) = &mut self.clock {
+            clock.hours = hours.rem_euclid(24.0);
+            self.sun = clock.sun_position();
+            self.update();
+        }
+    }
+
+    // Radiance arriving from the unit vector `direction`
+    pub fn radiance(&self, direction: &Vector3) -> LinearColor {
+        if direction.y < 0.0 {
+            return self.ground;
+        }
+        let cos_theta = direction.y.max(1e-4);
+        let cos_gamma = direction.dot(self.sun.direction()).clamp(-1.0, 1.0);
+        let gamma = cos_gamma.acos();
+        let [big_y, x, y] = [0, 1, 2].map(|i| self.zenith[i] * perez(&self.perez[i], cos_theta, gamma, cos_gamma));
+        xyy_to_linear(x, y, big_y * SKY_SCALE * self.twilight)
+    }
+
+    // Directional light for the sun after its way through the atmosphere,
+    // or None once it has set
+    pub fn sun_light(&self) -> Option<Light> {
+        if self.sun.elevation <= 0.0 {
+            return None;
+        }
+        let peak = self.sun_color.r.max(self.sun_color.g).max(self.sun_color.b);
+        if peak <= 0.0 {
+            return None;
+        }
+        let color = (self.sun_color * (1.0 / peak)).to_srgb();
+        Some(
+            Light::directional(-self.sun.direction(), color, peak)
+                .with_angular_radius(SUN_ANGULAR_RADIUS)
+                .with_samples(1),
+        )
+    }
+
+    // Recompute everything that depends on the sun, turbidity and albedo
+    fn update(&mut self) {
+        let t = self.turbidity.clamp(1.7, 10.0);
+        // Zenith angle of the sun; the sky model is only defined above the
+        // horizon, so a set sun is held there while the sky fades
+        let theta_s = (PI / 2.0 - self.sun.elevation).min(PI / 2.0);
+
+        self.perez = [
+            [0.1787 * t - 1.4630, -0.3554 * t + 0.4275, -0.0227 * t + 5.3251, 0.1206 * t - 2.5771, -0.0670 * t + 0.3703],
+            [-0.0193 * t - 0.2592, -0.0665 * t + 0.0008, -0.0004 * t + 0.2125, -0.0641 * t - 0.8989, -0.0033 * t + 0.0452],
+            [-0.0167 * t - 0.2608, -0.0950 * t + 0.0092, -0.0079 * t + 0.2102, -0.0441 * t - 1.6537, -0.0109 * t + 0.0529],
+        ];
+
+        let chi = (4.0 / 9.0 - t / 120.0) * (PI - 2.0 * theta_s);
+        let zenith_luminance = ((4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192).max(0.0);
+        let powers = [theta_s.powi(3), theta_s.powi(2), theta_s, 1.0];
+        let polynomial = |t2: [f32; 4], t1: [f32; 4], t0: [f32; 4]| -> f32 {
+            (0..4).map(|i| (t * t * t2[i] + t * t1[i] + t0[i]) * powers[i]).sum()
+        };
+        let zenith_x = polynomial(
+            [0.00166, -0.00375, 0.00209, 0.0],
+            [-0.02903, 0.06377, -0.03202, 0.00394],
+            [0.11693, -0.21196, 0.06052, 0.25886],
+        );
+        let zenith_y = polynomial(
+            [0.00275, -0.00610, 0.00317, 0.0],
+            [-0.04214, 0.08970, -0.04153, 0.00516],
+            [0.15346, -0.26756, 0.06670, 0.26688],
+        );
+        let zenith = [zenith_luminance, zenith_x, zenith_y];
+        // Divide by the Perez value at the zenith, where γ is θs
+        self.zenith = [0, 1, 2].map(|i| zenith[i] / perez(&self.perez[i], 1.0, theta_s, theta_s.cos()));
+
+        let fade = ((self.sun.elevation + TWILIGHT) / TWILIGHT).clamp(0.0, 1.0);
+        self.twilight = fade * fade * (3.0 - 2.0 * fade);
+
+        self.sun_color = if self.sun.elevation > 0.0 {
+            sun_transmittance(t, PI / 2.0 - self.sun.elevation) * (SOLAR_ILLUMINANCE * SKY_SCALE)
+        } else {
+            LinearColor::BLACK
+        };
+
+        // Ground: albedo / π times the sky's and the sun's irradiance on a
+        // horizontal plane
+        let sky_irradiance = self.sky_irradiance();
+        let sun_irradiance = self.sun_color * self.sun.elevation.sin().max(0.0);
+        self.ground = (sky_irradiance + sun_irradiance) * (self.ground_albedo.clamp(0.0, 1.0) / PI);
+    }
+
+    // Irradiance from the upper hemisphere of the sky on a horizontal plane,
+    // by the midpoint rule
+    fn sky_irradiance(&self) -> LinearColor {
+        const ROWS: usize = 32;
+        const COLUMNS: usize = 64;
+        let mut irradiance = LinearColor::BLACK;
+        for row in 0..ROWS {
+            let theta = (row as f32 + 0.5) / ROWS as f32 * PI / 2.0;
+            let (sin_theta, cos_theta) = theta.sin_cos();
+            let solid_angle = (PI / 2.0 / ROWS as f32) * (2.0 * PI / COLUMNS as f32) * sin_theta;
+            for column in 0..COLUMNS {
+                let phi = (column as f32 + 0.5) / COLUMNS as f32 * 2.0 * PI;
+                let direction = Vector3::new(sin_theta * phi.cos(), cos_theta, sin_theta * phi.sin());
+                irradiance += self.radiance(&direction) * (cos_theta * solid_angle);
+            }
+        }
+        irradiance
+    }
+}
+
+// Perez et al. luminance distribution for a view at zenith angle θ and
+// angle γ from the sun
+fn perez(c: &[f32; 5], cos_theta: f32, gamma: f32, cos_gamma: f32) -> f32 {
+    (1.0 + c[0] * (c[1] / cos_theta).exp()) * (1.0 + c[2] * (c[3] * gamma).exp() + c[4] * cos_gamma * cos_gamma)
+}
+
+// CIE xyY to linear sRGB, through XYZ
+fn xyy_to_linear(x: f32, y: f32, luminance: f32) -> LinearColor {
+    if y <= 0.0 {
+        return LinearColor::BLACK;
+    }
+    let big_x = x / y * luminance;
+    let big_z = (1.0 - x - y) / y * luminance;
+    LinearColor::new(
+        3.2406 * big_x - 1.5372 * luminance - 0.4986 * big_z,
+        -0.9689 * big_x + 1.8758 * luminance + 0.0415 * big_z,
+        0.0557 * big_x - 0.2040 * luminance + 1.0570 * big_z,
+    )
+    .map(|c| c.max(0.0))
+}
+
+// Fraction of sunlight per channel that survives Rayleigh and aerosol
+// scattering along the optical air mass for a sun at zenith angle θs
+fn sun_transmittance(turbidity: f32, theta_s: f32) -> LinearColor {
+    let air_mass = 1.0 / (theta_s.cos() + 0.15 * (93.885 - theta_s.to_degrees()).powf(-1.253));
+    // Ångström turbidity coefficient, with wavelength exponent 1.3
+    let beta = 0.04608 * turbidity - 0.04586;
+    let [r, g, b] = WAVELENGTHS.map(|lambda| {
+        let rayleigh = 0.008735 * lambda.powf(-4.08);
+        let aerosol = beta * lambda.powf(-1.3);
+        (-(rayleigh + aerosol) * air_mass).exp()
+    });
+    LinearColor::new(r, g, b)
+}
@@ -1,5 +1,6 @@
 use raylib::prelude::*;
 use crate::material::Material;
+use crate::assets::TextureHandle;
 
 #[derive(Debug, Clone)]
 pub struct Intersect {
@@ -8,6 +9,15 @@ pub struct Intersect {
     pub material: Material,
     pub point: Vector3,
     pub normal: Vector3,
+    // Surface coordinates and texture supplied by the primitive itself (mesh
+    // triangles); shapes that compute UVs on demand leave these empty.
+    pub uv: Option<(f32, f32)>,
+    // UV units per world unit around the hit, used to pick a mip level
+    pub uv_density: f32,
+    pub texture_id: Option<TextureHandle>,
+    // Direction of increasing U along the surface, which orients anisotropic
+    // highlights. A material with its own brushing axis sets it up front.
+    pub tangent: Option<Vector3>,
 }
 
 impl Intersect {
@@ -18,9 +28,30 @@ impl Intersect {
             material,
             point,
             normal,
+            uv: None,
+            uv_density: 0.0,
+            texture_id: None,
+            tangent: material.pbr.and_then(|pbr| pbr.tangent),
         }
     }
 
+    pub fn with_uv(mut self, u: f32, v: f32, density: f32) -> Self {
+        self.uv = Some((u, v));
+        self.uv_density = density;
+        self
+    }
+
+    pub fn with_texture(mut self, texture_id: Option<TextureHandle>) -> Self {
+        self.texture_id = texture_id;
+        self
+    }
+
+    // UV tangent of the primitive, unless the material fixed one already
+    pub fn with_tangent(mut self, tangent: Vector3) -> Self {
+        self.tangent = self.tangent.or(Some(tangent));
+        self
+    }
+
     pub fn empty() -> Self {
         Intersect {
             distance: 0.0,
@@ -28,6 +59,10 @@ impl Intersect {
             material: Material::rubber(), // Usar un material válido
             point: Vector3::new(0.0, 0.0, 0.0),
             normal: Vector3::new(0.0, 0.0, 0.0),
+            uv: None,
+            uv_density: 0.0,
+            texture_id: None,
+            tangent: None,
         }
     }
 }
@@ -2,145 +2,94 @@ use raylib::prelude::*;
 use crate::framebuffer::Framebuffer;
 use crate::ray_intersect::{Intersect, RayIntersect};
 use crate::material::Material;
-use crate::light::Light;
-use crate::color::Color;
-use crate::camera::CustomCamera;
+use crate::light::{Light, LightSample};
+use crate::color::LinearColor;
+use crate::camera::{CustomCamera, Projection};
 use crate::sphere::Sphere;
 use crate::cube::Cube;
+use crate::mesh::Mesh;
+use crate::transform::Transformed;
 use crate::texture::Texture;
+use crate::bvh::{Aabb, Bvh};
+use crate::accumulator::Accumulator;
+use crate::emitter::{Emitter, SurfaceSample, EMITTER_SAMPLES};
+use crate::sampling::{power_heuristic, stratified, Rng};
+use crate::path_tracer::trace_path;
+use crate::pbr::Bsdf;
+use crate::environment::Environment;
 use std::f32::consts::PI;
+use std::fmt;
+use std::str::FromStr;
 use rayon::prelude::*;
 
-const SHADOW_BIAS: f32 = 1e-4;
+pub const SHADOW_BIAS: f32 = 1e-4;
 const MAX_RECURSION_DEPTH: i32 = 3;
-
-// World-space AABB that bounds our diorama. Rays that don't enter this box will
-// skip object intersection entirely and sample the skybox. This creates a
-// compact, focused rendering area showing only the diorama like a microcube.
-const SCENE_MIN: Vector3 = Vector3 { x: -3.8, y: -1.0, z: -3.8 };
-const SCENE_MAX: Vector3 = Vector3 { x:  3.8, y:  3.2, z:  3.8 };
-
-#[inline]
-fn point_in_aabb(p: &Vector3, min: &Vector3, max: &Vector3) -> bool {
-    p.x >= min.x && p.x <= max.x &&
-    p.y >= min.y && p.y <= max.y &&
-    p.z >= min.z && p.z <= max.z
+const MAX_ACCUMULATED_SAMPLES: u32 = 256;
+
+// Light transport algorithm used for every pixel sample
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Integrator {
+    // Phong direct light, one perfect reflection and refraction, sky ambient
+    Whitted,
+    // Unbiased Monte Carlo path tracing with indirect light (path_tracer.rs)
+    PathTracer,
 }
 
-// Ray vs AABB using the slab method
-#[inline]
-fn ray_aabb_intersect(ro: &Vector3, rd: &Vector3, min: &Vector3, max: &Vector3) -> bool {
-    // Robust slab method with proper handling for near-zero components
-    let inv_x = if rd.x != 0.0 { 1.0 / rd.x } else { f32::INFINITY };
-    let inv_y = if rd.y != 0.0 { 1.0 / rd.y } else { f32::INFINITY };
-    let inv_z = if rd.z != 0.0 { 1.0 / rd.z } else { f32::INFINITY };
+impl Integrator {
+    pub const ALL: [Integrator; 2] = [Integrator::Whitted, Integrator::PathTracer];
 
-    let mut t1 = (min.x - ro.x) * inv_x;
-    let mut t2 = (max.x - ro.x) * inv_x;
-    if t1 > t2 { std::mem::swap(&mut t1, &mut t2); }
+    pub fn name(&self) -> &'static str {
+        match self {
+            Integrator::Whitted => "whitted",
+            Integrator::PathTracer => "path",
+        }
+    }
 
-    let mut ty1 = (min.y - ro.y) * inv_y;
-    let mut ty2 = (max.y - ro.y) * inv_y;
-    if ty1 > ty2 { std::mem::swap(&mut ty1, &mut ty2); }
+    // Next integrator in `ALL`, used to switch from the keyboard
+    pub fn next(&self) -> Integrator {
+        let index = Integrator::ALL.iter().position(|i| i == self).unwrap_or(0);
+        Integrator::ALL[(index + 1) % Integrator::ALL.len()]
+    }
+}
 
-    if (t1 > ty2) || (ty1 > t2) { return false; }
-    if ty1 > t1 { t1 = ty1; }
-    if ty2 < t2 { t2 = ty2; }
+impl fmt::Display for Integrator {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
 
-    let mut tz1 = (min.z - ro.z) * inv_z;
-    let mut tz2 = (max.z - ro.z) * inv_z;
-    if tz1 > tz2 { std::mem::swap(&mut tz1, &mut tz2); }
+impl FromStr for Integrator {
+    type Err = String;
 
-    if (t1 > tz2) || (tz1 > t2) { return false; }
-    if tz1 > t1 { t1 = tz1; }
-    if tz2 < t2 { t2 = tz2; }
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Integrator::ALL
+            .into_iter()
+            .find(|i| i.name() == s)
+            .ok_or_else(|| format!("unknown integrator '{}' (available: whitted, path)", s))
+    }
+}
 
-    // We consider an intersection only if the box is hit at t >= 0 (forward ray)
-    t2 >= 0.0
+// Per-frame options for `render` and `render_progressive`
+#[derive(Debug, Clone, Copy)]
+pub struct RenderSettings {
+    pub integrator: Integrator,
+    // Recursion limit of the Whitted tracer; paths end by Russian roulette
+    pub max_depth: i32,
+    // Jittered samples traced per pixel in each pass
+    pub samples_per_pixel: u32,
+    // Progressive rendering stops refining after this many samples per pixel
+    pub max_samples: u32,
 }
 
-// Zen Cosmic skybox function - Creates a serene futuristic atmosphere
-fn skybox_color(ray_direction: &Vector3) -> Color {
-    // Normalize ray direction
-    let dir = ray_direction.normalized();
-    
-    // Calculate spherical coordinates
-    let theta = dir.y.asin(); // Elevation angle (-π/2 to π/2)
-    let phi = dir.z.atan2(dir.x); // Azimuthal angle (-π to π)
-    
-    // Cosmic nebula centers for depth
-    let nebula1_theta = 0.8; // High elevation
-    let nebula1_phi = 1.0;   // Eastern side
-    let nebula2_theta = -0.3; // Lower elevation
-    let nebula2_phi = -1.5;   // Western side
-    
-    // Calculate distances to nebula centers
-    let d1_theta = theta - nebula1_theta;
-    let d1_phi = phi - nebula1_phi;
-    let dist1 = (d1_theta * d1_theta + d1_phi * d1_phi).sqrt();
-    
-    let d2_theta = theta - nebula2_theta;
-    let d2_phi = phi - nebula2_phi;
-    let dist2 = (d2_theta * d2_theta + d2_phi * d2_phi).sqrt();
-    
-    // Nebula parameters
-    let nebula_radius = 0.6;
-    let nebula_intensity = 0.4;
-    
-    // Base cosmic gradient
-    let elevation_factor = (theta + 1.57) / 3.14; // Normalize to 0-1
-    
-    let mut base_color = if elevation_factor > 0.8 {
-        // Deep space - dark blue with purple hints
-        let factor = (elevation_factor - 0.8) / 0.2;
-        let r = (15.0 * (1.0 - factor) + 25.0 * factor) as u8;
-        let g = (25.0 * (1.0 - factor) + 15.0 * factor) as u8;
-        let b = (45.0 * (1.0 - factor) + 55.0 * factor) as u8;
-        Color::new(r, g, b)
-    } else if elevation_factor > 0.4 {
-        // Mid sky - gentle blue gradient
-        let factor = (elevation_factor - 0.4) / 0.4;
-        let r = (30.0 * (1.0 - factor) + 15.0 * factor) as u8;
-        let g = (45.0 * (1.0 - factor) + 25.0 * factor) as u8;
-        let b = (80.0 * (1.0 - factor) + 45.0 * factor) as u8;
-        Color::new(r, g, b)
-    } else {
-        // Horizon - warmer cosmic tones
-        let factor = elevation_factor / 0.4;
-        let r = (45.0 * factor + 35.0 * (1.0 - factor)) as u8;
-        let g = (55.0 * factor + 40.0 * (1.0 - factor)) as u8;
-        let b = (85.0 * factor + 65.0 * (1.0 - factor)) as u8;
-        Color::new(r, g, b)
-    };
-    
-    // Add cyan nebula effect (complements zen water)
-    if dist1 < nebula_radius {
-        let nebula_factor = 1.0 - (dist1 / nebula_radius);
-        let intensity = nebula_factor * nebula_intensity;
-        base_color.r = ((base_color.r as f32) * (1.0 - intensity) + 60.0 * intensity) as u8;
-        base_color.g = ((base_color.g as f32) * (1.0 - intensity) + 120.0 * intensity) as u8;
-        base_color.b = ((base_color.b as f32) * (1.0 - intensity) + 140.0 * intensity) as u8;
-    }
-    
-    // Add purple nebula effect (complements crystal refractions)
-    if dist2 < nebula_radius {
-        let nebula_factor = 1.0 - (dist2 / nebula_radius);
-        let intensity = nebula_factor * nebula_intensity;
-        base_color.r = ((base_color.r as f32) * (1.0 - intensity) + 100.0 * intensity) as u8;
-        base_color.g = ((base_color.g as f32) * (1.0 - intensity) + 50.0 * intensity) as u8;
-        base_color.b = ((base_color.b as f32) * (1.0 - intensity) + 120.0 * intensity) as u8;
-    }
-    
-    // Add subtle stars effect with noise
-    let star_noise = ((phi * 50.0).sin() * (theta * 30.0).cos() * (phi * theta * 100.0).sin()).abs();
-    if star_noise > 0.98 {
-        let star_intensity = (star_noise - 0.98) / 0.02;
-        base_color.r = (base_color.r as f32 + 40.0 * star_intensity) as u8;
-        base_color.g = (base_color.g as f32 + 40.0 * star_intensity) as u8;
-        base_color.b = (base_color.b as f32 + 40.0 * star_intensity) as u8;
+impl Default for RenderSettings {
+    fn default() -> Self {
+        RenderSettings {
+            integrator: Integrator::Whitted,
+            max_depth: MAX_RECURSION_DEPTH,
+            samples_per_pixel: 1,
+            max_samples: MAX_ACCUMULATED_SAMPLES,
+        }
     }
-    
-    base_color
 }
 
 // Enum to handle different object types
@@ -148,6 +97,8 @@ fn skybox_color(ray_direction: &Vector3) -> Color {
 pub enum Object {
     Sphere(Sphere),
     Cube(Cube),
+    Mesh(Mesh),
+    Transformed(Transformed),
 }
 
 impl RayIntersect for Object {
@@ -155,41 +106,210 @@ impl RayIntersect for Object {
         match self {
             Object::Sphere(sphere) => sphere.ray_intersect(ray_origin, ray_direction),
             Object::Cube(cube) => cube.ray_intersect(ray_origin, ray_direction),
+            Object::Mesh(mesh) => mesh.ray_intersect(ray_origin, ray_direction),
+            Object::Transformed(transformed) => transformed.ray_intersect(ray_origin, ray_direction),
         }
     }
 }
 
 impl Object {
+    pub fn bounds(&self) -> Aabb {
+        match self {
+            Object::Sphere(sphere) => sphere.bounds(),
+            Object::Cube(cube) => cube.bounds(),
+            Object::Mesh(mesh) => mesh.bounds(),
+            Object::Transformed(transformed) => transformed.bounds(),
+        }
+    }
+
+    // Total surface area, the inverse of the density `sample_surface` picks
+    // points with
+    pub fn area(&self) -> f32 {
+        match self {
+            Object::Sphere(sphere) => sphere.area(),
+            Object::Cube(cube) => cube.area(),
+            Object::Mesh(mesh) => mesh.area(),
+            Object::Transformed(transformed) => transformed.area(),
+        }
+    }
+
+    // Point spread uniformly over the surface, from three numbers in [0, 1)
+    pub fn sample_surface(&self, s: f32, t: f32, pick: f32) -> Option<SurfaceSample> {
+        match self {
+            Object::Sphere(sphere) => Some(sphere.sample_surface(s, t)),
+            Object::Cube(cube) => Some(cube.sample_surface(s, t, pick)),
+            Object::Mesh(mesh) => mesh.sample_surface(s, t, pick),
+            Object::Transformed(transformed) => transformed.sample_surface(s, t, pick),
+        }
+    }
+
+    pub fn is_emissive(&self) -> bool {
+        match self {
+            Object::Sphere(sphere) => sphere.material.is_emissive(),
+            Object::Cube(cube) => cube.is_emissive(),
+            Object::Mesh(mesh) => mesh.is_emissive(),
+            Object::Transformed(transformed) => transformed.object.is_emissive(),
+        }
+    }
+
     pub fn get_material(&self) -> Material {
         match self {
             Object::Sphere(sphere) => sphere.material,
             Object::Cube(cube) => cube.material,
+            Object::Mesh(mesh) => mesh.materials.first().map_or(Material::rubber(), |m| m.material),
+            Object::Transformed(transformed) => transformed.object.get_material(),
         }
     }
 
-    pub fn get_texture_color(&self, intersect: &Intersect, textures: &[Texture]) -> Color {
+    // Surface color in linear light. `footprint` is the world-space width of
+    // the pixel's footprint at the hit, used for mip selection.
+    pub fn get_texture_color(&self, intersect: &Intersect, textures: &[Texture], footprint: f32) -> LinearColor {
         match self {
-            Object::Sphere(_) => intersect.material.diffuse, // Spheres use solid colors
-            Object::Cube(cube) => {
-                if let Some(texture_id) = cube.texture_id {
-                    if texture_id < textures.len() {
-                        let (u, v) = cube.get_uv(intersect.point, intersect.normal);
-                        return textures[texture_id].sample(u, v);
-                    }
+            Object::Sphere(sphere) => match sphere.texture_id {
+                Some(texture_id) => {
+                    let (u, v) = sphere.get_uv(intersect.point);
+                    textures[texture_id.index()].sample_filtered(u, v, footprint * sphere.uv_density())
+                }
+                None => intersect.material.diffuse.to_linear(),
+            },
+            Object::Cube(cube) => match cube.texture_for(intersect.normal) {
+                Some(texture_id) => {
+                    let (u, v) = cube.get_uv(intersect.point, intersect.normal);
+                    let (scale_u, scale_v) = cube.uv_scale;
+                    let uv_footprint = footprint * cube.uv_density(intersect.normal);
+                    textures[texture_id.index()].sample_filtered(u * scale_u, v * scale_v, uv_footprint)
+                }
+                None => intersect.material.diffuse.to_linear(),
+            },
+            // Meshes carry the UV and texture of the triangle that was hit
+            Object::Mesh(_) => match (intersect.texture_id, intersect.uv) {
+                (Some(texture_id), Some((u, v))) => {
+                    textures[texture_id.index()].sample_filtered(u, v, footprint * intersect.uv_density)
                 }
-                intersect.material.diffuse // Fallback to material color
+                _ => intersect.material.diffuse.to_linear(),
+            },
+            // Look the texture up in object space, where the footprint shrinks
+            // or grows with the object's scale
+            Object::Transformed(transformed) => {
+                let local = transformed.to_local(intersect);
+                let local_footprint = footprint / transformed.transform.mean_scale().max(1e-6);
+                transformed.object.get_texture_color(&local, textures, local_footprint)
             }
         }
     }
+
+    // Normal to light the hit with: bent by the object's normal or bump map
+    // where it has one. Call after the colour lookup, which needs the flat
+    // face normal.
+    pub fn shading_normal(&self, intersect: &Intersect, textures: &[Texture]) -> Vector3 {
+        match self {
+            Object::Cube(cube) => cube.shading_normal(intersect, textures),
+            Object::Transformed(transformed) => {
+                let local = transformed.to_local(intersect);
+                let normal = transformed.object.shading_normal(&local, textures);
+                transformed.transform.normal_to_world(normal)
+            }
+            Object::Sphere(_) | Object::Mesh(_) => intersect.normal,
+        }
+    }
+}
+
+// Cheap stand-in for ray differentials: the width of the region a pixel
+// covers at the ray origin, and how fast it grows per unit of distance.
+#[derive(Debug, Clone, Copy)]
+pub struct RayCone {
+    pub width: f32,
+    pub spread: f32,
+}
+
+impl RayCone {
+    // Cone of a primary ray for an image `height` pixels tall. Orthographic
+    // pixels keep their width at any distance; the angular projections
+    // spread by the angle one pixel spans.
+    pub fn primary(camera: &CustomCamera, height: usize) -> Self {
+        let (width, spread) = match camera.projection {
+            Projection::Perspective => (0.0, 2.0 * (camera.fov * 0.5).tan()),
+            Projection::Orthographic => (2.0 * (camera.center - camera.eye).length() * (camera.fov * 0.5).tan(), 0.0),
+            Projection::Panorama => (0.0, PI),
+            Projection::Fisheye => (0.0, camera.fov),
+        };
+        RayCone {
+            width: width / height as f32,
+            spread: spread / height as f32,
+        }
+    }
+
+    pub fn width_at(&self, distance: f32) -> f32 {
+        self.width + self.spread * distance
+    }
+
+    // Cone continuing from a hit `distance` away (reflection, refraction)
+    pub fn advanced(&self, distance: f32) -> Self {
+        RayCone {
+            width: self.width_at(distance),
+            spread: self.spread,
+        }
+    }
+}
+
+// Objects together with the BVH built over them. The object list is fixed
+// once the scene is built, so the hierarchy can never go stale.
+pub struct Scene {
+    objects: Vec<Object>,
+    bvh: Bvh,
+    // Objects with glowing materials, sampled as area lights
+    emitters: Vec<Emitter>,
+}
+
+impl Scene {
+    pub fn new(objects: Vec<Object>) -> Self {
+        let bvh = Self::build_bvh(&objects);
+        let emitters = Emitter::collect(&objects);
+        Scene { objects, bvh, emitters }
+    }
+
+    pub fn objects(&self) -> &[Object] {
+        &self.objects
+    }
+
+    pub fn emitters(&self) -> &[Emitter] {
+        &self.emitters
+    }
+
+    // The emitter wrapping `object`, if it glows
+    pub fn emitter_of(&self, object: &Object) -> Option<&Emitter> {
+        self.emitters
+            .iter()
+            .find(|emitter| std::ptr::eq(&self.objects[emitter.object], object))
+    }
+
+    fn build_bvh(objects: &[Object]) -> Bvh {
+        let bounds: Vec<Aabb> = objects.iter().map(Object::bounds).collect();
+        Bvh::build(&bounds)
+    }
+
+    // Closest intersection along the ray and the object that produced it
+    pub fn closest_hit(&self, ray_origin: &Vector3, ray_direction: &Vector3) -> Option<(Intersect, &Object)> {
+        self.bvh
+            .closest_hit(&self.objects, ray_origin, ray_direction)
+            .map(|(intersect, index)| (intersect, &self.objects[index]))
+    }
+
+    // First occluder (in object order) closer than max_distance, for shadow rays
+    pub fn occluder(&self, ray_origin: &Vector3, ray_direction: &Vector3, max_distance: f32) -> Option<Intersect> {
+        self.bvh
+            .first_occluder(&self.objects, ray_origin, ray_direction, max_distance)
+            .map(|(intersect, _)| intersect)
+    }
 }
 
 // Función de reflexión siguiendo la fórmula: R = I - 2(I·N)N
-fn reflect(incident: &Vector3, normal: &Vector3) -> Vector3 {
+pub fn reflect(incident: &Vector3, normal: &Vector3) -> Vector3 {
     *incident - *normal * 2.0 * incident.dot(*normal)
 }
 
 // Función de refracción siguiendo la Ley de Snell
-fn refract(incident: &Vector3, normal: &Vector3, eta: f32) -> Option<Vector3> {
+pub fn refract(incident: &Vector3, normal: &Vector3, eta: f32) -> Option<Vector3> {
     let cos_i = -incident.dot(*normal).max(-1.0).min(1.0);
     let sin_t2 = eta * eta * (1.0 - cos_i * cos_i);
     
@@ -203,7 +323,7 @@ fn refract(incident: &Vector3, normal: &Vector3, eta: f32) -> Option<Vector3> {
 }
 
 // Ecuaciones de Fresnel para determinar qué tanto se refleja vs refracta
-fn fresnel(incident: &Vector3, normal: &Vector3, ior: f32) -> f32 {
+pub fn fresnel(incident: &Vector3, normal: &Vector3, ior: f32) -> f32 {
     let cos_i = incident.dot(*normal).abs().max(-1.0).min(1.0);
     let eta_i = 1.0;
     let eta_t = ior;
@@ -223,15 +343,30 @@ fn fresnel(incident: &Vector3, normal: &Vector3, ior: f32) -> f32 {
     (r_ortho * r_ortho + r_para * r_para) / 2.0
 }
 
-fn cast_shadow(
-    intersect: &Intersect,
-    light: &Light,
-    objects: &[Object],
-) -> f32 {
-    let mut light_dir = light.position - intersect.point;
-    light_dir.normalize();
-    let light_distance = (light.position - intersect.point).length();
+// Energy-normalized Phong: Lambert's albedo / π plus a lobe scaled by
+// (n + 2) / 2π, so sharp and broad highlights reflect the same energy
+pub fn phong_brdf(
+    material: &Material,
+    surface_color: LinearColor,
+    normal: &Vector3,
+    light_dir: &Vector3,
+    view_dir: &Vector3,
+) -> LinearColor {
+    // Componente difusa usando color de textura
+    let diffuse = surface_color * (material.albedo[0] / PI);
+
+    // Componente especular
+    let reflect_dir = reflect(&-*light_dir, normal);
+    let lobe = view_dir.dot(reflect_dir).max(0.0).powf(material.specular);
+    let specular = material.albedo[1] * (material.specular + 2.0) / (2.0 * PI) * lobe;
+
+    diffuse + LinearColor::new(specular, specular, specular)
+}
 
+// Whether something blocks the shadow ray from the hit point to a light
+// sample. Directional samples are infinitely far, so any hit occludes.
+pub fn cast_shadow(intersect: &Intersect, sample: &LightSample, scene: &Scene) -> bool {
+    let light_dir = sample.direction;
     let offset_normal = intersect.normal * SHADOW_BIAS;
     let shadow_ray_origin = if light_dir.dot(intersect.normal) < 0.0 {
         intersect.point - offset_normal
@@ -239,102 +374,195 @@ fn cast_shadow(
         intersect.point + offset_normal
     };
 
-    let mut shadow_intensity = 0.0;
+    scene
+        .occluder(&shadow_ray_origin, &light_dir, sample.distance - SHADOW_BIAS)
+        .is_some()
+}
+
+// Closest hit of a ray with the diorama. Rays that miss the scene entirely
+// are rejected by the BVH root box.
+pub fn find_hit<'a>(scene: &'a Scene, ray_origin: &Vector3, ray_direction: &Vector3) -> Option<(Intersect, &'a Object)> {
+    scene.closest_hit(ray_origin, ray_direction)
+}
 
-    for object in objects {
-        let shadow_intersect = object.ray_intersect(&shadow_ray_origin, &light_dir);
-        if shadow_intersect.is_intersecting && shadow_intersect.distance < light_distance {
-            let distance_ratio = shadow_intersect.distance / light_distance;
-            shadow_intensity = 1.0 - distance_ratio.powf(2.0).min(1.0);
-            break;
-        }
-    }
+// Texture color at a hit in linear light. The footprint stretches as the
+// surface turns away from the ray (square root: halfway between the two axes
+// of the stretched ellipse).
+pub fn surface_color(
+    object: &Object,
+    intersect: &Intersect,
+    ray_direction: &Vector3,
+    textures: &[Texture],
+    cone: RayCone,
+) -> LinearColor {
+    let cos_incidence = ray_direction.normalized().dot(intersect.normal).abs().max(0.05);
+    let footprint = cone.width_at(intersect.distance) / cos_incidence.sqrt();
+    object.get_texture_color(intersect, textures, footprint)
+}
 
-    shadow_intensity
+// Everything a ray needs from the world, bundled so the recursion stays readable
+#[derive(Clone, Copy)]
+pub struct TraceContext<'a> {
+    pub scene: &'a Scene,
+    pub lights: &'a [Light],
+    pub textures: &'a [Texture],
+    pub environment: &'a Environment,
 }
 
+// `bsdf_pdf` is the density a PBR glossy reflection drew `ray_direction`
+// with, so the glow it finds can be weighed against sampling that emitter
+// directly; `None` for camera, mirror and refracted rays.
 pub fn cast_ray(
     ray_origin: &Vector3,
     ray_direction: &Vector3,
-    objects: &[Object],
-    lights: &[Light],
-    textures: &[Texture],
+    ctx: &TraceContext,
+    rng: &mut Rng,
     depth: i32,
-) -> Color {
+    cone: RayCone,
+    bsdf_pdf: Option<f32>,
+) -> LinearColor {
     if depth <= 0 {
-        return Color::new(0, 0, 0); // Negro si alcanzamos máxima profundidad
+        return LinearColor::BLACK; // Negro si alcanzamos máxima profundidad
     }
 
-    // Scene culling: if the ray origin is outside the scene AABB and the ray
-    // doesn't intersect the AABB, skip object tests and sample the skybox.
-    // This preserves the skybox while limiting rendering to our diorama.
-    if !point_in_aabb(ray_origin, &SCENE_MIN, &SCENE_MAX) {
-        if !ray_aabb_intersect(ray_origin, ray_direction, &SCENE_MIN, &SCENE_MAX) {
-            return skybox_color(ray_direction);
-        }
-    }
-
-    let mut intersect = Intersect::empty();
-    let mut zbuffer = f32::INFINITY;
-    let mut closest_object: Option<&Object> = None;
-
-    // Encontrar la intersección más cercana
-    for object in objects {
-        let i = object.ray_intersect(ray_origin, ray_direction);
-        if i.is_intersecting && i.distance < zbuffer {
-            zbuffer = i.distance;
-            intersect = i;
-            closest_object = Some(object);
-        }
-    }
+    // Encontrar la intersección más cercana. Rays that miss the diorama
+    // sample the skybox, which limits rendering to the diorama.
+    let Some((intersect, closest_object)) = find_hit(ctx.scene, ray_origin, ray_direction) else {
+        return ctx.environment.radiance(ray_direction); // Usar skybox en lugar de color fijo
+    };
 
-    if !intersect.is_intersecting {
-        return skybox_color(ray_direction); // Usar skybox en lugar de color fijo
-    }
+    // Emitter sampling at the previous surface could have found this glow
+    // too, so the two share it through MIS
+    let cos_emitter = -ray_direction.normalized().dot(intersect.normal);
+    let emission_weight = match bsdf_pdf {
+        Some(pdf) if cos_emitter > 0.0 => ctx.scene.emitter_of(closest_object).map_or(1.0, |emitter| {
+            power_heuristic(pdf, EMITTER_SAMPLES as f32 * emitter.pdf(intersect.distance, cos_emitter))
+        }),
+        _ => 1.0,
+    };
 
-    let closest_object = closest_object.unwrap();
+    let surface_color = surface_color(closest_object, &intersect, ray_direction, ctx.textures, cone);
+    let intersect = Intersect {
+        normal: closest_object.shading_normal(&intersect, ctx.textures),
+        ..intersect
+    };
+    let view_dir = (*ray_origin - intersect.point).normalized();
+
+    // PBR materials shade with the normal on the viewer's side, so the inside
+    // of glass gets the right Fresnel; Phong keeps the outward normal
+    let entering = ray_direction.dot(intersect.normal) < 0.0;
+    let bsdf = intersect.material.pbr.map(|pbr| {
+        let normal = if entering { intersect.normal } else { -intersect.normal };
+        Bsdf::new(pbr, surface_color, normal, intersect.tangent, view_dir, entering)
+    });
+    let normal = match &bsdf {
+        Some(_) if !entering => -intersect.normal,
+        _ => intersect.normal,
+    };
+    let brdf = |light_dir: &Vector3| match &bsdf {
+        Some(bsdf) => bsdf.eval(light_dir),
+        None => phong_brdf(&intersect.material, surface_color, &normal, light_dir, &view_dir),
+    };
 
-    // Get texture color for the surface
-    let surface_color = closest_object.get_texture_color(&intersect, textures);
-    
     // Color local (iluminación Phong)
-    let mut color = Color::new(0, 0, 0);
+    let mut color = LinearColor::BLACK;
     
-    // Iluminación ambiente
-    let ambient = surface_color * 0.1;
-    color = color + ambient;
+    // Iluminación ambiente: the sky's irradiance lights the diffuse part,
+    // and Phong highlights reflect the sky blurred by their lobe. PBR
+    // materials already trace their glossy reflection below.
+    let irradiance = ctx.environment.irradiance(&normal);
+    color += match &bsdf {
+        Some(bsdf) => surface_color * irradiance * (bsdf.ambient_share() / PI),
+        None => {
+            let material = &intersect.material;
+            let mirror = reflect(ray_direction, &normal);
+            surface_color * irradiance * (material.albedo[0] / PI)
+                + ctx.environment.specular(&mirror, material.specular) * material.albedo[1]
+        }
+    };
 
-    // Calcular iluminación directa para cada luz
-    for light in lights {
-        let mut light_dir = light.position - intersect.point;
-        light_dir.normalize();
-        let view_dir = (*ray_origin - intersect.point).normalized();
-        let reflect_dir = reflect(&-light_dir, &intersect.normal);
+    // Calcular iluminación directa para cada luz. Area lights are averaged
+    // over stratified points on their surface, which gives real penumbrae;
+    // a point light is the single-sample case.
+    for light in ctx.lights {
+        let samples = light.shadow_samples();
+        let mut light_color = LinearColor::BLACK;
+
+        for index in 0..samples {
+            let (s, t) = stratified(index, samples, rng);
+            let Some(sample) = light.sample(intersect.point, s, t) else {
+                continue;
+            };
+            let cos_incoming = normal.dot(sample.direction);
+            if cos_incoming <= 0.0 || cast_shadow(&intersect, &sample, ctx.scene) {
+                continue;
+            }
 
-        let shadow_intensity = cast_shadow(&intersect, light, objects);
-        let light_intensity = light.intensity * (1.0 - shadow_intensity);
+            light_color += brdf(&sample.direction) * (cos_incoming * sample.weight);
+        }
 
-        // Componente difusa usando color de textura
-        let diffuse_intensity = intersect.normal.dot(light_dir).max(0.0);
-        let diffuse = surface_color * intersect.material.albedo[0] * diffuse_intensity * light_intensity;
+        // Radiance = BRDF * irradiance, with the light's radiometric intensity
+        color += light_color * light.color.to_linear() * (light.intensity / samples as f32);
+    }
 
-        // Componente especular
-        let specular_intensity = view_dir.dot(reflect_dir).max(0.0).powf(intersect.material.specular);
-        let specular = light.color * intersect.material.albedo[1] * specular_intensity * light_intensity;
+    // Glowing objects light the scene the same way, from their own surface.
+    // PBR reflection rays can reach them as well, so the glossy lobe is
+    // MIS-weighted against the reflection ray traced below.
+    let reflects = bsdf.is_some() && depth > 1;
+    for emitter in ctx.scene.emitters() {
+        let mut light_color = LinearColor::BLACK;
+        for index in 0..EMITTER_SAMPLES {
+            let (s, t) = stratified(index, EMITTER_SAMPLES, rng);
+            let Some((sample, emitted)) = emitter.sample(ctx.scene, intersect.point, s, t, rng.next_f32()) else {
+                continue;
+            };
+            let cos_incoming = normal.dot(sample.direction);
+            if cos_incoming <= 0.0 || cast_shadow(&intersect, &sample, ctx.scene) {
+                continue;
+            }
+            let reflected = match &bsdf {
+                Some(bsdf) if reflects => {
+                    let (diffuse, glossy) = bsdf.eval_lobes(&sample.direction);
+                    let light_pdf = EMITTER_SAMPLES as f32 * sample.pdf.unwrap_or(0.0);
+                    diffuse + glossy * power_heuristic(light_pdf, bsdf.reflection_pdf(&sample.direction))
+                }
+                _ => brdf(&sample.direction),
+            };
+            light_color += reflected * emitted * (cos_incoming * sample.weight);
+        }
+        color += light_color * (1.0 / EMITTER_SAMPLES as f32);
+    }
 
-        color = color + diffuse + specular;
+    // PBR materials follow one glossy reflection and one rough refraction,
+    // drawn anew on every pass so they blur as the viewer accumulates
+    if let Some(bsdf) = bsdf {
+        let next_cone = cone.advanced(intersect.distance);
+        if let Some((direction, weight)) = bsdf.sample_reflection(rng.next_f32(), rng.next_f32()) {
+            let origin = intersect.point + normal * SHADOW_BIAS;
+            let pdf = bsdf.reflection_pdf(&direction);
+            color += cast_ray(&origin, &direction, ctx, rng, depth - 1, next_cone, Some(pdf)) * weight;
+        }
+        if let Some((direction, weight)) = bsdf.sample_transmission(rng.next_f32(), rng.next_f32()) {
+            let side = if direction.dot(normal) < 0.0 { -normal } else { normal };
+            let origin = intersect.point + side * SHADOW_BIAS;
+            color += cast_ray(&origin, &direction, ctx, rng, depth - 1, next_cone, None) * weight;
+        }
+        if intersect.material.is_emissive() && entering {
+            color += intersect.material.emitted() * emission_weight;
+        }
+        return color;
     }
 
     // Calcular reflexión - Solo si vale la pena
-    let mut reflect_color = Color::new(0, 0, 0);
+    let mut reflect_color = LinearColor::BLACK;
     if intersect.material.albedo[2] > 0.01 {
         let reflect_dir = reflect(ray_direction, &intersect.normal);
         let reflect_origin = intersect.point + intersect.normal * SHADOW_BIAS;
-        reflect_color = cast_ray(&reflect_origin, &reflect_dir, objects, lights, textures, depth - 1);
+        reflect_color = cast_ray(&reflect_origin, &reflect_dir, ctx, rng, depth - 1, cone.advanced(intersect.distance), None);
     }
 
     // Calcular refracción - Solo si vale la pena
-    let mut refract_color = Color::new(0, 0, 0);
+    let mut refract_color = LinearColor::BLACK;
     if intersect.material.albedo[3] > 0.01 && intersect.material.transparency > 0.01 {
         let mut normal = intersect.normal;
         let mut eta = 1.0 / intersect.material.refractive_index;
@@ -346,7 +574,7 @@ pub fn cast_ray(
 
         if let Some(refract_dir) = refract(ray_direction, &normal, eta) {
             let refract_origin = intersect.point - normal * SHADOW_BIAS;
-            refract_color = cast_ray(&refract_origin, &refract_dir, objects, lights, textures, depth - 1);
+            refract_color = cast_ray(&refract_origin, &refract_dir, ctx, rng, depth - 1, cone.advanced(intersect.distance), None);
         }
     }
 
@@ -363,63 +591,137 @@ pub fn cast_ray(
             reflect_color * kr + 
             refract_color * (1.0 - kr) * intersect.material.transparency;
 
+    // Glow seen from outside the surface
+    if intersect.material.is_emissive() && ray_direction.dot(intersect.normal) < 0.0 {
+        color += intersect.material.emitted() * emission_weight;
+    }
+
     color
 }
 
-pub fn render(
-    framebuffer: &mut Framebuffer, 
-    objects: &[Object], 
-    camera: &CustomCamera, 
-    lights: &[Light],
-    textures: &[Texture]
-) {
+// Distance the camera focuses at. Autofocus measures it along the view
+// direction to the object under the middle of the screen, and falls back to
+// the looked-at point when that ray hits nothing.
+fn focus_distance(camera: &CustomCamera, scene: &Scene) -> f32 {
+    camera.focal_distance().unwrap_or_else(|| {
+        let forward = (camera.center - camera.eye).normalized();
+        match find_hit(scene, &camera.eye, &forward) {
+            Some((intersect, _)) => intersect.distance,
+            None => (camera.center - camera.eye).length(),
+        }
+    })
+}
+
+// Trace one pass over the whole image. Each entry holds the linear sum of
+// `samples_per_pixel` jittered samples for that pixel, in row-major order.
+fn trace_pass(
+    framebuffer: &Framebuffer,
+    ctx: &TraceContext,
+    camera: &CustomCamera,
+    settings: &RenderSettings,
+    pass: u32,
+) -> Vec<LinearColor> {
     let width = framebuffer.width() as usize;
     let height = framebuffer.height() as usize;
     let aspect_ratio = width as f32 / height as f32;
-    let fov = PI / 3.0;
-    let perspective_scale = (fov * 0.5).tan();
-
-    // Create a buffer to store all pixel colors
-    // This allows parallel computation without concurrent writes to framebuffer
-    let pixels: Vec<(u32, u32, Color)> = (0..height)
+    let cone = RayCone::primary(camera, height);
+    let focal_distance = focus_distance(camera, ctx.scene);
+    // A lone first sample goes through the pixel centre and the lens centre,
+    // so the first interactive frame is sharp and stable. Any more samples
+    // are all jittered, or the centred one would leave a sharp ghost over
+    // the anti-aliasing and the defocus blur.
+    let centred = pass == 0 && settings.samples_per_pixel == 1 && camera.aperture <= 0.0;
+
+    (0..height)
         .into_par_iter()
         .flat_map(|y| {
             (0..width)
                 .into_par_iter()
                 .map(move |x| {
-                    // Map the pixel coordinate to screen space [-1, 1]
-                    let screen_x = (2.0 * x as f32) / width as f32 - 1.0;
-                    let screen_y = -(2.0 * y as f32) / height as f32 + 1.0;
-
-                    // Adjust for aspect ratio and perspective 
-                    let screen_x = screen_x * aspect_ratio * perspective_scale;
-                    let screen_y = screen_y * perspective_scale;
-
-                    // Calculate the direction of the ray for this pixel
-                    let mut ray_direction = Vector3::new(screen_x, screen_y, -1.0);
-                    ray_direction.normalize();
-
-                    // Apply camera rotation to the ray direction
-                    let rotated_direction = camera.basis_change(&ray_direction);
-
-                    // Cast the ray and get the pixel color
-                    let pixel_color = cast_ray(
-                        &camera.eye, 
-                        &rotated_direction, 
-                        objects, 
-                        lights, 
-                        textures, 
-                        MAX_RECURSION_DEPTH
-                    );
-
-                    (x as u32, y as u32, pixel_color)
+                    let mut rng = Rng::for_pixel(x as u32, y as u32, pass);
+                    let mut sum = LinearColor::BLACK;
+
+                    for _ in 0..settings.samples_per_pixel {
+                        let (jitter_x, jitter_y) = if centred {
+                            (0.5, 0.5)
+                        } else {
+                            (rng.next_f32(), rng.next_f32())
+                        };
+
+                        // Map the sample position to screen space [-1, 1]
+                        let screen_x = (2.0 * (x as f32 + jitter_x)) / width as f32 - 1.0;
+                        let screen_y = -(2.0 * (y as f32 + jitter_y)) / height as f32 + 1.0;
+
+                        // The camera's projection turns it into a ray. Each
+                        // sample also starts from its own point on the lens.
+                        let lens = if centred {
+                            None
+                        } else {
+                            Some((rng.next_f32(), rng.next_f32()))
+                        };
+                        let Some((ray_origin, rotated_direction)) =
+                            camera.primary_ray(screen_x, screen_y, aspect_ratio, focal_distance, lens)
+                        else {
+                            continue;
+                        };
+
+                        sum += match settings.integrator {
+                            Integrator::Whitted => cast_ray(
+                                &ray_origin, 
+                                &rotated_direction, 
+                                ctx, 
+                                &mut rng, 
+                                settings.max_depth,
+                                cone,
+                                None,
+                            ),
+                            Integrator::PathTracer => trace_path(&ray_origin, &rotated_direction, ctx, &mut rng, cone),
+                        };
+                    }
+
+                    sum
                 })
                 .collect::<Vec<_>>()
         })
-        .collect();
+        .collect()
+}
+
+// Render a complete frame with `samples_per_pixel` samples per pixel
+pub fn render(
+    framebuffer: &mut Framebuffer, 
+    ctx: &TraceContext,
+    camera: &CustomCamera, 
+    settings: &RenderSettings,
+) {
+    let width = framebuffer.width() as usize;
+    let height = framebuffer.height() as usize;
 
-    // Write all computed pixels to the framebuffer (sequential, but fast)
-    for (x, y, color) in pixels {
-        framebuffer.set_pixel_with_color(x, y, color);
+    let pass = trace_pass(framebuffer, ctx, camera, settings, 0);
+
+    let mut accumulator = Accumulator::new(width, height);
+    accumulator.add(&pass, settings.samples_per_pixel);
+    accumulator.resolve(framebuffer);
+}
+
+// Add one more pass to the accumulator and show the refined average. The
+// accumulation restarts whenever the camera moves, and stops refining once
+// `max_samples` samples per pixel have been gathered.
+pub fn render_progressive(
+    framebuffer: &mut Framebuffer,
+    accumulator: &mut Accumulator,
+    ctx: &TraceContext,
+    camera: &CustomCamera,
+    settings: &RenderSettings,
+) {
+    let width = framebuffer.width() as usize;
+    let height = framebuffer.height() as usize;
+
+    accumulator.sync(camera, width, height);
+    if accumulator.samples() < settings.max_samples {
+        let pass = trace_pass(framebuffer, ctx, camera, settings, accumulator.passes());
+        accumulator.add(&pass, settings.samples_per_pixel);
     }
-}
\ No newline at end of file
+
+    // Resolve even once converged, so tone mapping changes show up at once
+    accumulator.resolve(framebuffer);
+}
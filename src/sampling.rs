@@ -0,0 +1,128 @@
+use raylib::prelude::*;
+use std::f32::consts::{FRAC_PI_2, FRAC_PI_4, PI};
+
+// Random numbers for stochastic sampling. PCG32 is tiny, fast and gives every
+// pixel its own reproducible stream, which keeps parallel renders deterministic.
+#[derive(Debug, Clone)]
+pub struct Rng {
+    state: u64,
+    increment: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64, stream: u64) -> Self {
+        let mut rng = Rng {
+            state: 0,
+            increment: (stream << 1) | 1,
+        };
+        rng.next_u32();
+        rng.state = rng.state.wrapping_add(seed);
+        rng.next_u32();
+        rng
+    }
+
+    // Independent stream for one pixel in one accumulation pass
+    pub fn for_pixel(x: u32, y: u32, pass: u32) -> Self {
+        let pixel = ((y as u64) << 32) | x as u64;
+        Rng::new(hash(pixel ^ ((pass as u64) << 48)), pixel)
+    }
+
+    pub fn next_u32(&mut self) -> u32 {
+        let old = self.state;
+        self.state = old
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(self.increment);
+        let xorshifted = (((old >> 18) ^ old) >> 27) as u32;
+        let rot = (old >> 59) as u32;
+        xorshifted.rotate_right(rot)
+    }
+
+    // Uniform float in [0, 1)
+    pub fn next_f32(&mut self) -> f32 {
+        (self.next_u32() >> 8) as f32 * (1.0 / (1u32 << 24) as f32)
+    }
+}
+
+// SplitMix64 finalizer, used to decorrelate seeds
+fn hash(mut z: u64) -> u64 {
+    z = z.wrapping_add(0x9E3779B97F4A7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+    z ^ (z >> 31)
+}
+
+// Point in cell `index` of a roughly square grid of `count` cells covering
+// the unit square, jittered inside its cell. Spreading samples this way
+// converges much faster than independent uniform draws.
+pub fn stratified(index: u32, count: u32, rng: &mut Rng) -> (f32, f32) {
+    let columns = (count as f32).sqrt().ceil().max(1.0) as u32;
+    let rows = count.div_ceil(columns).max(1);
+    let (cx, cy) = (index % columns, index / columns);
+    (
+        (cx as f32 + rng.next_f32()) / columns as f32,
+        (cy as f32 + rng.next_f32()) / rows as f32,
+    )
+}
+
+// Shirley-Chiu concentric mapping from the unit square to the unit disk.
+// Unlike the polar mapping it keeps strata compact, so stratification survives.
+pub fn concentric_disk(u: f32, v: f32) -> (f32, f32) {
+    let a = 2.0 * u - 1.0;
+    let b = 2.0 * v - 1.0;
+    if a == 0.0 && b == 0.0 {
+        return (0.0, 0.0);
+    }
+    let (radius, angle) = if a.abs() > b.abs() {
+        (a, FRAC_PI_4 * (b / a))
+    } else {
+        (b, FRAC_PI_2 - FRAC_PI_4 * (a / b))
+    };
+    (radius * angle.cos(), radius * angle.sin())
+}
+
+// Uniform point in the regular polygon with `sides` corners on the unit
+// circle, the first at `rotation` radians from +x
+pub fn regular_polygon(sides: u32, rotation: f32, u: f32, v: f32) -> (f32, f32) {
+    // Pick one of the equal triangles fanning out from the centre, reusing
+    // the rest of `u` to place the point inside it
+    let scaled = u * sides as f32;
+    let index = (scaled as u32).min(sides - 1);
+    let u = scaled - index as f32;
+    let step = 2.0 * PI / sides as f32;
+    let (a, b) = (rotation + step * index as f32, rotation + step * (index + 1) as f32);
+    let radial = u.sqrt();
+    let (wa, wb) = (radial * (1.0 - v), radial * v);
+    (wa * a.cos() + wb * b.cos(), wa * a.sin() + wb * b.sin())
+}
+
+// Two unit vectors completing `normal` (unit) to an orthonormal basis
+pub fn tangent_frame(normal: Vector3) -> (Vector3, Vector3) {
+    let tangent = normal.perpendicular().normalized();
+    (tangent, normal.cross(tangent))
+}
+
+// Direction in the hemisphere around `normal` with density cos(theta) / π,
+// the ideal distribution for a Lambertian bounce
+pub fn cosine_hemisphere(normal: Vector3, u: f32, v: f32) -> Vector3 {
+    let (tangent, bitangent) = tangent_frame(normal);
+    let (x, y) = concentric_disk(u, v);
+    let z = (1.0 - x * x - y * y).max(0.0).sqrt();
+    (tangent * x + bitangent * y + normal * z).normalized()
+}
+
+// Direction around `axis` with density (n + 1) / 2π * cos^n(alpha), the
+// shape of a Phong highlight with exponent n
+pub fn phong_lobe(axis: Vector3, exponent: f32, u: f32, v: f32) -> Vector3 {
+    let (tangent, bitangent) = tangent_frame(axis);
+    let cos_alpha = u.powf(1.0 / (exponent + 1.0));
+    let sin_alpha = (1.0 - cos_alpha * cos_alpha).max(0.0).sqrt();
+    let phi = 2.0 * PI * v;
+    (tangent * (sin_alpha * phi.cos()) + bitangent * (sin_alpha * phi.sin()) + axis * cos_alpha).normalized()
+}
+
+// Power heuristic (beta = 2) weight of a technique with density `pdf` when
+// `other_pdf` could have produced the same sample
+pub fn power_heuristic(pdf: f32, other_pdf: f32) -> f32 {
+    let (a, b) = (pdf * pdf, other_pdf * other_pdf);
+    if a + b > 0.0 { a / (a + b) } else { 0.0 }
+}
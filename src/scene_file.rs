@@ -0,0 +1,1145 @@
+use raylib::prelude::*;
+use crate::json::{self, JsonError, JsonKey, JsonKind, JsonValue};
+use crate::diorama::Diorama;
+use crate::renderer::Object;
+use crate::cube::{Cube, Face};
+use crate::sphere::Sphere;
+use crate::obj;
+use crate::transform::{Transform, Transformed};
+use crate::texture::{Filter, Texture, WrapMode};
+use crate::camera::{CustomCamera, Focus};
+use crate::light::{Attenuation, Light};
+use crate::material::Material;
+use crate::assets::{MaterialLibrary, TextureHandle, TextureRegistry};
+use crate::pbr::Pbr;
+use crate::skybox::{Sky, Skybox};
+use crate::physical_sky::{PhysicalSky, SolarClock, SunPosition};
+use crate::hdr_image::HdrImage;
+use crate::color::Color;
+use std::f32::consts::PI;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+// Declarative diorama description. A scene file is JSON (with `//` comments
+// and trailing commas allowed) shaped like:
+//
+// {
+//   "camera":    { "eye": [8, 4, 8], "center": [0, 1, 0], "up": [0, 1, 0] },
+//   "skybox":    "zen_cosmic",
+//   "textures":  { "moss": { "generator": "zen_moss", "size": [64, 64] },
+//                  "gravel": { "file": "textures/gravel.png", "filter": "trilinear", "wrap": "repeat" } },
+//   "materials": { "wet_moss": { "preset": "zen_moss", "albedo": [0.6, 0.3, 0.1, 0.0] } },
+//   "objects": [
+//     { "cube": { "center": [0, -1, 0], "size": 1, "material": "concrete_base", "texture": "moss",
+//                 "repeat": { "count": [11, 1, 11], "step": [1, 0, 1] } } },
+//     { "cube": { "center": [0, -2, 0], "size": [11, 0.2, 11], "material": "concrete_base",
+//                 "texture": "gravel", "uv_scale": 11 } },
+//     { "sphere": { "center": [0, 1, 0], "radius": 0.5, "material": "glass" } },
+//     { "mesh": { "path": "models/lantern.obj", "center": [2, 0, 2], "scale": 0.5 } },
+//     { "cube": { "center": [1, 0, -2], "size": [0.1, 2, 0.1], "material": "charred_wood",
+//                 "rotate": [0, 0, 15] } }
+//   ],
+//   "lights": [ { "position": [0, 8, 0], "color": [240, 245, 255], "intensity": 1.8 },
+//               { "position": [0, 3, 0], "rect": { "u": [1, 0, 0], "v": [0, 0, 1] }, "samples": 16 } ]
+// }
+//
+// The camera sees "fov" degrees from the bottom to the top of the image
+// (60 by default) through a "projection": "perspective", "orthographic",
+// "panorama" (360°, ignores fov) or "fisheye" (fov up to 360).
+// The camera gets depth of field from a lens "aperture" (radius in world
+// units) focused at "focus": a distance, "auto" or "center" (the default);
+// "blades" (and "blade_rotation", degrees) make the bokeh polygonal.
+// Materials may name any `Material` preset directly or be declared under
+// "materials". Textures are declared by name and referenced by that name.
+// Any object may be tilted and stretched around its centre with "rotate"
+// (Euler angles in degrees) and "scale", which must be uniform on glowing
+// objects. Lights are points unless given a "rect" (edge vectors u and v,
+// emitting towards u x v), "disk" (normal, radius) or "sphere" (radius)
+// shape, sampled with "samples" shadow rays.
+// "type": "spot" adds "direction", "inner_angle" and "outer_angle" (degrees);
+// "type": "directional" has only a "direction" and an optional "angle", the
+// apparent diameter of the source in degrees. "intensity" is radiometric (see
+// `light.rs`); positional lights fall off with the inverse square of the
+// distance unless "attenuation" says otherwise.
+// Cubes take relief for lighting from a tangent-space "normal_map" and/or a
+// height "bump_map" (brightness; "bump_depth" world units from black to
+// white), both named textures tiled like "texture".
+// A cube's "faces" block overrides "texture" and/or "material" for "top",
+// "bottom", "left", "right", "front", "back" or all four "sides".
+// Spheres wrap a "texture" around themselves like a world map (north pole up)
+// and can spin it about their vertical axis with "texture_rotation" (degrees).
+// "skybox" names a preset or is an object with a "gradient" (zenith, horizon
+// and ground colours), a "cubemap" (six image files by face name) or an
+// "equirect" image (.hdr and .exr keep their full range), optionally turned
+// by "rotation" degrees about the vertical and scaled by "intensity". A
+// "physical" daylight sky brings its own sun light, placed by a fixed "sun"
+// or a "time" of day. The sky also lights the scene (environment.rs).
+// Texture and mesh paths are relative to the scene file; their MTL materials
+// are used unless "material"/"texture" override them.
+// Materials glow with "emission" (a colour) times "emission_strength"; glowing
+// objects are seen by the camera and light the scene like area lights.
+// "model": "pbr" (or any of "metallic", "roughness", "transmission",
+// "anisotropy", all in [0, 1], or "tangent", the object-space brushing axis)
+// switches a material to GGX shading, converting the preset's Phong
+// parameters into a starting point; "model": "phong" forces the old shading.
+
+// World units between the black and white levels of a bump map
+const DEFAULT_BUMP_DEPTH: f32 = 0.05;
+
+#[derive(Debug)]
+pub enum SceneError {
+    Io { path: String, source: std::io::Error },
+    Parse { path: String, error: JsonError },
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SceneError::Io { path, source } => write!(f, "{}: {}", path, source),
+            SceneError::Parse { path, error } => write!(f, "{}:{}", path, error),
+        }
+    }
+}
+
+impl std::error::Error for SceneError {}
+
+pub fn load(path: &str) -> Result<Diorama, SceneError> {
+    let source = fs::read_to_string(path).map_err(|source| SceneError::Io {
+        path: path.to_string(),
+        source,
+    })?;
+    let base_dir = Path::new(path).parent().unwrap_or(Path::new(""));
+    parse(&source, base_dir).map_err(|error| SceneError::Parse {
+        path: path.to_string(),
+        error,
+    })
+}
+
+// `base_dir` is where relative paths inside the scene (meshes) are resolved
+pub fn parse(source: &str, base_dir: &Path) -> Result<Diorama, JsonError> {
+    let root = json::parse(source)?;
+    let mut fields = Fields::new(&root, "scene")?;
+
+    let camera = match fields.get("camera") {
+        Some(value) => parse_camera(value)?,
+        None => return Err(root.error("scene is missing 'camera'")),
+    };
+
+    let skybox = match fields.get("skybox") {
+        Some(value) => parse_skybox(value, base_dir)?,
+        None => Skybox::default(),
+    };
+
+    let mut textures = TextureRegistry::new();
+    if let Some(value) = fields.get("textures") {
+        for (key, texture) in as_object(value)? {
+            parse_texture(key, texture, base_dir, &mut textures)?;
+        }
+    }
+
+    let mut materials = MaterialLibrary::new();
+    if let Some(value) = fields.get("materials") {
+        for (key, material) in as_object(value)? {
+            let material = parse_material(material)?;
+            materials.add(&key.name, material).map_err(|e| key_error(key, e))?;
+        }
+    }
+
+    let mut objects = Vec::new();
+    if let Some(value) = fields.get("objects") {
+        for object in as_array(value)? {
+            parse_object(object, &materials, &mut textures, base_dir, &mut objects)?;
+        }
+    }
+
+    let mut lights = Vec::new();
+    if let Some(value) = fields.get("lights") {
+        for light in as_array(value)? {
+            lights.push(parse_light(light)?);
+        }
+    }
+
+    fields.finish()?;
+
+    Ok(Diorama {
+        textures: textures.into_textures(),
+        objects,
+        lights,
+        skybox,
+        camera,
+    })
+}
+
+fn parse_camera(value: &JsonValue) -> Result<CustomCamera, JsonError> {
+    let mut fields = Fields::new(value, "camera")?;
+    let eye = as_vec3(fields.required("eye")?)?;
+    let center = as_vec3(fields.required("center")?)?;
+    let up = match fields.get("up") {
+        Some(v) => as_vec3(v)?,
+        None => Vector3::new(0.0, 1.0, 0.0),
+    };
+    let mut camera = CustomCamera::new(eye, center, up);
+    if let Some(v) = fields.get("projection") {
+        camera = camera.with_projection(as_str(v)?.parse().map_err(|e: String| v.error(e))?);
+    }
+    if let Some(v) = fields.get("fov") {
+        let fov = as_f32(v)?.to_radians();
+        if fov <= 0.0 || fov > camera.projection.max_fov() {
+            return Err(v.error(format!(
+                "fov must be above 0 and at most {} degrees for a {} camera",
+                camera.projection.max_fov().to_degrees().round(),
+                camera.projection
+            )));
+        }
+        camera = camera.with_fov(fov);
+    }
+    if let Some(v) = fields.get("aperture") {
+        let radius = as_f32(v)?;
+        if radius < 0.0 {
+            return Err(v.error("aperture must not be negative"));
+        }
+        camera = camera.with_aperture(radius);
+    }
+    if let Some(v) = fields.get("focus") {
+        camera = camera.with_focus(parse_focus(v)?);
+    }
+    if let Some(v) = fields.get("blades") {
+        let blades = as_f32(v)?;
+        if blades.fract() != 0.0 || !(3.0..=32.0).contains(&blades) {
+            return Err(v.error("blades must be a whole number from 3 to 32"));
+        }
+        let rotation = match fields.get("blade_rotation") {
+            Some(v) => as_f32(v)?.to_radians(),
+            None => 0.0,
+        };
+        camera = camera.with_blades(blades as u32, rotation);
+    }
+    fields.finish()?;
+    Ok(camera)
+}
+
+// "focus" is a distance from the eye, "auto" for the object under the
+// middle of the screen or "center" for the looked-at point
+fn parse_focus(value: &JsonValue) -> Result<Focus, JsonError> {
+    if let JsonKind::String(_) = &value.kind {
+        return match as_str(value)? {
+            "auto" => Ok(Focus::Auto),
+            "center" => Ok(Focus::Center),
+            other => Err(value.error(format!("unknown focus '{}' (expected a distance, \"auto\" or \"center\")", other))),
+        };
+    }
+    Ok(Focus::Distance(as_positive(value)?))
+}
+
+// "skybox" is a preset name, or an object with exactly one of "preset",
+// "gradient", "cubemap" (six image files by face name), "equirect" (one
+// image file) or "physical", plus an optional "rotation" in degrees and
+// "intensity"
+fn parse_skybox(value: &JsonValue, base_dir: &Path) -> Result<Skybox, JsonError> {
+    if let JsonKind::String(_) = &value.kind {
+        return parse_sky_preset(value).map(Skybox::new);
+    }
+
+    let mut fields = Fields::new(value, "skybox")?;
+    let kinds = [
+        ("preset", fields.get("preset")),
+        ("gradient", fields.get("gradient")),
+        ("cubemap", fields.get("cubemap")),
+        ("equirect", fields.get("equirect")),
+        ("physical", fields.get("physical")),
+    ];
+    let sky = match kinds.iter().filter(|(_, v)| v.is_some()).collect::<Vec<_>>().as_slice() {
+        [("preset", Some(v))] => parse_sky_preset(v)?,
+        [("gradient", Some(v))] => {
+            let mut gradient = Fields::new(v, "gradient")?;
+            let zenith = as_color(gradient.required("zenith")?)?.to_linear();
+            let horizon = as_color(gradient.required("horizon")?)?.to_linear();
+            let ground = match gradient.get("ground") {
+                Some(v) => as_color(v)?.to_linear(),
+                None => horizon,
+            };
+            let exponent = match gradient.get("exponent") {
+                Some(v) => as_positive(v)?,
+                None => 1.0,
+            };
+            gradient.finish()?;
+            Sky::Gradient { zenith, horizon, ground, exponent }
+        }
+        [("cubemap", Some(v))] => {
+            // Same face names as a cube's "faces"
+            let mut faces = Fields::new(v, "cubemap")?;
+            let mut face = |name: &str| load_sky_image(faces.required(name)?, base_dir);
+            let images = [face("right")?, face("left")?, face("top")?, face("bottom")?, face("front")?, face("back")?];
+            faces.finish()?;
+            Sky::Cubemap(Box::new(images))
+        }
+        [("equirect", Some(v))] => Sky::Equirect(load_sky_image(v, base_dir)?),
+        [("physical", Some(v))] => Sky::Physical(parse_physical_sky(v)?),
+        _ => {
+            return Err(value.error(
+                "skybox needs exactly one of \"preset\", \"gradient\", \"cubemap\", \"equirect\" or \"physical\"",
+            ))
+        }
+    };
+
+    let mut skybox = Skybox::new(sky);
+    if let Some(v) = fields.get("rotation") {
+        skybox = skybox.with_rotation(as_f32(v)?.to_radians());
+    }
+    if let Some(v) = fields.get("intensity") {
+        let intensity = as_f32(v)?;
+        if intensity < 0.0 {
+            return Err(v.error("intensity must not be negative"));
+        }
+        skybox = skybox.with_intensity(intensity);
+    }
+    fields.finish()?;
+    Ok(skybox)
+}
+
+fn parse_sky_preset(value: &JsonValue) -> Result<Sky, JsonError> {
+    match as_str(value)? {
+        "zen_cosmic" => Ok(Sky::ZenCosmic),
+        "daylight" => Ok(Sky::Physical(PhysicalSky::default())),
+        name => Err(value.error(format!("unknown skybox '{}' (available: zen_cosmic, daylight)", name))),
+    }
+}
+
+// "physical" places the sun either by a "sun" with "elevation" and "azimuth"
+// (degrees, azimuth clockwise from north = -Z) or by the local solar "time"
+// in hours at a "latitude" (degrees) and "day" of the year. Haze is
+// "turbidity" (2 clear .. 10 hazy) and "ground_albedo" colours the ground.
+fn parse_physical_sky(value: &JsonValue) -> Result<PhysicalSky, JsonError> {
+    let mut fields = Fields::new(value, "physical")?;
+    let mut sky = match fields.get("sun") {
+        Some(v) => {
+            if let Some(clock_field) = ["time", "latitude", "day"].into_iter().find(|name| fields.get(name).is_some()) {
+                return Err(value.error(format!("\"{}\" cannot be combined with a fixed \"sun\"", clock_field)));
+            }
+            let mut sun = Fields::new(v, "sun")?;
+            let elevation = as_f32(sun.required("elevation")?)?.to_radians();
+            let azimuth = match sun.get("azimuth") {
+                Some(v) => as_f32(v)?.to_radians(),
+                None => PI,
+            };
+            sun.finish()?;
+            PhysicalSky::new(SunPosition::new(elevation, azimuth))
+        }
+        None => {
+            let mut clock = SolarClock::default();
+            if let Some(v) = fields.get("time") {
+                let hours = as_f32(v)?;
+                if !(0.0..=24.0).contains(&hours) {
+                    return Err(v.error("time must be between 0 and 24 hours"));
+                }
+                clock.hours = hours;
+            }
+            if let Some(v) = fields.get("latitude") {
+                let latitude = as_f32(v)?;
+                if !(-90.0..=90.0).contains(&latitude) {
+                    return Err(v.error("latitude must be between -90 and 90 degrees"));
+                }
+                clock.latitude = latitude.to_radians();
+            }
+            if let Some(v) = fields.get("day") {
+                let day = as_f32(v)?;
+                if !(1.0..=366.0).contains(&day) {
+                    return Err(v.error("day must be between 1 and 366"));
+                }
+                clock.day = day;
+            }
+            PhysicalSky::at_time(clock)
+        }
+    };
+    if let Some(v) = fields.get("turbidity") {
+        let turbidity = as_f32(v)?;
+        if !(1.7..=10.0).contains(&turbidity) {
+            return Err(v.error("turbidity must be between 1.7 and 10"));
+        }
+        sky = sky.with_turbidity(turbidity);
+    }
+    if let Some(v) = fields.get("ground_albedo") {
+        sky = sky.with_ground_albedo(as_fraction(v)?);
+    }
+    fields.finish()?;
+    Ok(sky)
+}
+
+// HDR (.hdr, .exr) or ordinary image file, relative to the scene
+fn load_sky_image(value: &JsonValue, base_dir: &Path) -> Result<HdrImage, JsonError> {
+    let path = base_dir.join(as_str(value)?);
+    HdrImage::load(&path).map_err(|e| value.error(e))
+}
+
+// A texture comes from an image "file" or a procedural "generator", with
+// optional "filter" and "wrap" modes. Image files are decoded once even when
+// several names (or models) use them with the same modes.
+fn parse_texture(key: &JsonKey, value: &JsonValue, base_dir: &Path, textures: &mut TextureRegistry) -> Result<(), JsonError> {
+    let mut fields = Fields::new(value, "texture")?;
+
+    let mut wrap = None;
+    if let Some(v) = fields.get("wrap") {
+        let name = as_str(v)?;
+        wrap = Some(
+            WrapMode::from_name(name)
+                .ok_or_else(|| v.error(format!("unknown wrap mode '{}' (available: repeat, mirror, clamp)", name)))?,
+        );
+    }
+    let mut filter = None;
+    if let Some(v) = fields.get("filter") {
+        let name = as_str(v)?;
+        filter = Some(
+            Filter::from_name(name)
+                .ok_or_else(|| v.error(format!("unknown filter '{}' (available: nearest, bilinear, trilinear)", name)))?,
+        );
+    }
+
+    match (fields.get("file"), fields.get("generator")) {
+        (Some(file), None) => {
+            let path = base_dir.join(as_str(file)?);
+            let id = textures
+                .load_file(&path, filter.unwrap_or_default(), wrap.unwrap_or_default())
+                .map_err(|e| file.error(e))?;
+            textures.alias(&key.name, id).map_err(|e| key_error(key, e))?;
+        }
+        (None, Some(generator)) => {
+            let mut texture = parse_generator(key, generator, &mut fields)?;
+            if let Some(wrap) = wrap {
+                texture = texture.with_wrap(wrap);
+            }
+            if let Some(filter) = filter {
+                texture = texture.with_filter(filter);
+            }
+            textures.add(&key.name, texture).map_err(|e| key_error(key, e))?;
+        }
+        _ => return Err(value.error(format!("texture '{}' needs exactly one of \"file\" or \"generator\"", key.name))),
+    }
+
+    fields.finish()?;
+    Ok(())
+}
+
+fn parse_generator(key: &JsonKey, generator: &JsonValue, fields: &mut Fields<'_>) -> Result<Texture, JsonError> {
+    let (width, height) = match fields.get("size") {
+        Some(v) => {
+            let size = as_numbers(v, 2)?;
+            if size.iter().any(|s| *s < 1.0) {
+                return Err(v.error("texture size must be at least 1x1"));
+            }
+            (size[0] as u32, size[1] as u32)
+        }
+        None => (64, 64),
+    };
+
+    let name = as_str(generator)?;
+    let texture = match name {
+        "checkerboard" => {
+            let colors = fields.required("colors")?;
+            let pair = as_array(colors)?;
+            if pair.len() != 2 {
+                return Err(colors.error("checkerboard needs exactly two colors"));
+            }
+            Texture::checkerboard(width, height, as_color(&pair[0])?, as_color(&pair[1])?)
+        }
+        "brick" => Texture::brick(width, height),
+        "wood" => Texture::wood(width, height),
+        "marble" => Texture::marble(width, height),
+        "metal" => Texture::metal(width, height),
+        "ancient_stone" => Texture::ancient_stone(width, height),
+        "rusted_metal" => Texture::rusted_metal(width, height),
+        "blood_water" => Texture::blood_water(width, height),
+        "dark_crystal" => Texture::dark_crystal(width, height),
+        "charred_wood" => Texture::charred_wood(width, height),
+        "crystal_glass" => Texture::crystal_glass(width, height),
+        "chrome_mirror" => Texture::chrome_mirror(width, height),
+        "zen_water" => Texture::zen_water(width, height),
+        "marble_stone" => Texture::marble_stone(width, height),
+        "brushed_metal" => Texture::brushed_metal(width, height),
+        "zen_moss" => Texture::zen_moss(width, height),
+        "concrete_base" => Texture::concrete_base(width, height),
+        other => {
+            return Err(generator.error(format!("unknown texture generator '{}' for texture '{}'", other, key.name)));
+        }
+    };
+    Ok(texture)
+}
+
+// A material is either a preset with optional overrides or a full
+// definition of every field.
+fn parse_material(value: &JsonValue) -> Result<Material, JsonError> {
+    let mut fields = Fields::new(value, "material")?;
+    let mut material = match fields.get("preset") {
+        Some(v) => {
+            let name = as_str(v)?;
+            Material::preset(name).ok_or_else(|| v.error(format!("unknown material preset '{}'", name)))?
+        }
+        None => Material::new(Color::new(255, 255, 255), 50.0, [1.0, 0.0, 0.0, 0.0], 1.0, 0.0),
+    };
+
+    if let Some(v) = fields.get("diffuse") {
+        material.diffuse = as_color(v)?;
+    }
+    if let Some(v) = fields.get("specular") {
+        material.specular = as_f32(v)?;
+    }
+    if let Some(v) = fields.get("albedo") {
+        let albedo = as_numbers(v, 4)?;
+        material.albedo = [albedo[0], albedo[1], albedo[2], albedo[3]];
+    }
+    if let Some(v) = fields.get("refractive_index") {
+        material.refractive_index = as_f32(v)?;
+    }
+    if let Some(v) = fields.get("transparency") {
+        material.transparency = as_f32(v)?;
+    }
+    // Either key alone keeps the other from the preset, or white at strength 1
+    let emission = fields.get("emission").map(as_color).transpose()?;
+    let strength = match fields.get("emission_strength") {
+        Some(v) => {
+            let strength = as_f32(v)?;
+            if strength < 0.0 {
+                return Err(v.error("emission_strength must not be negative"));
+            }
+            Some(strength)
+        }
+        None => None,
+    };
+    if emission.is_some() || strength.is_some() {
+        let (color, default_strength) = if material.is_emissive() {
+            (material.emission, material.emission_strength)
+        } else {
+            (Color::new(255, 255, 255), 1.0)
+        };
+        material = material.with_emission(emission.unwrap_or(color), strength.unwrap_or(default_strength));
+    }
+
+    // "model": "pbr" converts the Phong parameters above (presets such as
+    // brushed_metal already come with their own); giving any of the
+    // metallic-roughness keys implies it
+    let model = fields.get("model");
+    let metallic = fields.get("metallic").map(as_fraction).transpose()?;
+    let roughness = fields.get("roughness").map(as_fraction).transpose()?;
+    let transmission = fields.get("transmission").map(as_fraction).transpose()?;
+    let anisotropy = fields.get("anisotropy").map(as_fraction).transpose()?;
+    let tangent = fields.get("tangent").map(as_direction).transpose()?;
+    let has_pbr_keys = metallic.is_some()
+        || roughness.is_some()
+        || transmission.is_some()
+        || anisotropy.is_some()
+        || tangent.is_some();
+    let pbr = match model {
+        Some(v) => match as_str(v)? {
+            "pbr" => true,
+            "phong" if has_pbr_keys => {
+                return Err(v.error("metallic, roughness, transmission, anisotropy and tangent need \"model\": \"pbr\""));
+            }
+            "phong" => {
+                material.pbr = None;
+                false
+            }
+            other => return Err(v.error(format!("unknown material model '{}' (available: phong, pbr)", other))),
+        },
+        None => has_pbr_keys,
+    };
+    if pbr {
+        let mut params = material.pbr.unwrap_or_else(|| Pbr::from_phong(&material));
+        if let Some(metallic) = metallic {
+            params.metallic = metallic;
+        }
+        if let Some(roughness) = roughness {
+            params.roughness = roughness;
+        }
+        if let Some(transmission) = transmission {
+            params.transmission = transmission;
+        }
+        if anisotropy.is_some() || tangent.is_some() {
+            params = params.with_anisotropy(anisotropy.unwrap_or(params.anisotropy), tangent.or(params.tangent));
+        }
+        material = material.with_pbr(params);
+    }
+    fields.finish()?;
+    Ok(material)
+}
+
+fn resolve_material(value: &JsonValue, materials: &MaterialLibrary) -> Result<Material, JsonError> {
+    materials.resolve(as_str(value)?).map_err(|e| value.error(e))
+}
+
+fn resolve_texture(value: &JsonValue, textures: &TextureRegistry) -> Result<TextureHandle, JsonError> {
+    textures.resolve(as_str(value)?).map_err(|e| value.error(e))
+}
+
+// Each entry in "objects" is a single-key object naming the shape. An
+// optional "repeat" block stamps out a grid of copies offset by "step".
+fn parse_object(
+    value: &JsonValue,
+    materials: &MaterialLibrary,
+    textures: &mut TextureRegistry,
+    base_dir: &Path,
+    objects: &mut Vec<Object>,
+) -> Result<(), JsonError> {
+    let entries = as_object(value)?;
+    let [(key, body)] = entries else {
+        return Err(value.error("each object must have exactly one key naming its shape (\"cube\", \"sphere\" or \"mesh\")"));
+    };
+
+    let mut fields = Fields::new(body, &key.name)?;
+    let center = as_vec3(fields.required("center")?)?;
+
+    let shape = match key.name.as_str() {
+        "cube" => {
+            let material = resolve_material(fields.required("material")?, materials)?;
+            let size = fields.required("size")?;
+            let mut cube = match &size.kind {
+                JsonKind::Number(_) => Cube::new(center, as_positive(size)?, material),
+                _ => {
+                    let d = as_vec3(size)?;
+                    if d.x <= 0.0 || d.y <= 0.0 || d.z <= 0.0 {
+                        return Err(size.error("cube dimensions must be positive"));
+                    }
+                    Cube::new_flat(center, d.x, d.y, d.z, material)
+                }
+            };
+            if let Some(v) = fields.get("texture") {
+                cube = cube.with_texture(resolve_texture(v, textures)?);
+            }
+            if let Some(value) = fields.get("uv_scale") {
+                let (u, v) = match &value.kind {
+                    JsonKind::Number(_) => (as_positive(value)?, as_positive(value)?),
+                    _ => {
+                        let scale = as_numbers(value, 2)?;
+                        if scale[0] <= 0.0 || scale[1] <= 0.0 {
+                            return Err(value.error("uv_scale factors must be positive"));
+                        }
+                        (scale[0], scale[1])
+                    }
+                };
+                cube = cube.with_uv_scale(u, v);
+            }
+            if let Some(value) = fields.get("faces") {
+                for (key, body) in as_object(value)? {
+                    let faces = match key.name.as_str() {
+                        "sides" => vec![Face::Right, Face::Left, Face::Front, Face::Back],
+                        name => vec![Face::from_name(name).ok_or_else(|| {
+                            key_error(key, format!("unknown face '{}' (expected \"top\", \"bottom\", \"left\", \"right\", \"front\", \"back\" or \"sides\")", name))
+                        })?],
+                    };
+                    let mut face_fields = Fields::new(body, &key.name)?;
+                    let texture = face_fields.get("texture").map(|v| resolve_texture(v, textures)).transpose()?;
+                    let material = face_fields.get("material").map(|v| resolve_material(v, materials)).transpose()?;
+                    face_fields.finish()?;
+                    for face in faces {
+                        if let Some(id) = texture {
+                            cube = cube.with_face_texture(face, id);
+                        }
+                        if let Some(material) = material {
+                            cube = cube.with_face_material(face, material);
+                        }
+                    }
+                }
+            }
+            if let Some(v) = fields.get("normal_map") {
+                cube = cube.with_normal_map(resolve_texture(v, textures)?);
+            }
+            let bump_depth = fields.get("bump_depth");
+            match (fields.get("bump_map"), bump_depth) {
+                (Some(v), depth) => {
+                    let depth = depth.map_or(Ok(DEFAULT_BUMP_DEPTH), as_f32)?;
+                    cube = cube.with_bump_map(resolve_texture(v, textures)?, depth);
+                }
+                (None, Some(depth)) => return Err(depth.error("\"bump_depth\" needs a \"bump_map\"")),
+                (None, None) => {}
+            }
+            Object::Cube(cube)
+        }
+        "sphere" => {
+            let material = resolve_material(fields.required("material")?, materials)?;
+            let radius = as_radius(fields.required("radius")?)?;
+            let mut sphere = Sphere::new(center, radius, material);
+            if let Some(v) = fields.get("texture") {
+                sphere = sphere.with_texture(resolve_texture(v, textures)?);
+            }
+            if let Some(v) = fields.get("texture_rotation") {
+                sphere = sphere.with_texture_rotation(as_f32(v)?.to_radians());
+            }
+            Object::Sphere(sphere)
+        }
+        "mesh" => {
+            let path = fields.required("path")?;
+            let file = base_dir.join(as_str(path)?);
+            let mut mesh = obj::load(&file.to_string_lossy(), textures)
+                .map_err(|e| path.error(e.to_string()))?
+                .placed(center, 1.0);
+            if let Some(v) = fields.get("material") {
+                mesh = mesh.with_material(resolve_material(v, materials)?);
+            }
+            if let Some(v) = fields.get("texture") {
+                mesh = mesh.with_texture(resolve_texture(v, textures)?);
+            }
+            Object::Mesh(mesh)
+        }
+        other => return Err(key_error(key, format!("unknown object type '{}' (expected \"cube\", \"sphere\" or \"mesh\")", other))),
+    };
+
+    let shape = match parse_transform(&mut fields, center, shape.is_emissive())? {
+        Some(transform) => Object::Transformed(Transformed::new(shape, transform)),
+        None => shape,
+    };
+
+    let (count, step) = match fields.get("repeat") {
+        Some(v) => {
+            let mut repeat = Fields::new(v, "repeat")?;
+            let count_value = repeat.required("count")?;
+            let count = as_numbers(count_value, 3)?;
+            if count.iter().any(|c| *c < 1.0 || c.fract() != 0.0) {
+                return Err(count_value.error("repeat counts must be positive integers"));
+            }
+            let step = as_vec3(repeat.required("step")?)?;
+            repeat.finish()?;
+            ([count[0] as i32, count[1] as i32, count[2] as i32], step)
+        }
+        None => ([1, 1, 1], Vector3::zero()),
+    };
+    fields.finish()?;
+
+    for ix in 0..count[0] {
+        for iy in 0..count[1] {
+            for iz in 0..count[2] {
+                let offset = Vector3::new(ix as f32 * step.x, iy as f32 * step.y, iz as f32 * step.z);
+                objects.push(translated(&shape, offset));
+            }
+        }
+    }
+    Ok(())
+}
+
+// Optional "rotate" (Euler degrees around X, Y, Z) and "scale" (number or
+// [x, y, z]), both applied around the object's centre. Glowing objects are
+// sampled as lights, which needs their area, so they only scale uniformly.
+fn parse_transform(fields: &mut Fields<'_>, center: Vector3, emissive: bool) -> Result<Option<Transform>, JsonError> {
+    let rotate = fields.get("rotate");
+    let scale = fields.get("scale");
+    if rotate.is_none() && scale.is_none() {
+        return Ok(None);
+    }
+
+    let mut transform = Transform::identity().with_pivot(center);
+    if let Some(v) = rotate {
+        transform = transform.with_euler_degrees(as_vec3(v)?);
+    }
+    if let Some(v) = scale {
+        let factors = match &v.kind {
+            JsonKind::Number(_) => {
+                let s = as_f32(v)?;
+                Vector3::new(s, s, s)
+            }
+            _ => as_vec3(v)?,
+        };
+        if factors.x <= 0.0 || factors.y <= 0.0 || factors.z <= 0.0 {
+            return Err(v.error("scale factors must be positive"));
+        }
+        transform = transform.with_scale(factors);
+        if emissive && !transform.has_uniform_scale() {
+            return Err(v.error("glowing objects can only be scaled uniformly (give \"scale\" a single number)"));
+        }
+    }
+    Ok(Some(transform))
+}
+
+fn translated(object: &Object, offset: Vector3) -> Object {
+    match object {
+        Object::Cube(cube) => {
+            let mut cube = cube.clone();
+            cube.min += offset;
+            cube.max += offset;
+            Object::Cube(cube)
+        }
+        Object::Sphere(sphere) => {
+            let mut sphere = sphere.clone();
+            sphere.center += offset;
+            Object::Sphere(sphere)
+        }
+        Object::Mesh(mesh) => Object::Mesh(mesh.clone().placed(offset, 1.0)),
+        Object::Transformed(transformed) => {
+            let mut transformed = transformed.clone();
+            transformed.transform.translation += offset;
+            Object::Transformed(transformed)
+        }
+    }
+}
+
+fn parse_light(value: &JsonValue) -> Result<Light, JsonError> {
+    let mut fields = Fields::new(value, "light")?;
+    let color = match fields.get("color") {
+        Some(v) => as_color(v)?,
+        None => Color::new(255, 255, 255),
+    };
+    let intensity = match fields.get("intensity") {
+        Some(v) => as_f32(v)?,
+        None => 1.0,
+    };
+
+    let kind_value = fields.get("type");
+    let kind = match kind_value {
+        Some(v) => as_str(v)?,
+        None => "point",
+    };
+    let mut light = match kind {
+        "directional" => {
+            let direction = as_direction(fields.required("direction")?)?;
+            let mut light = Light::directional(direction, color, intensity);
+            // Apparent diameter of the source in degrees (the sun is about 0.5)
+            if let Some(v) = fields.get("angle") {
+                let angle = as_f32(v)?;
+                if !(0.0..90.0).contains(&angle) {
+                    return Err(v.error("directional light angle must be between 0 and 90 degrees"));
+                }
+                light = light.with_angular_radius((angle * 0.5).to_radians());
+            }
+            light
+        }
+        "point" | "spot" => {
+            let position = as_vec3(fields.required("position")?)?;
+            let mut light = Light::new(position, color, intensity);
+            if kind == "spot" {
+                light = parse_spot(light, &mut fields)?;
+            }
+            if let Some(v) = fields.get("attenuation") {
+                light = light.with_attenuation(parse_attenuation(v)?);
+            }
+
+            // At most one area shape; without any the light is a point
+            let shapes = [("rect", fields.get("rect")), ("disk", fields.get("disk")), ("sphere", fields.get("sphere"))];
+            let mut given = shapes.iter().filter_map(|(name, v)| v.map(|v| (*name, v)));
+            if let Some((name, shape)) = given.next() {
+                if let Some((other, v)) = given.next() {
+                    return Err(v.error(format!("light has both \"{}\" and \"{}\"; pick one shape", name, other)));
+                }
+                light = parse_light_shape(light, name, shape)?;
+            }
+            light
+        }
+        _ => {
+            return Err(kind_value.unwrap_or(value).error(format!(
+                "unknown light type '{}' (available: point, directional, spot)",
+                kind
+            )));
+        }
+    };
+
+    if let Some(v) = fields.get("samples") {
+        let samples = as_f32(v)?;
+        if samples < 1.0 {
+            return Err(v.error("light samples must be at least 1"));
+        }
+        light = light.with_samples(samples as u32);
+    }
+
+    fields.finish()?;
+    Ok(light)
+}
+
+// "none", "inverse_square", { "constant", "linear", "quadratic" } (missing
+// coefficients are 0) or { "range" }
+fn parse_attenuation(value: &JsonValue) -> Result<Attenuation, JsonError> {
+    if let JsonKind::String(name) = &value.kind {
+        return match name.as_str() {
+            "none" => Ok(Attenuation::None),
+            "inverse_square" => Ok(Attenuation::InverseSquare),
+            _ => Err(value.error(format!(
+                "unknown attenuation '{}' (available: none, inverse_square, or an object with \"range\" or coefficients)",
+                name
+            ))),
+        };
+    }
+
+    let mut fields = Fields::new(value, "attenuation")?;
+    let attenuation = if let Some(v) = fields.get("range") {
+        let range = as_f32(v)?;
+        if range <= 0.0 {
+            return Err(v.error("attenuation range must be positive"));
+        }
+        Attenuation::Range { range }
+    } else {
+        let mut coefficient = |key| -> Result<f32, JsonError> {
+            match fields.get(key) {
+                Some(v) if as_f32(v)? < 0.0 => Err(v.error("attenuation coefficients must not be negative")),
+                Some(v) => as_f32(v),
+                None => Ok(0.0),
+            }
+        };
+        let (constant, linear, quadratic) = (coefficient("constant")?, coefficient("linear")?, coefficient("quadratic")?);
+        if constant + linear + quadratic <= 0.0 {
+            return Err(value.error("attenuation needs \"range\" or at least one positive coefficient"));
+        }
+        Attenuation::Polynomial { constant, linear, quadratic }
+    };
+    fields.finish()?;
+    Ok(attenuation)
+}
+
+// Cone half-angles are given in degrees
+fn parse_spot(light: Light, fields: &mut Fields<'_>) -> Result<Light, JsonError> {
+    let direction = as_direction(fields.required("direction")?)?;
+    let inner = as_f32(fields.required("inner_angle")?)?;
+    let outer_value = fields.required("outer_angle")?;
+    let outer = as_f32(outer_value)?;
+    if !(0.0..=180.0).contains(&inner) || !(0.0..=180.0).contains(&outer) {
+        return Err(outer_value.error("spot angles must be between 0 and 180 degrees"));
+    }
+    if outer < inner {
+        return Err(outer_value.error("spot outer_angle must not be smaller than inner_angle"));
+    }
+    Ok(light.with_spot(direction, inner.to_radians(), outer.to_radians()))
+}
+
+fn parse_light_shape(light: Light, name: &str, value: &JsonValue) -> Result<Light, JsonError> {
+    let mut fields = Fields::new(value, &format!("{} light", name))?;
+    let light = match name {
+        "rect" => {
+            let u = as_vec3(fields.required("u")?)?;
+            let v_value = fields.required("v")?;
+            let v = as_vec3(v_value)?;
+            if u.cross(v).length() <= 0.0 {
+                return Err(v_value.error("rect light edges must not be parallel"));
+            }
+            light.with_rect(u, v)
+        }
+        "disk" => {
+            let normal = as_direction(fields.required("normal")?)?;
+            light.with_disk(normal, as_radius(fields.required("radius")?)?)
+        }
+        _ => light.with_sphere(as_radius(fields.required("radius")?)?),
+    };
+    fields.finish()?;
+    Ok(light)
+}
+
+// Any non-zero vector; callers normalize it
+fn as_direction(value: &JsonValue) -> Result<Vector3, JsonError> {
+    let direction = as_vec3(value)?;
+    if direction.length() <= 0.0 {
+        return Err(value.error("direction must not be zero"));
+    }
+    Ok(direction)
+}
+
+fn as_fraction(value: &JsonValue) -> Result<f32, JsonError> {
+    let fraction = as_f32(value)?;
+    if !(0.0..=1.0).contains(&fraction) {
+        return Err(value.error("expected a number between 0 and 1"));
+    }
+    Ok(fraction)
+}
+
+fn as_positive(value: &JsonValue) -> Result<f32, JsonError> {
+    let number = as_f32(value)?;
+    if number <= 0.0 {
+        return Err(value.error("expected a positive number"));
+    }
+    Ok(number)
+}
+
+fn as_radius(value: &JsonValue) -> Result<f32, JsonError> {
+    let radius = as_f32(value)?;
+    if radius <= 0.0 {
+        return Err(value.error("radius must be positive"));
+    }
+    Ok(radius)
+}
+
+// Tracks which keys of an object were read so that typos and unsupported
+// keys are reported instead of silently ignored.
+struct Fields<'a> {
+    value: &'a JsonValue,
+    what: String,
+    entries: &'a [(JsonKey, JsonValue)],
+    used: Vec<bool>,
+}
+
+impl<'a> Fields<'a> {
+    fn new(value: &'a JsonValue, what: &str) -> Result<Self, JsonError> {
+        match &value.kind {
+            JsonKind::Object(entries) => Ok(Fields {
+                value,
+                what: what.to_string(),
+                entries,
+                used: vec![false; entries.len()],
+            }),
+            _ => Err(value.error(format!("{} must be an object, found {}", what, value.type_name()))),
+        }
+    }
+
+    fn get(&mut self, key: &str) -> Option<&'a JsonValue> {
+        let index = self.entries.iter().position(|(k, _)| k.name == key)?;
+        self.used[index] = true;
+        Some(&self.entries[index].1)
+    }
+
+    fn required(&mut self, key: &str) -> Result<&'a JsonValue, JsonError> {
+        let what = self.what.clone();
+        self.get(key)
+            .ok_or_else(|| self.value.error(format!("{} is missing '{}'", what, key)))
+    }
+
+    fn finish(self) -> Result<(), JsonError> {
+        match self.entries.iter().zip(&self.used).find(|(_, used)| !**used) {
+            Some(((key, _), _)) => Err(key_error(key, format!("unknown key '{}' in {}", key.name, self.what))),
+            None => Ok(()),
+        }
+    }
+}
+
+fn key_error(key: &JsonKey, message: String) -> JsonError {
+    JsonError {
+        line: key.line,
+        column: key.column,
+        message,
+    }
+}
+
+fn as_f32(value: &JsonValue) -> Result<f32, JsonError> {
+    match value.kind {
+        JsonKind::Number(n) => Ok(n as f32),
+        _ => Err(value.error(format!("expected a number, found {}", value.type_name()))),
+    }
+}
+
+fn as_str(value: &JsonValue) -> Result<&str, JsonError> {
+    match &value.kind {
+        JsonKind::String(s) => Ok(s),
+        _ => Err(value.error(format!("expected a string, found {}", value.type_name()))),
+    }
+}
+
+fn as_array(value: &JsonValue) -> Result<&[JsonValue], JsonError> {
+    match &value.kind {
+        JsonKind::Array(items) => Ok(items),
+        _ => Err(value.error(format!("expected an array, found {}", value.type_name()))),
+    }
+}
+
+fn as_object(value: &JsonValue) -> Result<&[(JsonKey, JsonValue)], JsonError> {
+    match &value.kind {
+        JsonKind::Object(entries) => Ok(entries),
+        _ => Err(value.error(format!("expected an object, found {}", value.type_name()))),
+    }
+}
+
+fn as_numbers(value: &JsonValue, len: usize) -> Result<Vec<f32>, JsonError> {
+    let items = as_array(value)?;
+    if items.len() != len {
+        return Err(value.error(format!("expected {} numbers, found {}", len, items.len())));
+    }
+    items.iter().map(as_f32).collect()
+}
+
+fn as_vec3(value: &JsonValue) -> Result<Vector3, JsonError> {
+    let v = as_numbers(value, 3)?;
+    Ok(Vector3::new(v[0], v[1], v[2]))
+}
+
+// Colors are [r, g, b] in 0-255 or a "#rrggbb" string
+fn as_color(value: &JsonValue) -> Result<Color, JsonError> {
+    if let JsonKind::String(s) = &value.kind {
+        let hex = s.strip_prefix('#').unwrap_or(s);
+        return match u32::from_str_radix(hex, 16) {
+            Ok(v) if hex.len() == 6 => Ok(Color::from_hex(v)),
+            _ => Err(value.error(format!("invalid color '{}' (expected \"#rrggbb\")", s))),
+        };
+    }
+    let c = as_numbers(value, 3)?;
+    if c.iter().any(|v| !(0.0..=255.0).contains(v)) {
+        return Err(value.error("color channels must be between 0 and 255"));
+    }
+    Ok(Color::new(c[0] as u8, c[1] as u8, c[2] as u8))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CAMERA: &str = "\"camera\": { \"eye\": [0, 0, 5], \"center\": [0, 0, 0] }";
+
+    fn parse_error(source: &str) -> String {
+        match parse(source, Path::new("")) {
+            Ok(_) => panic!("scene should have been rejected"),
+            Err(error) => error.to_string(),
+        }
+    }
+
+    #[test]
+    fn parses_the_zen_garden() {
+        let source = include_str!("../scenes/zen_garden.json");
+        let diorama = parse(source, Path::new("scenes")).unwrap_or_else(|e| panic!("{}", e));
+        assert!(diorama.objects.len() > 100);
+        assert!(!diorama.lights.is_empty());
+        assert_eq!(diorama.textures.len(), 7);
+    }
+
+    #[test]
+    fn comments_and_trailing_commas() {
+        let source = format!(
+            "{{\n  // the whole scene\n  {},\n  \"objects\": [\n    {{ \"sphere\": {{ \"center\": [0, 0, 0], \"radius\": 1, \"material\": \"glass\", }} }}, // one\n  ],\n}}\n",
+            CAMERA
+        );
+        let diorama = parse(&source, Path::new("")).unwrap_or_else(|e| panic!("{}", e));
+        assert_eq!(diorama.objects.len(), 1);
+    }
+
+    #[test]
+    fn unknown_key_points_at_the_key() {
+        let source = format!(
+            "{{\n  {},\n  \"objects\": [\n    {{ \"cube\": {{ \"center\": [0, 0, 0], \"size\": 1, \"material\": \"glass\", \"colour\": [1, 2, 3] }} }}\n  ]\n}}",
+            CAMERA
+        );
+        assert_eq!(parse_error(&source), "4:70: unknown key 'colour' in cube");
+    }
+
+    #[test]
+    fn type_mismatch_points_at_the_value() {
+        let source = format!(
+            "{{\n  {},\n  \"objects\": [\n    {{ \"sphere\": {{ \"center\": [0, 0, 0], \"radius\": \"big\", \"material\": \"glass\" }} }}\n  ]\n}}",
+            CAMERA
+        );
+        assert_eq!(parse_error(&source), "4:50: expected a number, found a string");
+
+        let source = source.replace("\"big\"", "false");
+        assert_eq!(parse_error(&source), "4:50: expected a number, found false");
+    }
+
+    #[test]
+    fn rejects_non_positive_sizes() {
+        let cube = |size: &str| {
+            format!(
+                "{{ {}, \"objects\": [ {{ \"cube\": {{ \"center\": [0, 0, 0], \"size\": {}, \"material\": \"glass\" }} }} ] }}",
+                CAMERA, size
+            )
+        };
+        assert!(parse_error(&cube("-1")).ends_with("expected a positive number"));
+        assert!(parse_error(&cube("[1, 0, 1]")).ends_with("cube dimensions must be positive"));
+
+        let sphere = format!(
+            "{{ {}, \"objects\": [ {{ \"sphere\": {{ \"center\": [0, 0, 0], \"radius\": 0, \"material\": \"glass\" }} }} ] }}",
+            CAMERA
+        );
+        assert!(parse_error(&sphere).ends_with("radius must be positive"));
+    }
+
+    #[test]
+    fn glowing_objects_scale_uniformly() {
+        let lamp = |scale: &str| {
+            format!(
+                "{{ {},\n  \"materials\": {{ \"lamp\": {{ \"preset\": \"rubber\", \"emission\": [255, 255, 255] }} }},\n  \"objects\": [ {{ \"cube\": {{ \"center\": [0, 0, 0], \"size\": 1, \"material\": \"lamp\", \"scale\": {} }} }} ] }}",
+                CAMERA, scale
+            )
+        };
+        assert!(parse(&lamp("2"), Path::new("")).is_ok());
+        assert!(parse(&lamp("[2, 2, 2]"), Path::new("")).is_ok());
+        assert_eq!(
+            parse_error(&lamp("[1, 2, 1]")),
+            "3:89: glowing objects can only be scaled uniformly (give \"scale\" a single number)"
+        );
+    }
+}
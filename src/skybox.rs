@@ -0,0 +1,241 @@
+use raylib::prelude::*;
+use crate::color::{Color, LinearColor};
+use crate::hdr_image::HdrImage;
+use crate::light::{Light, LightKind};
+use crate::physical_sky::PhysicalSky;
+use std::f32::consts::PI;
+
+// What rays that leave the diorama see, and the light it sends back in
+#[derive(Debug, Clone)]
+pub enum Sky {
+    // The original procedural sky with its two nebulas and stars
+    ZenCosmic,
+    // Blend from the horizon colour up to the zenith and down to the ground;
+    // a higher exponent keeps the horizon colour further from the horizon
+    Gradient {
+        zenith: LinearColor,
+        horizon: LinearColor,
+        ground: LinearColor,
+        exponent: f32,
+    },
+    // Six images in `Face` order (+X, -X, +Y, -Y, +Z, -Z). Each is what a
+    // camera at the centre sees looking along that axis with +Y up; the top
+    // and bottom images are seen as if tilting the head from facing -Z, so
+    // their upper edges point to +Z and -Z respectively.
+    Cubemap(Box<[HdrImage; 6]>),
+    // Latitude-longitude map: the image centre looks towards -Z, its right
+    // half towards +X, the top row straight up
+    Equirect(HdrImage),
+    // Analytic daylight driven by the sun's position, with a matching sun
+    // light (see `Skybox::sun_light`)
+    Physical(PhysicalSky),
+}
+
+#[derive(Debug, Clone)]
+pub struct Skybox {
+    pub sky: Sky,
+    // Radians the sky is turned about the vertical axis, counterclockwise
+    // seen from above like the objects' "rotate"
+    pub rotation: f32,
+    // Multiplies the radiance, mostly to balance HDR maps against the lights
+    pub intensity: f32,
+}
+
+impl Default for Skybox {
+    fn default() -> Self {
+        Skybox::new(Sky::ZenCosmic)
+    }
+}
+
+impl Skybox {
+    pub fn new(sky: Sky) -> Self {
+        Skybox {
+            sky,
+            rotation: 0.0,
+            intensity: 1.0,
+        }
+    }
+
+    pub fn with_rotation(mut self, angle: f32) -> Self {
+        self.rotation = angle;
+        self
+    }
+
+    pub fn with_intensity(mut self, intensity: f32) -> Self {
+        self.intensity = intensity;
+        self
+    }
+
+    // Radiance arriving from `direction` (need not be normalised)
+    pub fn radiance(&self, direction: &Vector3) -> LinearColor {
+        // Turn the direction back by the sky's rotation
+        let (sin, cos) = self.rotation.sin_cos();
+        let d = Vector3::new(cos * direction.x - sin * direction.z, direction.y, sin * direction.x + cos * direction.z);
+
+        let radiance = match &self.sky {
+            // Normalizes by itself
+            Sky::ZenCosmic => zen_cosmic(&d).to_linear(),
+            Sky::Gradient { zenith, horizon, ground, exponent } => {
+                let d = d.normalized();
+                let (towards, t) = if d.y >= 0.0 { (*zenith, d.y) } else { (*ground, -d.y) };
+                let t = t.clamp(0.0, 1.0).powf(*exponent);
+                *horizon * (1.0 - t) + towards * t
+            }
+            Sky::Cubemap(faces) => {
+                let (face, u, v) = cube_face(&d);
+                faces[face].sample(u, v, false)
+            }
+            Sky::Equirect(image) => {
+                let (u, v) = direction_to_equirect(&d.normalized());
+                image.sample(u, v, true)
+            }
+            Sky::Physical(sky) => sky.radiance(&d.normalized()),
+        };
+        radiance * self.intensity
+    }
+
+    // The directional light a physical sky's sun casts, turned and scaled
+    // with the sky; None for other skies and after sunset
+    pub fn sun_light(&self) -> Option<Light> {
+        let Sky::Physical(sky) = &self.sky else {
+            return None;
+        };
+        let mut light = sky.sun_light()?;
+        if let LightKind::Directional { direction, .. } = &mut light.kind {
+            let (sin, cos) = self.rotation.sin_cos();
+            *direction = Vector3::new(cos * direction.x + sin * direction.z, direction.y, cos * direction.z - sin * direction.x);
+        }
+        light.intensity *= self.intensity;
+        Some(light)
+    }
+
+    // Hour of the day for a physical sky that follows a clock
+    pub fn time_of_day(&self) -> Option<f32> {
+        match &self.sky {
+            Sky::Physical(sky) => sky.time(),
+            _ => None,
+        }
+    }
+
+    // Move a clock-driven physical sky to `hours`; other skies are unchanged
+    pub fn set_time_of_day(&mut self, hours: f32) {
+        if let Sky::Physical(sky) = &mut self.sky {
+            sky.set_time(hours);
+        }
+    }
+}
+
+// Latitude-longitude coordinates of a unit direction, as laid out in
+// `Sky::Equirect`
+pub fn direction_to_equirect(d: &Vector3) -> (f32, f32) {
+    let u = 0.5 + d.x.atan2(-d.z) / (2.0 * PI);
+    let v = d.y.clamp(-1.0, 1.0).acos() / PI;
+    (u, v)
+}
+
+pub fn equirect_to_direction(u: f32, v: f32) -> Vector3 {
+    let (sin_phi, cos_phi) = ((u - 0.5) * 2.0 * PI).sin_cos();
+    let (sin_theta, cos_theta) = (v * PI).sin_cos();
+    Vector3::new(sin_theta * sin_phi, cos_theta, -sin_theta * cos_phi)
+}
+
+// Face index (in `Face` order) and image coordinates for a direction, using
+// the orientations described on `Sky::Cubemap`
+fn cube_face(d: &Vector3) -> (usize, f32, f32) {
+    let (ax, ay, az) = (d.x.abs(), d.y.abs(), d.z.abs());
+    // Right and down across the image, divided by the major axis
+    let (face, right, down, major) = if ax >= ay && ax >= az {
+        if d.x > 0.0 { (0, d.z, -d.y, ax) } else { (1, -d.z, -d.y, ax) }
+    } else if ay >= az {
+        if d.y > 0.0 { (2, d.x, -d.z, ay) } else { (3, d.x, d.z, ay) }
+    } else if d.z > 0.0 {
+        (4, -d.x, -d.y, az)
+    } else {
+        (5, d.x, -d.y, az)
+    };
+    (face, 0.5 * (right / major + 1.0), 0.5 * (down / major + 1.0))
+}
+
+// Zen Cosmic skybox function - Creates a serene futuristic atmosphere
+fn zen_cosmic(ray_direction: &Vector3) -> Color {
+    // Normalize ray direction
+    let dir = ray_direction.normalized();
+    
+    // Calculate spherical coordinates
+    let theta = dir.y.asin(); // Elevation angle (-π/2 to π/2)
+    let phi = dir.z.atan2(dir.x); // Azimuthal angle (-π to π)
+    
+    // Cosmic nebula centers for depth
+    let nebula1_theta = 0.8; // High elevation
+    let nebula1_phi = 1.0;   // Eastern side
+    let nebula2_theta = -0.3; // Lower elevation
+    let nebula2_phi = -1.5;   // Western side
+    
+    // Calculate distances to nebula centers
+    let d1_theta = theta - nebula1_theta;
+    let d1_phi = phi - nebula1_phi;
+    let dist1 = (d1_theta * d1_theta + d1_phi * d1_phi).sqrt();
+    
+    let d2_theta = theta - nebula2_theta;
+    let d2_phi = phi - nebula2_phi;
+    let dist2 = (d2_theta * d2_theta + d2_phi * d2_phi).sqrt();
+    
+    // Nebula parameters
+    let nebula_radius = 0.6;
+    let nebula_intensity = 0.4;
+    
+    // Base cosmic gradient
+    let elevation_factor = (theta + 1.57) / 3.14; // Normalize to 0-1
+    
+    let mut base_color = if elevation_factor > 0.8 {
+        // Deep space - dark blue with purple hints
+        let factor = (elevation_factor - 0.8) / 0.2;
+        let r = (15.0 * (1.0 - factor) + 25.0 * factor) as u8;
+        let g = (25.0 * (1.0 - factor) + 15.0 * factor) as u8;
+        let b = (45.0 * (1.0 - factor) + 55.0 * factor) as u8;
+        Color::new(r, g, b)
+    } else if elevation_factor > 0.4 {
+        // Mid sky - gentle blue gradient
+        let factor = (elevation_factor - 0.4) / 0.4;
+        let r = (30.0 * (1.0 - factor) + 15.0 * factor) as u8;
+        let g = (45.0 * (1.0 - factor) + 25.0 * factor) as u8;
+        let b = (80.0 * (1.0 - factor) + 45.0 * factor) as u8;
+        Color::new(r, g, b)
+    } else {
+        // Horizon - warmer cosmic tones
+        let factor = elevation_factor / 0.4;
+        let r = (45.0 * factor + 35.0 * (1.0 - factor)) as u8;
+        let g = (55.0 * factor + 40.0 * (1.0 - factor)) as u8;
+        let b = (85.0 * factor + 65.0 * (1.0 - factor)) as u8;
+        Color::new(r, g, b)
+    };
+    
+    // Add cyan nebula effect (complements zen water)
+    if dist1 < nebula_radius {
+        let nebula_factor = 1.0 - (dist1 / nebula_radius);
+        let intensity = nebula_factor * nebula_intensity;
+        base_color.r = ((base_color.r as f32) * (1.0 - intensity) + 60.0 * intensity) as u8;
+        base_color.g = ((base_color.g as f32) * (1.0 - intensity) + 120.0 * intensity) as u8;
+        base_color.b = ((base_color.b as f32) * (1.0 - intensity) + 140.0 * intensity) as u8;
+    }
+    
+    // Add purple nebula effect (complements crystal refractions)
+    if dist2 < nebula_radius {
+        let nebula_factor = 1.0 - (dist2 / nebula_radius);
+        let intensity = nebula_factor * nebula_intensity;
+        base_color.r = ((base_color.r as f32) * (1.0 - intensity) + 100.0 * intensity) as u8;
+        base_color.g = ((base_color.g as f32) * (1.0 - intensity) + 50.0 * intensity) as u8;
+        base_color.b = ((base_color.b as f32) * (1.0 - intensity) + 120.0 * intensity) as u8;
+    }
+    
+    // Add subtle stars effect with noise
+    let star_noise = ((phi * 50.0).sin() * (theta * 30.0).cos() * (phi * theta * 100.0).sin()).abs();
+    if star_noise > 0.98 {
+        let star_intensity = (star_noise - 0.98) / 0.02;
+        base_color.r = (base_color.r as f32 + 40.0 * star_intensity) as u8;
+        base_color.g = (base_color.g as f32 + 40.0 * star_intensity) as u8;
+        base_color.b = (base_color.b as f32 + 40.0 * star_intensity) as u8;
+    }
+    
+    base_color
+}
@@ -5,6 +5,9 @@ use crate::material::Material;
 use crate::light::Light;
 use crate::color::Color;
 use crate::camera::CustomCamera;
+use crate::bvh::Aabb;
+use crate::emitter::SurfaceSample;
+use crate::assets::TextureHandle;
 use std::f32::consts::PI;
 
 const SHADOW_BIAS: f32 = 1e-4;
@@ -15,6 +18,69 @@ pub struct Sphere {
     pub center: Vector3,
     pub radius: f32,
     pub material: Material,
+    pub texture_id: Option<TextureHandle>, // Equirectangular map, poles on the Y axis
+    pub texture_rotation: f32,     // Radians the texture is turned about Y
+}
+
+impl Sphere {
+    pub fn new(center: Vector3, radius: f32, material: Material) -> Self {
+        Sphere {
+            center,
+            radius,
+            material,
+            texture_id: None,
+            texture_rotation: 0.0,
+        }
+    }
+
+    pub fn with_texture(mut self, texture_id: TextureHandle) -> Self {
+        self.texture_id = Some(texture_id);
+        self
+    }
+
+    // Spin the texture about the vertical axis, e.g. to turn a planet
+    pub fn with_texture_rotation(mut self, angle: f32) -> Self {
+        self.texture_rotation = angle;
+        self
+    }
+
+    // Equirectangular UVs: u goes once around the equator starting at +Z
+    // (eastwards, towards +X), v from the north pole (0) to the south (1)
+    pub fn get_uv(&self, point: Vector3) -> (f32, f32) {
+        let d = (point - self.center) / self.radius;
+        let longitude = d.x.atan2(d.z) - self.texture_rotation;
+        let u = (longitude / (2.0 * PI)).rem_euclid(1.0);
+        let v = d.y.clamp(-1.0, 1.0).acos() / PI;
+        (u, v)
+    }
+
+    // UV units per world unit, averaged over the two directions: u spans the
+    // equator (2πr) and v a meridian (πr)
+    pub fn uv_density(&self) -> f32 {
+        1.0 / (PI * self.radius.max(1e-6) * 2.0f32.sqrt())
+    }
+
+    pub fn bounds(&self) -> Aabb {
+        let r = Vector3::new(self.radius, self.radius, self.radius);
+        Aabb::new(self.center - r, self.center + r)
+    }
+
+    pub fn area(&self) -> f32 {
+        4.0 * PI * self.radius * self.radius
+    }
+
+    // Uniformly distributed point on the surface
+    pub fn sample_surface(&self, s: f32, t: f32) -> SurfaceSample {
+        let z = 1.0 - 2.0 * s;
+        let r = (1.0 - z * z).max(0.0).sqrt();
+        let phi = 2.0 * PI * t;
+        let normal = Vector3::new(r * phi.cos(), r * phi.sin(), z);
+        SurfaceSample {
+            point: self.center + normal * self.radius,
+            normal,
+            material: self.material,
+        }
+    }
 }
 
 impl RayIntersect for Sphere {
@@ -43,7 +109,13 @@ impl RayIntersect for Sphere {
                 let point = *ray_origin + *ray_direction * t;
                 let mut normal = point - self.center;
                 normal.normalize();
-                return Intersect::new(point, normal, t, self.material.clone());
+                let intersect = Intersect::new(point, normal, t, self.material.clone());
+                // Direction of growing u, along the parallel; undefined at the poles
+                let tangent = Vector3::new(normal.z, 0.0, -normal.x);
+                if tangent.length() > 1e-6 {
+                    return intersect.with_tangent(tangent.normalized());
+                }
+                return intersect;
             }
         }
 
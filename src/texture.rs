@@ -1,10 +1,86 @@
-use crate::color::Color;
+use crate::color::{Color, LinearColor};
+
+// How texels are reconstructed between their centres
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Filter {
+    #[default]
+    Nearest,
+    Bilinear,
+    // Bilinear on the two mip levels closest to the sample footprint
+    Trilinear,
+}
+
+// What happens to UVs outside [0, 1]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum WrapMode {
+    Repeat,
+    Mirror,
+    #[default]
+    Clamp,
+}
+
+impl Filter {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "nearest" => Some(Filter::Nearest),
+            "bilinear" => Some(Filter::Bilinear),
+            "trilinear" => Some(Filter::Trilinear),
+            _ => None,
+        }
+    }
+}
+
+// Texel under a coordinate for nearest lookups. Within each 0..1 tile the
+// coordinate spans `size - 1` texels, as the original sampler did, so 0 and 1
+// land on the first and last texel; the wrap mode then handles other tiles.
+fn nearest_texel(coord: f32, size: u32) -> i64 {
+    let tile = coord.floor();
+    let x = ((coord - tile) * (size as f32 - 1.0)) as i64;
+    tile as i64 * size as i64 + x
+}
+
+impl WrapMode {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "repeat" => Some(WrapMode::Repeat),
+            "mirror" => Some(WrapMode::Mirror),
+            "clamp" => Some(WrapMode::Clamp),
+            _ => None,
+        }
+    }
+
+    // Map any integer texel coordinate into 0..size
+    fn texel(self, i: i64, size: u32) -> usize {
+        let size = size as i64;
+        let wrapped = match self {
+            WrapMode::Repeat => i.rem_euclid(size),
+            WrapMode::Clamp => i.clamp(0, size - 1),
+            WrapMode::Mirror => {
+                let m = i.rem_euclid(2 * size);
+                if m >= size { 2 * size - 1 - m } else { m }
+            }
+        };
+        wrapped as usize
+    }
+}
+
+// One level of the mip chain, downsampled in linear light
+#[derive(Debug, Clone)]
+struct MipLevel {
+    width: u32,
+    height: u32,
+    texels: Vec<LinearColor>,
+}
 
 #[derive(Debug, Clone)]
 pub struct Texture {
     pub width: u32,
     pub height: u32,
     pub data: Vec<Color>,
+    pub filter: Filter,
+    pub wrap: WrapMode,
+    // Levels 1.. of the mip chain, only built for trilinear filtering
+    mips: Vec<MipLevel>,
 }
 
 impl Texture {
@@ -13,18 +89,148 @@ impl Texture {
             width,
             height,
             data: vec![Color::new(255, 255, 255); (width * height) as usize],
+            filter: Filter::default(),
+            wrap: WrapMode::default(),
+            mips: Vec::new(),
         }
     }
 
+    // Load an image file (PNG, JPEG, TGA, BMP, ...) through raylib
+    pub fn from_file(path: &str) -> Result<Self, String> {
+        let image = raylib::texture::Image::load_image(path).map_err(|e| format!("could not load texture '{}': {}", path, e))?;
+        let mut texture = Texture::new(image.width() as u32, image.height() as u32);
+        texture.data = image
+            .get_image_data()
+            .iter()
+            .map(|c| Color::new(c.r, c.g, c.b))
+            .collect();
+        Ok(texture)
+    }
+
+    // Set the filter; trilinear builds the mip chain from the current data,
+    // so call this after the texels are final.
+    pub fn with_filter(mut self, filter: Filter) -> Self {
+        self.filter = filter;
+        self.mips = if filter == Filter::Trilinear { self.build_mips() } else { Vec::new() };
+        self
+    }
+
+    pub fn with_wrap(mut self, wrap: WrapMode) -> Self {
+        self.wrap = wrap;
+        self
+    }
+
+    // 2x2 box filter per level until both sides reach 1 texel
+    fn build_mips(&self) -> Vec<MipLevel> {
+        let base: Vec<LinearColor> = self.data.iter().map(|c| c.to_linear()).collect();
+        let mut levels: Vec<MipLevel> = Vec::new();
+        let mut width = self.width;
+        let mut height = self.height;
+
+        while width > 1 || height > 1 {
+            let source = levels.last().map_or(&base, |level| &level.texels);
+            let at = |x: u32, y: u32| source[(y * width + x) as usize];
+            let next_width = (width / 2).max(1);
+            let next_height = (height / 2).max(1);
+
+            let mut texels = Vec::with_capacity((next_width * next_height) as usize);
+            for y in 0..next_height {
+                for x in 0..next_width {
+                    let x0 = (2 * x).min(width - 1);
+                    let x1 = (2 * x + 1).min(width - 1);
+                    let y0 = (2 * y).min(height - 1);
+                    let y1 = (2 * y + 1).min(height - 1);
+                    texels.push((at(x0, y0) + at(x1, y0) + at(x0, y1) + at(x1, y1)) * 0.25);
+                }
+            }
+
+            levels.push(MipLevel { width: next_width, height: next_height, texels });
+            width = next_width;
+            height = next_height;
+        }
+        levels
+    }
+
+    // Texel of level 0 after wrapping
+    fn texel(&self, x: i64, y: i64) -> Color {
+        let x = self.wrap.texel(x, self.width);
+        let y = self.wrap.texel(y, self.height);
+        self.data[y * self.width as usize + x]
+    }
+
+    // Nearest texel, wrapped according to the texture's wrap mode
     pub fn sample(&self, u: f32, v: f32) -> Color {
-        let u = u.clamp(0.0, 1.0);
-        let v = v.clamp(0.0, 1.0);
-        
-        let x = ((u * (self.width as f32 - 1.0)) as u32).min(self.width - 1);
-        let y = ((v * (self.height as f32 - 1.0)) as u32).min(self.height - 1);
-        
-        let index = (y * self.width + x) as usize;
-        self.data[index]
+        self.texel(nearest_texel(u, self.width), nearest_texel(v, self.height))
+    }
+
+    // Filtered lookup in linear light. `footprint` is the width of the area
+    // the sample covers in UV units (1.0 = the whole texture); it picks the
+    // mip level for trilinear filtering and is ignored otherwise.
+    pub fn sample_filtered(&self, u: f32, v: f32, footprint: f32) -> LinearColor {
+        match self.filter {
+            Filter::Nearest => self.sample(u, v).to_linear(),
+            Filter::Bilinear => self.bilinear(0, u, v),
+            Filter::Trilinear => {
+                let texels = footprint * self.width.max(self.height) as f32;
+                let lod = texels.max(1.0).log2().min(self.mips.len() as f32);
+                let level = lod.floor() as usize;
+                let blend = lod - level as f32;
+                let near = self.bilinear(level, u, v);
+                if blend <= 0.0 || level >= self.mips.len() {
+                    return near;
+                }
+                near * (1.0 - blend) + self.bilinear(level + 1, u, v) * blend
+            }
+        }
+    }
+
+    // Bilinear lookup of the stored values as they are, scaled to 0..1, for
+    // data such as normal and height maps that must not be decoded as sRGB
+    pub fn sample_raw(&self, u: f32, v: f32) -> LinearColor {
+        let at = |x: i64, y: i64| {
+            let c = self.texel(x, y);
+            LinearColor::new(c.r as f32, c.g as f32, c.b as f32) * (1.0 / 255.0)
+        };
+        let x = u * self.width as f32 - 0.5;
+        let y = v * self.height as f32 - 0.5;
+        let (x0, y0) = (x.floor(), y.floor());
+        let (fx, fy) = (x - x0, y - y0);
+        let (x0, y0) = (x0 as i64, y0 as i64);
+
+        let top = at(x0, y0) * (1.0 - fx) + at(x0 + 1, y0) * fx;
+        let bottom = at(x0, y0 + 1) * (1.0 - fx) + at(x0 + 1, y0 + 1) * fx;
+        top * (1.0 - fy) + bottom * fy
+    }
+
+    fn bilinear(&self, level: usize, u: f32, v: f32) -> LinearColor {
+        let (width, height) = match level {
+            0 => (self.width, self.height),
+            _ => (self.mips[level - 1].width, self.mips[level - 1].height),
+        };
+        let at = |x: i64, y: i64| -> LinearColor {
+            match level {
+                0 => self.texel(x, y).to_linear(),
+                _ => {
+                    let mip = &self.mips[level - 1];
+                    let x = self.wrap.texel(x, mip.width);
+                    let y = self.wrap.texel(y, mip.height);
+                    mip.texels[y * mip.width as usize + x]
+                }
+            }
+        };
+
+        // Texel centres sit at half-integer coordinates
+        let x = u * width as f32 - 0.5;
+        let y = v * height as f32 - 0.5;
+        let x0 = x.floor();
+        let y0 = y.floor();
+        let fx = x - x0;
+        let fy = y - y0;
+        let (x0, y0) = (x0 as i64, y0 as i64);
+
+        let top = at(x0, y0) * (1.0 - fx) + at(x0 + 1, y0) * fx;
+        let bottom = at(x0, y0 + 1) * (1.0 - fx) + at(x0 + 1, y0 + 1) * fx;
+        top * (1.0 - fy) + bottom * fy
     }
 
     // Create a checkerboard texture
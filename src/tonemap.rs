@@ -0,0 +1,91 @@
+use crate::color::{Color, LinearColor};
+use std::fmt;
+use std::str::FromStr;
+
+// How linear HDR radiance is squeezed into the displayable [0, 1] range
+// before sRGB encoding.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ToneMapper {
+    // Scale by the exposure and clip whatever is still above 1.0
+    Exposure,
+    // x / (1 + x) on luminance, keeps hue in bright areas
+    Reinhard,
+    // Narkowicz's fit of the ACES filmic curve
+    Aces,
+}
+
+impl ToneMapper {
+    pub const ALL: [ToneMapper; 3] = [ToneMapper::Exposure, ToneMapper::Reinhard, ToneMapper::Aces];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ToneMapper::Exposure => "exposure",
+            ToneMapper::Reinhard => "reinhard",
+            ToneMapper::Aces => "aces",
+        }
+    }
+
+    // Next operator in `ALL`, used to cycle through them from the keyboard
+    pub fn next(&self) -> ToneMapper {
+        let index = ToneMapper::ALL.iter().position(|m| m == self).unwrap_or(0);
+        ToneMapper::ALL[(index + 1) % ToneMapper::ALL.len()]
+    }
+
+    pub fn apply(&self, color: LinearColor) -> LinearColor {
+        match self {
+            ToneMapper::Exposure => color,
+            ToneMapper::Reinhard => {
+                let luminance = color.luminance();
+                if luminance <= 0.0 {
+                    return LinearColor::BLACK;
+                }
+                color * (1.0 / (1.0 + luminance))
+            }
+            ToneMapper::Aces => color.map(|x| {
+                ((x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14)).clamp(0.0, 1.0)
+            }),
+        }
+    }
+}
+
+impl fmt::Display for ToneMapper {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for ToneMapper {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        ToneMapper::ALL
+            .into_iter()
+            .find(|m| m.name() == s)
+            .ok_or_else(|| format!("unknown tone mapper '{}' (available: exposure, reinhard, aces)", s))
+    }
+}
+
+// Tone mapper plus exposure, applied when HDR pixels reach the framebuffer
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ToneMapping {
+    pub mapper: ToneMapper,
+    // Linear multiplier applied before the curve (1.0 = unchanged)
+    pub exposure: f32,
+}
+
+impl Default for ToneMapping {
+    fn default() -> Self {
+        ToneMapping {
+            mapper: ToneMapper::Aces,
+            exposure: 1.0,
+        }
+    }
+}
+
+impl ToneMapping {
+    // Linear radiance to the final 8-bit sRGB value
+    pub fn to_display(self, color: LinearColor) -> Color {
+        let exposed = color.map(|c| (c * self.exposure).max(0.0));
+        self.mapper.apply(exposed).to_srgb()
+    }
+}
@@ -0,0 +1,169 @@
+use raylib::prelude::*;
+use crate::ray_intersect::{Intersect, RayIntersect};
+use crate::renderer::Object;
+use crate::bvh::Aabb;
+use crate::emitter::SurfaceSample;
+
+// Translation, rotation and non-uniform scale applied around a pivot:
+//
+//   world = rotation * (scale * (local - pivot)) + pivot + translation
+//
+// Only TRS is supported (no shear), which keeps every direction of the
+// transform a couple of component-wise operations and one quaternion rotation.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Transform {
+    pub translation: Vector3,
+    pub rotation: Quaternion,
+    pub scale: Vector3,
+    pub pivot: Vector3,
+}
+
+impl Transform {
+    pub fn identity() -> Self {
+        Transform {
+            translation: Vector3::zero(),
+            rotation: Quaternion::identity(),
+            scale: Vector3::one(),
+            pivot: Vector3::zero(),
+        }
+    }
+
+    pub fn with_rotation(mut self, rotation: Quaternion) -> Self {
+        self.rotation = rotation.normalized();
+        self
+    }
+
+    // Rotation from Euler angles in degrees around X, Y and Z
+    pub fn with_euler_degrees(self, angles: Vector3) -> Self {
+        self.with_rotation(Quaternion::from_euler(
+            angles.x.to_radians(),
+            angles.y.to_radians(),
+            angles.z.to_radians(),
+        ))
+    }
+
+    pub fn with_scale(mut self, scale: Vector3) -> Self {
+        self.scale = scale;
+        self
+    }
+
+    // Point that rotation and scale keep fixed, usually the object's centre
+    pub fn with_pivot(mut self, pivot: Vector3) -> Self {
+        self.pivot = pivot;
+        self
+    }
+
+    pub fn point_to_world(&self, p: Vector3) -> Vector3 {
+        self.vector_to_world(p - self.pivot) + self.pivot + self.translation
+    }
+
+    pub fn point_to_local(&self, p: Vector3) -> Vector3 {
+        self.vector_to_local(p - self.pivot - self.translation) + self.pivot
+    }
+
+    pub fn vector_to_world(&self, v: Vector3) -> Vector3 {
+        (v * self.scale).rotate_by(self.rotation)
+    }
+
+    // Not normalized, so a distance along the local ray equals the world one
+    pub fn vector_to_local(&self, v: Vector3) -> Vector3 {
+        v.rotate_by(self.rotation.inverted()) / self.scale
+    }
+
+    // Normals use the inverse transpose: rotate, but divide by the scale
+    pub fn normal_to_world(&self, n: Vector3) -> Vector3 {
+        (n / self.scale).rotate_by(self.rotation).normalized()
+    }
+
+    pub fn normal_to_local(&self, n: Vector3) -> Vector3 {
+        (n.rotate_by(self.rotation.inverted()) * self.scale).normalized()
+    }
+
+    // World box around the eight transformed corners of a local box
+    pub fn bounds_to_world(&self, bounds: &Aabb) -> Aabb {
+        let mut world = Aabb::empty();
+        for i in 0..8 {
+            let corner = Vector3::new(
+                if i & 1 == 0 { bounds.min.x } else { bounds.max.x },
+                if i & 2 == 0 { bounds.min.y } else { bounds.max.y },
+                if i & 4 == 0 { bounds.min.z } else { bounds.max.z },
+            );
+            world.grow(self.point_to_world(corner));
+        }
+        world
+    }
+
+    // Whether every axis is scaled alike, so all areas grow by the same factor
+    pub fn has_uniform_scale(&self) -> bool {
+        self.scale.x == self.scale.y && self.scale.y == self.scale.z
+    }
+
+    // Average linear scale factor, used to convert texture footprints
+    pub fn mean_scale(&self) -> f32 {
+        (self.scale.x * self.scale.y * self.scale.z).abs().cbrt()
+    }
+}
+
+// Any object placed with a transform. Rays are moved into the object's space,
+// intersected there, and the hit is brought back to world space.
+#[derive(Clone)]
+pub struct Transformed {
+    pub object: Box<Object>,
+    pub transform: Transform,
+}
+
+impl Transformed {
+    pub fn new(object: Object, transform: Transform) -> Self {
+        Transformed {
+            object: Box::new(object),
+            transform,
+        }
+    }
+
+    pub fn bounds(&self) -> Aabb {
+        self.transform.bounds_to_world(&self.object.bounds())
+    }
+
+    // Only valid for uniform scale: a non-uniform one stretches the surface
+    // unevenly, so neither this area nor the density of `sample_surface`
+    // would match. Scene files refuse such a scale on glowing objects, the
+    // only ones sampled as lights.
+    pub fn area(&self) -> f32 {
+        debug_assert!(self.transform.has_uniform_scale(), "area of a non-uniformly scaled object");
+        self.object.area() * self.transform.mean_scale().powi(2)
+    }
+
+    pub fn sample_surface(&self, s: f32, t: f32, pick: f32) -> Option<SurfaceSample> {
+        let local = self.object.sample_surface(s, t, pick)?;
+        Some(SurfaceSample {
+            point: self.transform.point_to_world(local.point),
+            normal: self.transform.normal_to_world(local.normal),
+            material: local.material,
+        })
+    }
+
+    // The hit as the wrapped object sees it, for lookups such as
+    // `Cube::get_uv` that work in the object's own coordinates
+    pub fn to_local(&self, intersect: &Intersect) -> Intersect {
+        let mut local = intersect.clone();
+        local.point = self.transform.point_to_local(intersect.point);
+        local.normal = self.transform.normal_to_local(intersect.normal);
+        local.tangent = intersect.tangent.map(|t| self.transform.vector_to_local(t).normalized());
+        local
+    }
+}
+
+impl RayIntersect for Transformed {
+    fn ray_intersect(&self, ray_origin: &Vector3, ray_direction: &Vector3) -> Intersect {
+        let local_origin = self.transform.point_to_local(*ray_origin);
+        let local_direction = self.transform.vector_to_local(*ray_direction);
+
+        let mut hit = self.object.ray_intersect(&local_origin, &local_direction);
+        if hit.is_intersecting {
+            hit.point = *ray_origin + *ray_direction * hit.distance;
+            hit.normal = self.transform.normal_to_world(hit.normal);
+            hit.tangent = hit.tangent.map(|t| self.transform.vector_to_world(t).normalized());
+        }
+        hit
+    }
+}